@@ -0,0 +1,126 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// The maximum number of files to keep.
+const CACHE_MAX_FILES: usize = 64;
+
+/// The maximum total size of files to keep, in bytes.
+const CACHE_MAX_BYTES: usize = 8 * 1024 * 1024;
+
+/// Least recently used cache of file contents.
+///
+/// The cache is meant to live for one request, so edits on disk are visible
+/// to the next request.
+#[derive(Debug)]
+pub struct FileCache {
+    inner: Mutex<FileCacheInner>,
+
+    /// Encoding of source files.
+    encoding: &'static encoding_rs::Encoding,
+}
+
+#[derive(Debug, Default)]
+struct FileCacheInner {
+    /// File contents keyed by path.
+    files: HashMap<String, Arc<String>>,
+
+    /// Paths from least to most recently used.
+    order: VecDeque<String>,
+
+    /// Total size of cached files.
+    bytes: usize,
+
+    /// Number of reads served from cache.
+    hits: usize,
+
+    /// Number of reads served from disk.
+    misses: usize,
+}
+
+impl FileCache {
+    /// Create empty cache.
+    ///
+    /// # Arguments
+    ///
+    /// + `encoding`: Encoding of source files, see
+    ///   [`crate::TagsLspConfig::file_encoding`].
+    pub fn new(encoding: &'static encoding_rs::Encoding) -> FileCache {
+        return FileCache {
+            inner: Mutex::default(),
+            encoding,
+        };
+    }
+
+    /// Encoding of source files.
+    pub fn encoding(&self) -> &'static encoding_rs::Encoding {
+        return self.encoding;
+    }
+
+    /// Read file content, from cache if possible.
+    ///
+    /// # Arguments
+    ///
+    /// + `path`: File path.
+    pub async fn read(&self, path: &str) -> Result<Arc<String>, tower_lsp::jsonrpc::Error> {
+        if let Some(v) = self.get(path) {
+            return Ok(v);
+        }
+
+        let content = Arc::new(crate::method::read_file_content(path, self.encoding).await?);
+        self.put(path, content.clone());
+
+        return Ok(content);
+    }
+
+    fn get(&self, path: &str) -> Option<Arc<String>> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let content = match inner.files.get(path) {
+            Some(v) => v.clone(),
+            None => {
+                inner.misses += 1;
+                return None;
+            }
+        };
+
+        inner.hits += 1;
+        if let Some(idx) = inner.order.iter().position(|v| v == path) {
+            let key = inner.order.remove(idx).unwrap();
+            inner.order.push_back(key);
+        }
+
+        return Some(content);
+    }
+
+    fn put(&self, path: &str, content: Arc<String>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if content.len() > CACHE_MAX_BYTES || inner.files.contains_key(path) {
+            return;
+        }
+
+        while inner.files.len() >= CACHE_MAX_FILES || inner.bytes + content.len() > CACHE_MAX_BYTES
+        {
+            let key = match inner.order.pop_front() {
+                Some(v) => v,
+                None => break,
+            };
+            if let Some(v) = inner.files.remove(&key) {
+                inner.bytes -= v.len();
+            }
+        }
+
+        inner.bytes += content.len();
+        inner.order.push_back(path.to_string());
+        inner.files.insert(path.to_string(), content);
+    }
+}
+
+impl Drop for FileCache {
+    fn drop(&mut self) {
+        let inner = self.inner.lock().unwrap();
+        if inner.hits + inner.misses > 0 {
+            tracing::debug!("File cache: {} hits, {} misses", inner.hits, inner.misses);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The maximum number of entries to resolve.
+const SAMPLE_SIZE: usize = 200;
+
+/// Validate tags file and print a summary.
+///
+/// Each line of output is a `key: value` pair, so scripts can grep it.
+///
+/// # Arguments
+///
+/// + `path`: Path to tags file.
+/// + `hash_index_limit`: See [`crate::tags::parse`].
+/// + `encoding`: Encoding of source files.
+///
+/// # Returns
+///
+/// Whether the tags file has no problem.
+pub async fn check(
+    path: &Path,
+    hash_index_limit: usize,
+    encoding: &'static encoding_rs::Encoding,
+) -> bool {
+    let path = std::path::absolute(path).unwrap_or(path.to_path_buf());
+    let tags_file = match crate::tags::parse(&path, hash_index_limit, encoding) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("error: {}: {}", path.display(), e);
+            return false;
+        }
+    };
+
+    println!("tags: {}", path.display());
+    println!("entries: {}", tags_file.entries.len());
+    println!("bad_lines: {}", tags_file.malformed.len());
+    for line_no in &tags_file.malformed {
+        println!("bad_line: {}", line_no + 1);
+    }
+
+    // Spread samples over the whole file.
+    let step = tags_file.entries.len().div_ceil(SAMPLE_SIZE).max(1);
+    let cache = crate::cache::FileCache::new(encoding);
+    let mut missing = HashSet::new();
+    let mut unresolved = 0;
+    let mut checked = 0;
+
+    for entry in tags_file.entries.iter().step_by(step) {
+        checked += 1;
+
+        let entry_path = tags_file.entry_path(entry);
+        if !entry_path.exists() {
+            if missing.insert(entry_path.clone()) {
+                println!("missing_file: {}", entry_path.display());
+            }
+            continue;
+        }
+
+        if !is_resolvable(&tags_file, entry, &cache).await {
+            unresolved += 1;
+            println!("unresolved: {} {}", entry.name, entry_path.display());
+        }
+    }
+
+    println!("checked: {}", checked);
+    println!("missing_files: {}", missing.len());
+    println!("unresolved_entries: {}", unresolved);
+
+    return tags_file.malformed.is_empty() && missing.is_empty() && unresolved == 0;
+}
+
+/// Whether address of entry still points to the symbol.
+///
+/// # Arguments
+///
+/// + `tags_file`: The tags file that contains this entry.
+/// + `entry`: Tag entry.
+/// + `cache`: Cache of file contents.
+async fn is_resolvable(
+    tags_file: &crate::tags::TagsFile,
+    entry: &crate::tags::TagEntry,
+    cache: &crate::cache::FileCache,
+) -> bool {
+    // Location of pattern address falls back to begin of file if pattern
+    // does not match, so check the pattern itself.
+    if let crate::tags::TagAddress::Pattern(pattern) = &entry.address {
+        let entry_path = tags_file.entry_path(entry);
+        let content = match cache.read(&entry_path.to_string_lossy()).await {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+        let pattern = crate::tags::SearchPattern::new(pattern);
+        return pattern.find_line(&content).is_some();
+    }
+
+    return crate::method::find_tag_location(
+        tags_file,
+        entry,
+        false,
+        crate::tags::AddressResolution::Verify,
+        cache,
+    )
+    .await
+    .is_ok();
+}
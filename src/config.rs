@@ -0,0 +1,110 @@
+use std::path::Path;
+
+/// File name of workspace configuration.
+pub const CONFIG_FILENAME: &str = ".tags-lsp.json";
+
+/// Configuration read from workspace root.
+///
+/// Command line arguments and initialization options take precedence over
+/// this file.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Same as `--tags`.
+    pub tags_files: Option<Vec<String>>,
+
+    /// Same as `--ctags-bin`.
+    pub ctags_binary: Option<String>,
+
+    /// Same as `--ctags-arg`.
+    pub ctags_args: Option<Vec<String>>,
+
+    /// Same as `--loglevel`.
+    pub log_level: Option<String>,
+
+    /// Same as `--max-results`.
+    pub max_results: Option<usize>,
+
+    /// Opposite of `--no-auto-generate`.
+    pub auto_generate: Option<bool>,
+
+    /// Same as `--sorted-index-threshold`.
+    pub sorted_index_threshold: Option<usize>,
+}
+
+/// Load configuration from workspace root.
+///
+/// Missing file is not an error. Malformed file is ignored with a warning.
+///
+/// # Arguments
+///
+/// + `workspace`: Workspace folder path.
+pub fn load(workspace: &Path) -> Option<Config> {
+    let path = workspace.join(CONFIG_FILENAME);
+
+    let content = match std::fs::read_to_string(&path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            tracing::warn!("Cannot read {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    match serde_json::from_str::<Config>(&content) {
+        Ok(v) => {
+            tracing::info!("Loaded configuration from {}", path.display());
+            return Some(v);
+        }
+        Err(e) => {
+            tracing::warn!("Ignore invalid {}: {}", path.display(), e);
+            return None;
+        }
+    }
+}
+
+impl Config {
+    /// Fill configuration that not set yet.
+    ///
+    /// # Arguments
+    ///
+    /// + `config`: Configuration from command line arguments and
+    ///   initialization options.
+    pub fn apply(self, config: &mut crate::TagsLspConfig) {
+        use clap::ValueEnum;
+
+        if config.tags.is_empty() {
+            config.tags = self.tags_files.unwrap_or_default();
+        }
+
+        if config.ctags_bin.is_none() {
+            config.ctags_bin = self.ctags_binary;
+        }
+
+        if config.ctags_args.is_empty() {
+            config.ctags_args = self.ctags_args.unwrap_or_default();
+        }
+
+        // `--quiet` is an explicit choice of level.
+        if config.loglevel.is_none() && !config.quiet {
+            if let Some(v) = self.log_level {
+                match crate::LogLevel::from_str(&v, true) {
+                    Ok(v) => config.loglevel = Some(v),
+                    Err(e) => tracing::warn!("Invalid logLevel `{}`: {}", v, e),
+                }
+            }
+        }
+
+        if config.max_results.is_none() {
+            config.max_results = self.max_results;
+        }
+
+        if !config.no_auto_generate {
+            config.no_auto_generate = !self.auto_generate.unwrap_or(true);
+        }
+
+        if config.sorted_index_threshold.is_none() {
+            config.sorted_index_threshold = self.sorted_index_threshold;
+        }
+    }
+}
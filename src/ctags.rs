@@ -0,0 +1,564 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+/// The maximum number of attempts to run ctags.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for each retry.
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Default ctags arguments for languages found in workspace.
+///
+/// Prototypes and extern variables are indexed so that they can be found by
+/// `textDocument/declaration`.
+const LANGUAGE_ARGS: &[(&str, &[&str])] =
+    &[("C", &["--c-kinds=+px"]), ("C++", &["--c++-kinds=+px"])];
+
+/// A command to run, described as data so that runners can inspect it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    /// Program to execute.
+    pub program: String,
+
+    /// Arguments of program.
+    pub args: Vec<String>,
+
+    /// Working directory.
+    pub cwd: PathBuf,
+
+    /// Content written to stdin, stdin is null if `None`.
+    pub stdin: Option<Vec<u8>>,
+}
+
+/// Captured result of a finished command.
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    /// Exit code, `None` if terminated by signal.
+    pub code: Option<i32>,
+
+    /// Content of stdout.
+    pub stdout: Vec<u8>,
+
+    /// Content of stderr.
+    pub stderr: Vec<u8>,
+}
+
+/// Runs commands to completion.
+#[tower_lsp::async_trait]
+pub trait CommandRunner: Send + Sync {
+    /// Run command and capture its output.
+    ///
+    /// # Arguments
+    ///
+    /// + `cmd`: The command to run.
+    async fn run(&self, cmd: &CommandSpec) -> std::io::Result<CommandOutput>;
+}
+
+/// Runs commands as child processes.
+pub struct SystemRunner;
+
+#[tower_lsp::async_trait]
+impl CommandRunner for SystemRunner {
+    async fn run(&self, cmd: &CommandSpec) -> std::io::Result<CommandOutput> {
+        let mut child = tokio::process::Command::new(&cmd.program)
+            .current_dir(&cmd.cwd)
+            .args(&cmd.args)
+            .stdin(match cmd.stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        if let (Some(data), Some(mut stdin)) = (&cmd.stdin, child.stdin.take()) {
+            stdin.write_all(data).await?;
+        }
+
+        let output = child.wait_with_output().await?;
+        return Ok(CommandOutput {
+            code: output.status.code(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        });
+    }
+}
+
+/// Runner that records commands and returns canned output, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockRunner {
+    /// Commands run so far.
+    pub calls: std::sync::Mutex<Vec<CommandSpec>>,
+
+    /// Results to return in order, successful empty output when exhausted.
+    pub results: std::sync::Mutex<std::collections::VecDeque<std::io::Result<CommandOutput>>>,
+}
+
+#[cfg(test)]
+#[tower_lsp::async_trait]
+impl CommandRunner for MockRunner {
+    async fn run(&self, cmd: &CommandSpec) -> std::io::Result<CommandOutput> {
+        self.calls.lock().unwrap().push(cmd.clone());
+        return match self.results.lock().unwrap().pop_front() {
+            Some(v) => v,
+            None => Ok(CommandOutput {
+                code: Some(0),
+                ..Default::default()
+            }),
+        };
+    }
+}
+
+/// Generate tags file for workspace.
+///
+/// The tags file is written to the root of workspace, and all file paths in
+/// it are relative to the workspace. Transient failures of spawning ctags are
+/// retried, see [`retry`].
+///
+/// # Arguments
+///
+/// + `runner`: Runs ctags, usually [`SystemRunner`].
+/// + `ctags_bin`: The ctags program to execute.
+/// + `workspace`: Workspace folder path.
+/// + `filename`: File name of tags file.
+/// + `no_ignore`: Do not respect ignore files, let ctags walk the workspace.
+/// + `args`: Extra arguments from user, see [`user_args`].
+pub async fn run_ctags(
+    runner: &dyn CommandRunner,
+    ctags_bin: &str,
+    workspace: &Path,
+    filename: &str,
+    no_ignore: bool,
+    args: &[String],
+) -> std::io::Result<PathBuf> {
+    let path = workspace.join(filename);
+
+    let dir = workspace.to_path_buf();
+    let files = tokio::task::spawn_blocking(move || {
+        crate::walk::walk(&dir, no_ignore, globset::GlobSet::empty())
+    })
+    .await?;
+
+    let mut extra_args = language_args(files.iter().map(|v| v.as_path()));
+    extra_args.extend(user_args(args));
+    tracing::debug!("Extra ctags arguments: {:?}", extra_args);
+
+    let mut cmd = CommandSpec {
+        program: ctags_bin.to_string(),
+        args: vec!["-f".to_string(), path.to_string_lossy().to_string()],
+        cwd: workspace.to_path_buf(),
+        stdin: None,
+    };
+    cmd.args.extend(extra_args);
+
+    if no_ignore {
+        cmd.args.push("-R".to_string());
+    } else {
+        // Feed the file list that respects ignore files through stdin.
+        let mut file_list = String::new();
+        for file in files {
+            let file = file.strip_prefix(workspace).unwrap_or(&file);
+            file_list.push_str(&file.to_string_lossy());
+            file_list.push('\n');
+        }
+
+        cmd.args.push("-L".to_string());
+        cmd.args.push("-".to_string());
+        cmd.stdin = Some(file_list.into_bytes());
+    }
+
+    let output = retry(ctags_bin, || runner.run(&cmd)).await?;
+    return check_output(ctags_bin, &output).map(|_| path);
+}
+
+/// Run ctags until it does not fail with IO error.
+///
+/// Up to `MAX_ATTEMPTS` attempts are made with exponential backoff. ctags that
+/// cannot be found is not retried, and the error tells how to fix it. Nonzero
+/// exit status is not an IO error, so it is not retried either.
+///
+/// # Arguments
+///
+/// + `ctags_bin`: The ctags program to execute, for logging.
+/// + `f`: Run ctags once.
+async fn retry<F, Fut>(ctags_bin: &str, mut f: F) -> std::io::Result<CommandOutput>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = std::io::Result<CommandOutput>>,
+{
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let e = match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+
+        if e.kind() == std::io::ErrorKind::NotFound {
+            return Err(std::io::Error::new(
+                e.kind(),
+                format!(
+                    "`{}` is not found, install ctags or point --ctags-bin at it",
+                    ctags_bin
+                ),
+            ));
+        }
+        if attempt == MAX_ATTEMPTS {
+            return Err(std::io::Error::new(
+                e.kind(),
+                format!(
+                    "run `{}` failed after {} attempts: {}",
+                    ctags_bin, attempt, e
+                ),
+            ));
+        }
+
+        tracing::warn!(
+            "Run `{}` failed (attempt {}/{}): {}, retry in {:?}",
+            ctags_bin,
+            attempt,
+            MAX_ATTEMPTS,
+            e,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Generate tags for one file.
+///
+/// File paths of the returned entries are relative to `dir`.
+///
+/// # Arguments
+///
+/// + `runner`: Runs ctags, usually [`SystemRunner`].
+/// + `ctags_bin`: The ctags program to execute.
+/// + `dir`: Directory to run ctags in, usually where the tags file is.
+/// + `file`: Path of source file.
+/// + `args`: Extra arguments from user, see [`user_args`].
+/// + `encoding`: Encoding of source file, ctags copies patterns from it.
+pub async fn run_ctags_file(
+    runner: &dyn CommandRunner,
+    ctags_bin: &str,
+    dir: &Path,
+    file: &Path,
+    args: &[String],
+    encoding: &'static encoding_rs::Encoding,
+) -> std::io::Result<Vec<crate::tags::TagEntry>> {
+    // Same arguments as the whole workspace, so entries look the same.
+    let mut extra_args = language_args(std::iter::once(file));
+    extra_args.extend(user_args(args));
+
+    let file = file.strip_prefix(dir).unwrap_or(file);
+
+    let mut cmd = CommandSpec {
+        program: ctags_bin.to_string(),
+        args: vec!["-f".to_string(), "-".to_string()],
+        cwd: dir.to_path_buf(),
+        stdin: None,
+    };
+    cmd.args.extend(extra_args);
+    cmd.args.push(file.to_string_lossy().to_string());
+
+    let output = retry(ctags_bin, || runner.run(&cmd)).await?;
+    check_output(ctags_bin, &output)?;
+
+    let (content, _, _) = encoding.decode(&output.stdout);
+    let entries = content
+        .lines()
+        .filter(|v| !v.starts_with("!_TAG_"))
+        .filter_map(crate::tags::parse_line)
+        .collect();
+
+    return Ok(entries);
+}
+
+/// Default arguments for languages of files.
+///
+/// # Arguments
+///
+/// + `files`: Source files to index.
+fn language_args<'a>(files: impl Iterator<Item = &'a Path>) -> Vec<String> {
+    let mut languages = std::collections::HashSet::new();
+    for file in files {
+        if let Some(v) = crate::tags::language_from_path(file) {
+            languages.insert(v);
+        }
+    }
+
+    return LANGUAGE_ARGS
+        .iter()
+        .filter(|(lang, _)| languages.contains(lang))
+        .flat_map(|(_, args)| args.iter().map(|v| v.to_string()))
+        .collect();
+}
+
+/// Drop arguments that change where ctags writes the output.
+///
+/// The output path is always controlled by server, so `-f`, `-o` and their
+/// values are removed with a warning.
+///
+/// # Arguments
+///
+/// + `args`: Extra arguments from user.
+fn user_args(args: &[String]) -> Vec<String> {
+    let mut ret = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-f" | "-o" => {
+                let value = iter.next();
+                tracing::warn!(
+                    "Ignore ctags argument `{} {}`: output is controlled by server",
+                    arg,
+                    value.map(|v| v.as_str()).unwrap_or_default()
+                );
+            }
+            v if v.starts_with("-f") || v.starts_with("-o") => {
+                tracing::warn!(
+                    "Ignore ctags argument `{}`: output is controlled by server",
+                    arg
+                );
+            }
+            _ => ret.push(arg.clone()),
+        }
+    }
+
+    return ret;
+}
+
+/// Convert unsuccessful exit status into error.
+///
+/// # Arguments
+///
+/// + `ctags_bin`: The ctags program executed.
+/// + `output`: Output of ctags.
+fn check_output(ctags_bin: &str, output: &CommandOutput) -> std::io::Result<()> {
+    let status = match output.code {
+        Some(0) => return Ok(()),
+        Some(v) => format!("exit status: {}", v),
+        None => String::from("signal"),
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    return Err(std::io::Error::other(format!(
+        "`{}` exit with {}: {}",
+        ctags_bin,
+        status,
+        stderr.trim()
+    )));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create runner that returns `results` in order.
+    ///
+    /// # Arguments
+    ///
+    /// + `results`: Results of each run.
+    fn mock(results: Vec<std::io::Result<CommandOutput>>) -> MockRunner {
+        return MockRunner {
+            calls: Default::default(),
+            results: std::sync::Mutex::new(results.into()),
+        };
+    }
+
+    /// Run `ctags -f - main.c` through runner.
+    ///
+    /// # Arguments
+    ///
+    /// + `runner`: Runner of ctags.
+    async fn run_file(runner: &MockRunner) -> std::io::Result<Vec<crate::tags::TagEntry>> {
+        return run_ctags_file(
+            runner,
+            "ctags",
+            Path::new("/src"),
+            Path::new("/src/main.c"),
+            &[],
+            encoding_rs::UTF_8,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn retry_after_transient_failure() {
+        let runner = mock(vec![
+            Err(std::io::Error::from(std::io::ErrorKind::Interrupted)),
+            Ok(CommandOutput {
+                code: Some(0),
+                ..Default::default()
+            }),
+        ]);
+        assert!(run_file(&runner).await.unwrap().is_empty());
+        assert_eq!(runner.calls.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_attempts() {
+        let runner = mock(
+            (0..MAX_ATTEMPTS)
+                .map(|_| Err(std::io::Error::from(std::io::ErrorKind::Interrupted)))
+                .collect(),
+        );
+        let e = run_file(&runner).await.unwrap_err();
+        assert!(e.to_string().contains("after 3 attempts"), "{}", e);
+        assert_eq!(runner.calls.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[tokio::test]
+    async fn no_retry_if_not_found_or_exit_with_error() {
+        let runner = mock(vec![Err(std::io::Error::from(
+            std::io::ErrorKind::NotFound,
+        ))]);
+        let e = run_file(&runner).await.unwrap_err();
+        assert!(e.to_string().contains("--ctags-bin"), "{}", e);
+        assert_eq!(runner.calls.lock().unwrap().len(), 1);
+
+        let runner = mock(vec![Ok(CommandOutput {
+            code: Some(1),
+            stderr: b"bad option".to_vec(),
+            ..Default::default()
+        })]);
+        let e = run_file(&runner).await.unwrap_err();
+        assert!(e.to_string().contains("bad option"), "{}", e);
+        assert_eq!(runner.calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn generate_workspace_tags() {
+        let dir = std::env::temp_dir().join(format!("tags-lsp-test-ctags-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+
+        let runner = mock(Vec::new());
+        let path = run_ctags(&runner, "ctags", &dir, "tags", false, &[])
+            .await
+            .unwrap();
+        assert_eq!(path, dir.join("tags"));
+
+        let tags = dir.join("tags").to_string_lossy().to_string();
+        {
+            let calls = runner.calls.lock().unwrap();
+            assert_eq!(calls.len(), 1);
+            assert_eq!(calls[0].program, "ctags");
+            assert_eq!(calls[0].cwd, dir);
+            assert_eq!(calls[0].args, ["-f", &tags, "--c-kinds=+px", "-L", "-"]);
+            assert_eq!(calls[0].stdin.as_deref(), Some(&b"main.c\n"[..]));
+        }
+
+        // ctags walks the workspace itself.
+        let runner = mock(Vec::new());
+        run_ctags(&runner, "ctags", &dir, "tags", true, &[])
+            .await
+            .unwrap();
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0].args, ["-f", &tags, "--c-kinds=+px", "-R"]);
+        assert_eq!(calls[0].stdin, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn generate_file_tags() {
+        let runner = mock(vec![Ok(CommandOutput {
+            code: Some(0),
+            stdout: b"!_TAG_FILE_SORTED\t1\t//\nmain\tmain.c\t1\n".to_vec(),
+            ..Default::default()
+        })]);
+        let entries = run_ctags_file(
+            &runner,
+            "ctags",
+            Path::new("/src"),
+            Path::new("/src/main.c"),
+            &[String::from("--fields=+l")],
+            encoding_rs::UTF_8,
+        )
+        .await
+        .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "main");
+        assert_eq!(entries[0].file, "main.c");
+
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0].cwd, Path::new("/src"));
+        assert_eq!(
+            calls[0].args,
+            ["-f", "-", "--c-kinds=+px", "--fields=+l", "main.c"]
+        );
+    }
+
+    #[test]
+    fn drop_output_arguments() {
+        let args: Vec<String> = [
+            "--languages=C,C++",
+            "-f",
+            "other",
+            "-otags2",
+            "--kinds-C=+p",
+            "-o",
+            "tags3",
+            "-ftags4",
+            "-R",
+        ]
+        .iter()
+        .map(|v| v.to_string())
+        .collect();
+        assert_eq!(
+            user_args(&args),
+            ["--languages=C,C++", "--kinds-C=+p", "-R"]
+        );
+
+        // Trailing flag without value.
+        assert!(user_args(&[String::from("-f")]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn pass_user_arguments() {
+        let runner = mock(Vec::new());
+        let args = [
+            String::from("--languages=C"),
+            String::from("-f"),
+            String::from("/tmp/other"),
+        ];
+        run_ctags_file(
+            &runner,
+            "ctags",
+            Path::new("/src"),
+            Path::new("/src/main.rs"),
+            &args,
+            encoding_rs::UTF_8,
+        )
+        .await
+        .unwrap();
+
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0].args, ["-f", "-", "--languages=C", "main.rs"]);
+    }
+
+    #[tokio::test]
+    async fn missing_ctags_binary() {
+        let e = run_ctags_file(
+            &SystemRunner,
+            "tags-lsp-test-no-such-ctags",
+            &std::env::temp_dir(),
+            Path::new("main.c"),
+            &[],
+            encoding_rs::UTF_8,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(
+            e.to_string(),
+            "`tags-lsp-test-no-such-ctags` is not found, install ctags or point --ctags-bin at it"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+/// Failure of request handler.
+///
+/// Converted into [`tower_lsp::jsonrpc::Error`] with a human readable message
+/// before returning to client.
+#[derive(Debug)]
+pub enum TagsLspError {
+    /// IO failed, e.g. source file cannot be read.
+    Io {
+        /// What was being done.
+        context: String,
+
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// Text cannot be parsed, e.g. output of external program.
+    Parse {
+        /// What was being parsed.
+        context: String,
+
+        /// Why it cannot be parsed.
+        detail: String,
+    },
+
+    /// Request arrived before initialization completes.
+    NotInitialized,
+
+    /// Symbol, line or path cannot be resolved.
+    Lookup(String),
+
+    /// Request is rejected in current state, e.g. a required program is
+    /// missing.
+    Failed(String),
+
+    /// Parameters of request are invalid.
+    InvalidParams {
+        /// Why parameters are invalid.
+        message: String,
+
+        /// Extra information for client.
+        data: Option<serde_json::Value>,
+    },
+}
+
+impl TagsLspError {
+    /// Create IO error with context.
+    ///
+    /// # Arguments
+    ///
+    /// + `context`: What was being done.
+    /// + `source`: The underlying error.
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> TagsLspError {
+        return TagsLspError::Io {
+            context: context.into(),
+            source,
+        };
+    }
+
+    /// Create parse error with context.
+    ///
+    /// # Arguments
+    ///
+    /// + `context`: What was being parsed.
+    /// + `detail`: Why it cannot be parsed.
+    pub fn parse(context: impl Into<String>, detail: impl ToString) -> TagsLspError {
+        return TagsLspError::Parse {
+            context: context.into(),
+            detail: detail.to_string(),
+        };
+    }
+}
+
+impl std::fmt::Display for TagsLspError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TagsLspError::Io { context, source } => return write!(f, "{}: {}", context, source),
+            TagsLspError::Parse { context, detail } => {
+                return write!(f, "cannot parse {}: {}", context, detail)
+            }
+            TagsLspError::NotInitialized => return write!(f, "server not initialized"),
+            TagsLspError::Lookup(v) => return write!(f, "{}", v),
+            TagsLspError::Failed(v) => return write!(f, "{}", v),
+            TagsLspError::InvalidParams { message, .. } => return write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TagsLspError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TagsLspError::Io { source, .. } => return Some(source),
+            _ => return None,
+        }
+    }
+}
+
+impl From<TagsLspError> for tower_lsp::jsonrpc::Error {
+    fn from(value: TagsLspError) -> Self {
+        let code = match &value {
+            TagsLspError::NotInitialized => tower_lsp::jsonrpc::ErrorCode::ServerError(
+                crate::LspErrorCode::ServerNotInitialized.code(),
+            ),
+            TagsLspError::InvalidParams { .. } => tower_lsp::jsonrpc::ErrorCode::InvalidParams,
+            _ => tower_lsp::jsonrpc::ErrorCode::ServerError(
+                crate::LspErrorCode::RequestFailed.code(),
+            ),
+        };
+
+        let message = value.to_string();
+        let data = match value {
+            TagsLspError::InvalidParams { data, .. } => data,
+            _ => None,
+        };
+
+        return tower_lsp::jsonrpc::Error {
+            code,
+            message: std::borrow::Cow::Owned(message),
+            data,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::jsonrpc::ErrorCode;
+
+    #[test]
+    fn code_and_message_of_error() {
+        let convert = |e: TagsLspError| {
+            let e = tower_lsp::jsonrpc::Error::from(e);
+            return (e.code, e.message.to_string(), e.data);
+        };
+
+        let e = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+        assert_eq!(
+            convert(TagsLspError::io("cannot open a.c", e)),
+            (
+                ErrorCode::ServerError(-32803),
+                String::from("cannot open a.c: not found"),
+                None
+            )
+        );
+        assert_eq!(
+            convert(TagsLspError::parse("output of global", "bad line")),
+            (
+                ErrorCode::ServerError(-32803),
+                String::from("cannot parse output of global: bad line"),
+                None
+            )
+        );
+        assert_eq!(
+            convert(TagsLspError::NotInitialized),
+            (
+                ErrorCode::ServerError(-32002),
+                String::from("server not initialized"),
+                None
+            )
+        );
+        assert_eq!(
+            convert(TagsLspError::Lookup(String::from("line 3 out of range"))),
+            (
+                ErrorCode::ServerError(-32803),
+                String::from("line 3 out of range"),
+                None
+            )
+        );
+
+        let data = Some(serde_json::json!({ "uri": "file:///a.c" }));
+        let e = TagsLspError::InvalidParams {
+            message: String::from("cannot find workspace folder"),
+            data: data.clone(),
+        };
+        assert_eq!(
+            convert(e),
+            (
+                ErrorCode::InvalidParams,
+                String::from("cannot find workspace folder"),
+                data
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn error_of_failed_handler() {
+        let e = crate::method::read_file_content("/nonexistent/a.c", encoding_rs::UTF_8)
+            .await
+            .unwrap_err();
+        assert_eq!(e.code, ErrorCode::ServerError(-32803));
+        assert!(
+            e.message.starts_with("cannot open /nonexistent/a.c: "),
+            "{}",
+            e.message
+        );
+
+        let pos = tower_lsp::lsp_types::Position::new(5, 0);
+        let e =
+            crate::method::get_symbol_by_pos_from_dat("int a;\n", &pos, "c", false).unwrap_err();
+        assert_eq!(e.code, ErrorCode::ServerError(-32803));
+        assert_eq!(e.message, "line 6 out of range");
+
+        let pos = tower_lsp::lsp_types::Position::new(0, 6);
+        let e =
+            crate::method::get_symbol_by_pos_from_dat("int a;\n", &pos, "c", false).unwrap_err();
+        assert_eq!(e.message, "no word found at line 1, character 6");
+    }
+}
@@ -0,0 +1,240 @@
+use regex::Regex;
+use tower_lsp::lsp_types::*;
+
+/// Statements that look like `NAME(...)` but never define anything.
+const KEYWORDS: &[&str] = &[
+    "return", "else", "new", "delete", "throw", "case", "goto", "await", "yield", "if", "while",
+    "for", "switch", "sizeof",
+];
+
+/// Definition patterns of language, `NAME` is replaced with the symbol.
+///
+/// # Arguments
+///
+/// + `lang`: Language of file, as named by ctags.
+fn patterns(lang: &str) -> &'static [&'static str] {
+    match lang {
+        "Rust" => &[
+            r"\b(?:fn|struct|enum|union|trait|type|mod|const|static)\s+NAME\b",
+            r"\bmacro_rules!\s*NAME\b",
+        ],
+        "Python" => &[r"^\s*(?:async\s+)?(?:def|class)\s+NAME\b"],
+        "Go" => &[r"^\s*(?:func(?:\s*\([^)]*\))?|type|var|const)\s+NAME\b"],
+        "JavaScript" | "TypeScript" => &[
+            r"\b(?:function\*?|class|interface|type|enum)\s+NAME\b",
+            r"^\s*(?:export\s+)?(?:const|let|var)\s+NAME\b",
+        ],
+        "Ruby" => &[r"^\s*(?:def|class|module)\s+(?:self\.)?NAME\b"],
+        "Lua" => &[r"\bfunction\s+(?:[\w.:]+[.:])?NAME\b"],
+        "Sh" => &[r"^\s*(?:function\s+NAME\b|NAME\s*\(\s*\))"],
+        "Perl" => &[r"^\s*sub\s+NAME\b"],
+        "PHP" => &[r"\b(?:function|class|interface|trait)\s+NAME\b"],
+        _ => &[
+            r"^\s*#\s*define\s+NAME\b",
+            r"\b(?:struct|class|union|enum|interface)\s+NAME\b[^;]*$",
+            r"^\s*(?:[\w:<>\*&,\[\]]+\s+)+[\*&]*(?:\w+::)*NAME\s*\([^;]*$",
+        ],
+    }
+}
+
+/// Compile definition patterns of language for symbol.
+///
+/// The symbol is captured by the first group.
+///
+/// # Arguments
+///
+/// + `lang`: Language of file, as named by ctags.
+/// + `name`: Symbol name.
+fn compile(lang: &str, name: &str) -> Vec<Regex> {
+    let name = format!("({})", regex::escape(name));
+    return patterns(lang)
+        .iter()
+        .filter_map(|v| Regex::new(&v.replace("NAME", &name)).ok())
+        .collect();
+}
+
+/// Search source files of workspace folder for lines that look like the
+/// definition of symbol.
+///
+/// Only used when tags know nothing about the symbol, so the result is a
+/// guess that may contain false positives.
+///
+/// # Arguments
+///
+/// + `rt`: Snapshot of runtime.
+/// + `cwd`: Workspace folder Url.
+/// + `name`: Symbol name.
+/// + `lang`: Language of the requesting file, as named by ctags.
+pub async fn find_definitions(
+    rt: &crate::Snapshot,
+    cwd: &Url,
+    name: &str,
+    lang: Option<&str>,
+) -> Vec<Location> {
+    let mut loc_list = Vec::new();
+    let max_results = rt.config.max_results();
+    let encoding = rt.config.file_encoding();
+
+    // Dropped without finishing if client cancels the request.
+    let guard = crate::method::CancelGuard::new("Definition grep");
+
+    let scan = async {
+        let files = crate::method::list_source_files(cwd, rt.config.no_ignore, &rt.exclude).await;
+        for file in files {
+            // Give cancellation and timeout a chance between files.
+            tokio::task::yield_now().await;
+
+            let file_lang = match crate::tags::language_from_path(std::path::Path::new(&file)) {
+                Some(v) => v,
+                None => continue,
+            };
+            if lang.is_some_and(|v| !crate::tags::is_same_language(v, file_lang)) {
+                continue;
+            }
+
+            if rt.config.max_file_size != 0 {
+                let size = tokio::fs::metadata(&file).await.map(|v| v.len());
+                if size.is_ok_and(|v| v > rt.config.max_file_size) {
+                    continue;
+                }
+            }
+
+            let content = match crate::method::read_file_content(&file, encoding).await {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            // Cheap check before running regex on every line.
+            if !content.contains(name) {
+                continue;
+            }
+            let uri = match crate::tags::path_to_uri(std::path::Path::new(&file)) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let re_list = compile(file_lang, name);
+            for (line_no, line) in content.lines().enumerate() {
+                if line
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .find(|v| !v.is_empty())
+                    .is_some_and(|v| KEYWORDS.contains(&v))
+                {
+                    continue;
+                }
+
+                let mat = match re_list
+                    .iter()
+                    .find_map(|re| re.captures(line).and_then(|v| v.get(1)))
+                {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let range = match rt.config.low_precision {
+                    true => Range::new(
+                        Position::new(line_no as u32, 0),
+                        Position::new(line_no as u32, 0),
+                    ),
+                    false => Range::new(
+                        Position::new(line_no as u32, mat.start() as u32),
+                        Position::new(line_no as u32, mat.end() as u32),
+                    ),
+                };
+                loc_list.push(Location::new(uri.clone(), range));
+                if loc_list.len() >= max_results {
+                    return;
+                }
+            }
+        }
+    };
+
+    match rt.config.reference_timeout_ms {
+        0 => scan.await,
+        v => {
+            let timeout = std::time::Duration::from_millis(v);
+            tokio::select! {
+                _ = scan => (),
+                _ = tokio::time::sleep(timeout) => {
+                    tracing::debug!(
+                        "Definition grep of `{}` cut short: timeout after {:?}",
+                        name,
+                        timeout
+                    );
+                }
+            }
+        }
+    }
+    guard.finish();
+
+    tracing::debug!(
+        "Definition grep of `{}` found {} candidates",
+        name,
+        loc_list.len()
+    );
+    return loc_list;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definition_patterns() {
+        let is_match = |lang: &str, name: &str, line: &str| {
+            return compile(lang, name).iter().any(|v| v.is_match(line));
+        };
+
+        assert!(is_match("C", "helper", "static int helper(int x)"));
+        assert!(is_match("C", "Point", "struct Point {"));
+        assert!(is_match("C", "MAX", "#define MAX 10"));
+        assert!(!is_match("C", "helper", "    x = helper(1);"));
+        assert!(!is_match("C", "helper", "int helper(int x);"));
+        assert!(is_match("Rust", "run", "pub async fn run() {"));
+        assert!(is_match("Python", "run", "    async def run(self):"));
+        assert!(!is_match("Python", "run", "run()"));
+    }
+
+    #[tokio::test]
+    async fn find_symbol_missing_from_tags() {
+        let dir = std::env::temp_dir().join(format!("tags-lsp-test-grep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = "int helper(int x)\n{\n    return x;\n}\n\nint main(void)\n{\n    return helper(1);\n}\n";
+        std::fs::write(dir.join("a.c"), content).unwrap();
+        std::fs::write(dir.join("tags-lsp-test-tags"), "main\ta.c\t6;\"\tf\n").unwrap();
+        let root = Url::from_directory_path(&dir).unwrap();
+        let uri = Url::from_file_path(dir.join("a.c")).unwrap();
+
+        let mut ret = Vec::new();
+        for fallback in [false, true] {
+            let mut args = vec![
+                "--no-auto-generate",
+                "--tags-filename",
+                "tags-lsp-test-tags",
+            ];
+            if fallback {
+                args.push("--grep-fallback");
+            }
+            let mut client = crate::testing::Client::start(&args);
+            client.initialize(&root).await;
+
+            let params = GotoDefinitionParams {
+                text_document_position_params: crate::testing::position(&uri, 7, 12),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            ret.push(
+                client
+                    .request::<request::GotoDefinition>(params)
+                    .await
+                    .unwrap(),
+            );
+            client.shutdown().await;
+        }
+
+        let expect = Location::new(uri, Range::new(Position::new(0, 4), Position::new(0, 10)));
+        assert_eq!(ret[0], Some(GotoDefinitionResponse::Array(Vec::new())));
+        assert_eq!(ret[1], Some(GotoDefinitionResponse::Scalar(expect)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Input stream that ends when no bytes arrive for a while.
+///
+/// Ending the stream looks like the client closed connection, so the server
+/// stops serving it and its session is cleaned up.
+pub struct IdleTimeout<R> {
+    inner: R,
+
+    /// How long to wait for bytes, `None` for no limit.
+    timeout: Option<Duration>,
+
+    /// Fires when the connection has been idle for `timeout`.
+    sleep: Pin<Box<tokio::time::Sleep>>,
+}
+
+impl<R> IdleTimeout<R> {
+    /// Wrap input stream.
+    ///
+    /// # Arguments
+    ///
+    /// + `inner`: Input stream.
+    /// + `timeout`: How long to wait for bytes, `None` for no limit.
+    pub fn new(inner: R, timeout: Option<Duration>) -> IdleTimeout<R> {
+        let sleep = Box::pin(tokio::time::sleep(timeout.unwrap_or(Duration::MAX)));
+        return IdleTimeout {
+            inner,
+            timeout,
+            sleep,
+        };
+    }
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for IdleTimeout<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+
+        let before = buf.filled().len();
+        if let Poll::Ready(v) = Pin::new(&mut this.inner).poll_read(cx, buf) {
+            if let (Some(timeout), true) = (this.timeout, buf.filled().len() > before) {
+                let deadline = tokio::time::Instant::now() + timeout;
+                this.sleep.as_mut().reset(deadline);
+            }
+            return Poll::Ready(v);
+        }
+
+        let timeout = match this.timeout {
+            Some(v) => v,
+            None => return Poll::Pending,
+        };
+        match this.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                tracing::info!("No data from client for {:?}, close connection", timeout);
+                return Poll::Ready(Ok(()));
+            }
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn end_stream_after_silence() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut input = IdleTimeout::new(server, Some(Duration::from_millis(100)));
+        let mut buf = [0u8; 16];
+
+        // Bytes arrive in time.
+        let mut last = std::time::Instant::now();
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.write_all(b"ping").await.unwrap();
+            assert_eq!(input.read(&mut buf).await.unwrap(), 4);
+            last = std::time::Instant::now();
+        }
+
+        // Client goes silent without closing connection.
+        assert_eq!(input.read(&mut buf).await.unwrap(), 0);
+        assert!(last.elapsed() >= Duration::from_millis(90));
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn wait_forever_without_timeout() {
+        let (_client, server) = tokio::io::duplex(1024);
+        let mut input = IdleTimeout::new(server, None);
+        let mut buf = [0u8; 16];
+
+        let ret = tokio::time::timeout(Duration::from_millis(200), input.read(&mut buf)).await;
+        assert!(ret.is_err());
+    }
+
+    #[tokio::test]
+    async fn close_silent_session() {
+        let (_client, server) = tokio::io::duplex(1024);
+        let (input, output) = tokio::io::split(server);
+        let input = IdleTimeout::new(input, Some(Duration::from_millis(100)));
+
+        let session = crate::serve_session(crate::Runtime::default(), input, output);
+        tokio::time::timeout(Duration::from_secs(5), session)
+            .await
+            .expect("session is not closed");
+    }
+}
@@ -0,0 +1,647 @@
+use tower_lsp::lsp_types::*;
+
+/// Backend that reads ctags `tags` files.
+pub struct CtagsBackend<'a> {
+    /// Loaded tags files.
+    pub tags: &'a [std::sync::Arc<crate::tags::TagsFile>],
+
+    /// Workspace folder to scan for references.
+    pub cwd: &'a Url,
+
+    /// Do not find precise position, just point to begin of line.
+    pub low_precision: bool,
+
+    /// Whether to confirm addresses against file contents.
+    pub address_resolution: crate::tags::AddressResolution,
+
+    /// Do not respect ignore files.
+    pub no_ignore: bool,
+
+    /// Paths to skip when scanning for references.
+    pub exclude: &'a globset::GlobSet,
+
+    /// How to compare symbol with tag names.
+    pub match_mode: crate::tags::MatchMode,
+
+    /// Stop scanning for references after this long, `None` for no limit.
+    pub reference_timeout: Option<std::time::Duration>,
+
+    /// Skip files larger than this when scanning for references, `None` for
+    /// no limit.
+    pub max_file_size: Option<u64>,
+
+    /// Cache of file contents for current request.
+    pub cache: &'a crate::cache::FileCache,
+}
+
+#[tower_lsp::async_trait]
+impl<'a> super::Backend for CtagsBackend<'a> {
+    /// Definitions in the same language as `lang` are preferred. Definitions
+    /// in other languages are only returned if there is no same language
+    /// match. Declarations are only returned if there is no definition.
+    async fn lookup(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        return self.find(name, lang, &|v| !v.is_declaration()).await;
+    }
+
+    /// Definitions whose scope matches qualifier are preferred. Unqualified
+    /// lookup is used if there is no such definition.
+    async fn lookup_scoped(
+        &self,
+        name: &str,
+        qualifier: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        let is_scoped = |v: &crate::tags::TagEntry| {
+            !v.is_declaration()
+                && v.scope
+                    .as_deref()
+                    .is_some_and(|scope| scope_matches(scope, qualifier))
+        };
+
+        let has_scoped = self
+            .tags
+            .iter()
+            .flat_map(|v| v.lookup_with(name, self.match_mode))
+            .any(is_scoped);
+        if !has_scoped {
+            return self.lookup(name, lang).await;
+        }
+
+        return self.find(name, lang, &is_scoped).await;
+    }
+
+    /// Definitions are only returned if there is no declaration.
+    async fn declarations(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        return self.find(name, lang, &|v| v.is_declaration()).await;
+    }
+
+    /// Methods of Rust `impl` blocks and methods of classes that inherit
+    /// others are implementations. All definitions are returned if there is
+    /// no implementation.
+    async fn implementations(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        let is_impl = |v: &crate::tags::TagEntry| {
+            !v.is_declaration() && (v.is_implementation() || self.is_derived_member(v))
+        };
+
+        let has_impl = self
+            .tags
+            .iter()
+            .flat_map(|v| v.lookup_with(name, self.match_mode))
+            .any(is_impl);
+        if !has_impl {
+            return self.lookup(name, lang).await;
+        }
+
+        return self.find(name, lang, &is_impl).await;
+    }
+
+    /// A tags file only records definitions, so every textual match of the
+    /// symbol in workspace folder is treated as a reference.
+    async fn references(
+        &self,
+        name: &str,
+        include_declaration: bool,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        // Location list for return.
+        let mut loc_list = Vec::new();
+
+        // Dropped without finishing if client cancels the request.
+        let guard = crate::method::CancelGuard::new("Reference scan");
+
+        let scan = async {
+            let files =
+                crate::method::list_source_files(self.cwd, self.no_ignore, self.exclude).await;
+            for file in files {
+                // Give cancellation and timeout a chance between files.
+                tokio::task::yield_now().await;
+
+                if let Some(limit) = self.max_file_size {
+                    let size = tokio::fs::metadata(&file).await.map(|v| v.len());
+                    if size.as_ref().is_ok_and(|v| *v > limit) {
+                        tracing::debug!("Skip {}: larger than {} bytes", file, limit);
+                        continue;
+                    }
+                }
+
+                let content =
+                    match crate::method::read_file_content(&file, self.cache.encoding()).await {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    };
+                let uri = match crate::tags::path_to_uri(std::path::Path::new(&file)) {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let lang = crate::tags::language_id_from_path(std::path::Path::new(&file));
+                let lang = lang.unwrap_or_default();
+                for range in crate::method::find_symbol_in_content(&content, name, lang) {
+                    let range = match self.low_precision {
+                        true => Range::new(
+                            Position::new(range.start.line, 0),
+                            Position::new(range.start.line, 0),
+                        ),
+                        false => range,
+                    };
+                    loc_list.push(Location::new(uri.clone(), range));
+                }
+            }
+        };
+
+        match self.reference_timeout {
+            Some(timeout) => tokio::select! {
+                _ = scan => (),
+                _ = tokio::time::sleep(timeout) => {
+                    tracing::debug!(
+                        "Reference scan of `{}` cut short: timeout after {:?}",
+                        name,
+                        timeout
+                    );
+                }
+            },
+            None => scan.await,
+        }
+        guard.finish();
+
+        if !include_declaration {
+            let mut declaration_list = self.lookup(name, None).await.unwrap_or_default();
+            let mut ret = self.declarations(name, None).await.unwrap_or_default();
+            declaration_list.append(&mut ret);
+
+            loc_list.retain(|loc| {
+                !declaration_list
+                    .iter()
+                    .any(|ele| ele.uri == loc.uri && ele.range.start.line == loc.range.start.line)
+            });
+        }
+
+        return Ok(loc_list);
+    }
+}
+
+impl<'a> CtagsBackend<'a> {
+    /// Find tag entries of symbol and resolve their locations.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `lang`: Language of the requesting file, as named by ctags.
+    /// + `prefer`: Only keep entries accepted by it, unless there is none.
+    async fn find(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+        prefer: &(dyn Fn(&crate::tags::TagEntry) -> bool + Sync),
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        // Location list for return.
+        let mut loc_list = Vec::new();
+
+        let mut entry_list = Vec::new();
+        for tags_file in self.tags {
+            for entry in tags_file.lookup_with(name, self.match_mode) {
+                entry_list.push((tags_file, entry));
+            }
+        }
+
+        if entry_list.iter().any(|(_, entry)| prefer(entry)) {
+            entry_list.retain(|(_, entry)| prefer(entry));
+        }
+
+        if let Some(lang) = lang {
+            let is_same = |entry: &crate::tags::TagEntry| match entry.language() {
+                Some(v) => crate::tags::is_same_language(v, lang),
+                None => false,
+            };
+            if entry_list.iter().any(|(_, entry)| is_same(entry)) {
+                entry_list.retain(|(_, entry)| is_same(entry));
+            }
+        }
+
+        // Resolving patterns of a common name may touch many files.
+        let guard = crate::method::CancelGuard::new("Lookup");
+
+        // Several tags files may cover the same code.
+        let mut seen = std::collections::HashSet::new();
+        for (tags_file, entry) in entry_list {
+            // Give cancellation a chance, file contents may all be cached.
+            tokio::task::yield_now().await;
+
+            if !crate::method::tag_target_exists(tags_file, entry).await {
+                continue;
+            }
+            let location = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                self.low_precision,
+                self.address_resolution,
+                self.cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    tracing::debug!(
+                        "Skip stale tag `{}` in {}: {}",
+                        entry.name,
+                        entry.file,
+                        e.message
+                    );
+                    continue;
+                }
+            };
+            if seen.insert((&entry.name, location.uri.clone(), location.range.start.line)) {
+                loc_list.push(location);
+            }
+        }
+        guard.finish();
+
+        return Ok(loc_list);
+    }
+
+    /// Whether entry is a member of class that inherits others.
+    ///
+    /// # Arguments
+    ///
+    /// + `entry`: Tag entry.
+    fn is_derived_member(&self, entry: &crate::tags::TagEntry) -> bool {
+        let scope = match entry.scope.as_deref() {
+            Some(v) => v,
+            None => return false,
+        };
+
+        // Scope may be qualified, e.g. `ns::Derived`.
+        let class_name = crate::method::tag_name_of(scope);
+        return self
+            .tags
+            .iter()
+            .flat_map(|v| v.lookup(class_name))
+            .any(|v| v.inherits.is_some());
+    }
+}
+
+/// Whether scope of tag entry matches the qualifier written in code.
+///
+/// Either one may be more qualified than the other, e.g. scope `ns::Foo`
+/// matches qualifier `Foo`. Java and friends separate scopes with `.`.
+///
+/// # Arguments
+///
+/// + `scope`: Scope of tag entry.
+/// + `qualifier`: Scope qualifier written in code.
+fn scope_matches(scope: &str, qualifier: &str) -> bool {
+    let split = |v: &str| -> Vec<String> {
+        v.split([':', '.'])
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect()
+    };
+    let scope = split(scope);
+    let qualifier = split(qualifier);
+
+    let count = scope.len().min(qualifier.len());
+    return count > 0 && scope[scope.len() - count..] == qualifier[qualifier.len() - count..];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Backend;
+
+    /// Temporary workspace with source files and a tags file.
+    struct Workspace {
+        /// Root of workspace.
+        dir: std::path::PathBuf,
+
+        /// Url of root.
+        cwd: Url,
+
+        /// Loaded tags file.
+        tags: Vec<std::sync::Arc<crate::tags::TagsFile>>,
+
+        /// Nothing is excluded.
+        exclude: globset::GlobSet,
+
+        /// Cache of file contents.
+        cache: crate::cache::FileCache,
+    }
+
+    impl Workspace {
+        /// Create workspace.
+        ///
+        /// # Arguments
+        ///
+        /// + `name`: Unique name of workspace.
+        /// + `files`: File paths and contents.
+        /// + `tags`: Lines of tags file.
+        fn new(name: &str, files: &[(&str, &str)], tags: &[&str]) -> Workspace {
+            let dir =
+                std::env::temp_dir().join(format!("tags-lsp-test-{}-{}", name, std::process::id()));
+            for (path, content) in files {
+                let path = dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+            }
+
+            let entries = tags
+                .iter()
+                .filter_map(|v| crate::tags::parse_line(v))
+                .collect();
+            let tags = crate::tags::TagsFile::new(dir.join("tags"), entries);
+            return Workspace {
+                cwd: Url::from_directory_path(&dir).unwrap(),
+                dir,
+                tags: vec![std::sync::Arc::new(tags)],
+                exclude: globset::GlobSet::empty(),
+                cache: crate::cache::FileCache::new(encoding_rs::UTF_8),
+            };
+        }
+
+        /// Get backend of workspace.
+        ///
+        /// # Arguments
+        ///
+        /// + `match_mode`: How to compare symbol with tag names.
+        fn backend(&self, match_mode: crate::tags::MatchMode) -> CtagsBackend<'_> {
+            return CtagsBackend {
+                tags: &self.tags,
+                cwd: &self.cwd,
+                low_precision: false,
+                address_resolution: Default::default(),
+                no_ignore: false,
+                exclude: &self.exclude,
+                match_mode,
+                reference_timeout: None,
+                max_file_size: None,
+                cache: &self.cache,
+            };
+        }
+
+        /// Look up definitions of `name` in exact match mode.
+        ///
+        /// # Arguments
+        ///
+        /// + `name`: Symbol name.
+        /// + `lang`: Language of the requesting file.
+        async fn lookup(&self, name: &str, lang: Option<&str>) -> Vec<(String, u32)> {
+            let ret = self.backend(Default::default()).lookup(name, lang).await;
+            return self.describe(ret.unwrap());
+        }
+
+        /// Describe locations by file name and line.
+        ///
+        /// # Arguments
+        ///
+        /// + `list`: Locations found.
+        fn describe(&self, list: Vec<Location>) -> Vec<(String, u32)> {
+            return list
+                .into_iter()
+                .map(|v| {
+                    let path = v.uri.to_file_path().unwrap();
+                    let path = path
+                        .strip_prefix(&self.dir)
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string();
+                    (path, v.range.start.line)
+                })
+                .collect();
+        }
+    }
+
+    impl Drop for Workspace {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[tokio::test]
+    async fn definitions_in_several_files() {
+        let ws = Workspace::new(
+            "several",
+            &[
+                ("a.c", "int read(void) {}\n"),
+                ("b.c", "\nint read(int fd) {}\n"),
+            ],
+            &["read\ta.c\t1;\"\tf", "read\tb.c\t2;\"\tf"],
+        );
+        assert_eq!(
+            ws.lookup("read", None).await,
+            [(String::from("a.c"), 0), (String::from("b.c"), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn prefer_definitions_in_same_language() {
+        let ws = Workspace::new(
+            "language",
+            &[("io.c", "int read(void) {}\n"), ("io.py", "def read():\n")],
+            &["read\tio.c\t1;\"\tf", "read\tio.py\t1;\"\tf"],
+        );
+        assert_eq!(
+            ws.lookup("read", Some("Python")).await,
+            [(String::from("io.py"), 0)]
+        );
+        assert_eq!(
+            ws.lookup("read", Some("C")).await,
+            [(String::from("io.c"), 0)]
+        );
+
+        // Other languages only if there is no same language match.
+        assert_eq!(ws.lookup("read", Some("Rust")).await.len(), 2);
+        assert_eq!(ws.lookup("read", None).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn drop_lookup_before_finish() {
+        let files: Vec<_> = (0..50)
+            .map(|i| {
+                (
+                    format!("{}.c", i),
+                    format!("\nstatic int init(void) {{ return {}; }}\n", i),
+                )
+            })
+            .collect();
+        let tags: Vec<_> = (0..50)
+            .map(|i| {
+                format!(
+                    "init\t{}.c\t/^static int init(void) {{ return {}; }}$/;\"\tf",
+                    i, i
+                )
+            })
+            .collect();
+        let files: Vec<_> = files
+            .iter()
+            .map(|(a, b)| (a.as_str(), b.as_str()))
+            .collect();
+        let tags: Vec<_> = tags.iter().map(|v| v.as_str()).collect();
+        let ws = Workspace::new("cancel", &files, &tags);
+
+        // Client cancels the request after it started resolving patterns.
+        let backend = ws.backend(Default::default());
+        let mut lookup = Box::pin(backend.lookup("init", None));
+        for _ in 0..5 {
+            let _ = futures::poll!(&mut lookup);
+        }
+        drop(lookup);
+
+        let ret = ws.lookup("init", None).await;
+        assert_eq!(ret.len(), 50);
+        assert!(ret.iter().all(|(_, line)| *line == 1));
+    }
+
+    #[tokio::test]
+    async fn skip_tags_of_missing_files() {
+        let ws = Workspace::new(
+            "missing",
+            &[("a.c", "int read(void) {}\n")],
+            &[
+                "read\ta.c\t1;\"\tf",
+                "read\tgone.c\t1;\"\tf",
+                "write\tgone.c\t2;\"\tf",
+            ],
+        );
+        assert_eq!(ws.lookup("read", None).await, [(String::from("a.c"), 0)]);
+        assert_eq!(ws.lookup("write", None).await, []);
+    }
+
+    #[tokio::test]
+    async fn skip_large_files_in_reference_scan() {
+        let large = format!("int count;\n{}", "count++;\n".repeat(100));
+        let ws = Workspace::new(
+            "large",
+            &[("a.c", "int count;\n"), ("generated.c", &large)],
+            &["count\ta.c\t1;\"\tv"],
+        );
+
+        let mut backend = ws.backend(Default::default());
+        let ret = ws.describe(backend.references("count", true).await.unwrap());
+        assert_eq!(ret.len(), 102);
+
+        backend.max_file_size = Some(100);
+        let ret = ws.describe(backend.references("count", true).await.unwrap());
+        assert_eq!(ret, [(String::from("a.c"), 0)]);
+    }
+
+    #[tokio::test]
+    async fn trust_or_verify_shifted_line() {
+        // Two lines were inserted after tags were generated.
+        let ws = Workspace::new(
+            "shifted",
+            &[("a.c", "#include <unistd.h>\n\nint read(void) {}\n")],
+            &["read\ta.c\t/^int read(void) {}$/;\"\tf\tline:1"],
+        );
+
+        let mut backend = ws.backend(Default::default());
+        backend.address_resolution = crate::tags::AddressResolution::Verify;
+        let ret = ws.describe(backend.lookup("read", None).await.unwrap());
+        assert_eq!(ret, [(String::from("a.c"), 2)]);
+
+        backend.address_resolution = crate::tags::AddressResolution::Trust;
+        let ret = ws.describe(backend.lookup("read", None).await.unwrap());
+        assert_eq!(ret, [(String::from("a.c"), 0)]);
+    }
+
+    #[tokio::test]
+    async fn one_location_from_nested_tags_files() {
+        let mut ws = Workspace::new(
+            "nested",
+            &[("sub/a.c", "int read(void) {}\n")],
+            &["read\tsub/a.c\t1;\"\tf"],
+        );
+        let entry = crate::tags::parse_line("read\ta.c\t1;\"\tf").unwrap();
+        let tags = crate::tags::TagsFile::new(ws.dir.join("sub/tags"), vec![entry]);
+        ws.tags.push(std::sync::Arc::new(tags));
+
+        assert_eq!(
+            ws.lookup("read", None).await,
+            [(String::from("sub/a.c"), 0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn implementations_of_trait_method() {
+        let lib = "trait Shape {\n    fn area(&self) -> f64;\n}\n\n\
+                   impl Shape for Square {\n    fn area(&self) -> f64 { 1.0 }\n}\n\n\
+                   impl Shape for Circle {\n    fn area(&self) -> f64 { 3.0 }\n}\n";
+        let cpp = "class Base {\n    virtual void draw();\n};\n\n\
+                   class Derived : public Base {\n    void draw();\n};\n";
+        let ws = Workspace::new(
+            "implementation",
+            &[("lib.rs", lib), ("shape.cpp", cpp)],
+            &[
+                "Base\tshape.cpp\t1;\"\tc",
+                "Derived\tshape.cpp\t5;\"\tc\tinherits:Base",
+                "area\tlib.rs\t2;\"\tP\tinterface:Shape",
+                "area\tlib.rs\t6;\"\tP\timplementation:Square",
+                "area\tlib.rs\t10;\"\tP\timplementation:Circle",
+                "draw\tshape.cpp\t2;\"\tf\tclass:Base",
+                "draw\tshape.cpp\t6;\"\tf\tclass:Derived",
+            ],
+        );
+        let backend = ws.backend(Default::default());
+
+        let ret = backend.implementations("area", None).await.unwrap();
+        assert_eq!(
+            ws.describe(ret),
+            [(String::from("lib.rs"), 5), (String::from("lib.rs"), 9)]
+        );
+        let ret = backend.implementations("draw", None).await.unwrap();
+        assert_eq!(ws.describe(ret), [(String::from("shape.cpp"), 5)]);
+    }
+
+    #[tokio::test]
+    async fn skip_excluded_files_in_reference_scan() {
+        let ws = Workspace::new(
+            "exclude",
+            &[("a.c", "int count;\n"), ("vendor/b.c", "count++;\n")],
+            &["count\ta.c\t1;\"\tv"],
+        );
+        let exclude = crate::walk::build_exclude(&[String::from("vendor/**")]).unwrap();
+
+        let mut backend = ws.backend(Default::default());
+        let ret = ws.describe(backend.references("count", true).await.unwrap());
+        assert_eq!(ret.len(), 2);
+
+        backend.exclude = &exclude;
+        let ret = ws.describe(backend.references("count", true).await.unwrap());
+        assert_eq!(ret, [(String::from("a.c"), 0)]);
+    }
+
+    #[tokio::test]
+    async fn prefer_definitions_in_scope() {
+        let ws = Workspace::new(
+            "scoped",
+            &[(
+                "a.cpp",
+                "int bar() {}\n\nstruct Foo {\n    int bar();\n};\n",
+            )],
+            &["bar\ta.cpp\t1;\"\tf", "bar\ta.cpp\t4;\"\tf\tstruct:Foo"],
+        );
+        let backend = ws.backend(Default::default());
+
+        let ret = backend.lookup_scoped("bar", "Foo", None).await.unwrap();
+        assert_eq!(ws.describe(ret), [(String::from("a.cpp"), 3)]);
+        let ret = backend.lookup("bar", None).await.unwrap();
+        assert_eq!(
+            ws.describe(ret),
+            [(String::from("a.cpp"), 0), (String::from("a.cpp"), 3)]
+        );
+        let ret = backend.lookup_scoped("bar", "Baz", None).await.unwrap();
+        assert_eq!(
+            ws.describe(ret),
+            [(String::from("a.cpp"), 0), (String::from("a.cpp"), 3)]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use tower_lsp::lsp_types::*;
+
+/// Backend that shells out to GNU Global.
+pub struct GlobalBackend<'a> {
+    /// Workspace folder that contains `GTAGS`.
+    pub cwd: &'a Url,
+
+    /// Do not find precise position, just point to begin of line.
+    pub low_precision: bool,
+
+    /// How to compare symbol with tag names.
+    pub match_mode: crate::tags::MatchMode,
+
+    /// Cache of file contents for current request.
+    pub cache: &'a crate::cache::FileCache,
+}
+
+#[tower_lsp::async_trait]
+impl<'a> super::Backend for GlobalBackend<'a> {
+    async fn lookup(
+        &self,
+        name: &str,
+        _: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        let pattern = format!("{}.*", name);
+        let args = match self.match_mode {
+            crate::tags::MatchMode::Exact => vec!["-d", "-x", name],
+            crate::tags::MatchMode::Prefix => vec!["-d", "-x", &pattern],
+            crate::tags::MatchMode::Ci => vec!["-d", "-i", "-x", name],
+        };
+        return self.search(&args).await;
+    }
+
+    /// GNU Global does not distinguish declarations from definitions.
+    async fn declarations(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        return self.lookup(name, lang).await;
+    }
+
+    /// GNU Global does not record class hierarchy, so all definitions are
+    /// returned.
+    async fn implementations(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        return self.lookup(name, lang).await;
+    }
+
+    async fn references(
+        &self,
+        name: &str,
+        include_declaration: bool,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        // Location list for return.
+        let mut loc_list = Vec::new();
+
+        if include_declaration {
+            let mut ret = self.lookup(name, None).await?;
+            loc_list.append(&mut ret);
+        }
+
+        let args = vec!["-r", "-s", "-x", name];
+        let mut ret = self.search(&args).await?;
+        loc_list.append(&mut ret);
+
+        return Ok(loc_list);
+    }
+
+    async fn symbols(
+        &self,
+        pattern: &str,
+    ) -> Result<Vec<SymbolInformation>, tower_lsp::jsonrpc::Error> {
+        // Symbol list for return.
+        let mut symbol_list = Vec::new();
+
+        let args = vec!["-d", "-i", "-x", pattern];
+        let lines = crate::method::execute_and_split_lines("global", self.cwd, &args).await?;
+
+        for line in lines {
+            let (name, line_no, file_path, rest) = crate::method::parse_cxref(&line)?;
+
+            let path = crate::method::join_workspace_path(self.cwd, &file_path)?;
+            let location = crate::method::find_symbol_in_line(
+                &path,
+                line_no - 1,
+                &name,
+                self.low_precision,
+                self.cache,
+            )
+            .await?;
+
+            let info = crate::method::create_symbol_information(name, location, &rest);
+            symbol_list.push(info);
+        }
+
+        return Ok(symbol_list);
+    }
+}
+
+impl<'a> GlobalBackend<'a> {
+    /// Execute `global` and convert its `cxref` output into locations.
+    ///
+    /// # Arguments
+    ///
+    /// + `args`: Arguments list.
+    async fn search(&self, args: &Vec<&str>) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        // Location list for return.
+        let mut loc_list = Vec::new();
+
+        let lines = crate::method::execute_and_split_lines("global", self.cwd, args).await?;
+
+        for line in lines {
+            let (name, line_no, file_path, _) = crate::method::parse_cxref(&line)?;
+
+            let path = crate::method::join_workspace_path(self.cwd, &file_path)?;
+            let location = crate::method::find_symbol_in_line(
+                &path,
+                line_no - 1,
+                &name,
+                self.low_precision,
+                self.cache,
+            )
+            .await?;
+
+            loc_list.push(location);
+        }
+
+        return Ok(loc_list);
+    }
+}
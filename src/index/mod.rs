@@ -0,0 +1,173 @@
+pub mod ctags;
+pub mod global;
+
+use tower_lsp::lsp_types::*;
+
+/// Kind of tags database.
+#[derive(Debug, clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Exuberant/Universal ctags `tags` file.
+    Ctags,
+
+    /// GNU Global `GTAGS` database.
+    Global,
+}
+
+/// A tags database that can answer symbol queries.
+#[tower_lsp::async_trait]
+pub trait Backend {
+    /// Find definitions of symbol.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `lang`: Language of the requesting file, as named by ctags.
+    async fn lookup(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error>;
+
+    /// Find definitions of symbol written with scope qualifier, e.g. `bar` of
+    /// `Foo::bar`.
+    ///
+    /// Same as `lookup` unless the backend knows scopes of symbols.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `qualifier`: Scope qualifier, e.g. `Foo` or `ns::Foo`.
+    /// + `lang`: Language of the requesting file, as named by ctags.
+    async fn lookup_scoped(
+        &self,
+        name: &str,
+        qualifier: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error> {
+        let _ = qualifier;
+        return self.lookup(name, lang).await;
+    }
+
+    /// Find declarations of symbol.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `lang`: Language of the requesting file, as named by ctags.
+    async fn declarations(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error>;
+
+    /// Find implementations of symbol, e.g. methods that implement an
+    /// interface or override a virtual method.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `lang`: Language of the requesting file, as named by ctags.
+    async fn implementations(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error>;
+
+    /// Find references of symbol.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `include_declaration`: Also return the definitions of the symbol.
+    async fn references(
+        &self,
+        name: &str,
+        include_declaration: bool,
+    ) -> Result<Vec<Location>, tower_lsp::jsonrpc::Error>;
+
+    /// Find symbols whose name matches pattern, for `workspace/symbol`.
+    ///
+    /// Loaded tags files are searched by `workspace/symbol` itself, so only
+    /// backends with their own database return anything.
+    ///
+    /// # Arguments
+    ///
+    /// + `pattern`: Regular expression of symbol name, matched case-insensitively.
+    async fn symbols(
+        &self,
+        pattern: &str,
+    ) -> Result<Vec<SymbolInformation>, tower_lsp::jsonrpc::Error> {
+        let _ = pattern;
+        return Ok(Vec::new());
+    }
+}
+
+/// Detect which backend serves the workspace folder.
+///
+/// `--backend` is used if specified. Otherwise GNU Global is chosen when the
+/// folder root contains `GTAGS` but no `tags` file.
+///
+/// # Arguments
+///
+/// + `config`: Configuration.
+/// + `folder`: Workspace folder.
+pub fn detect(config: &crate::TagsLspConfig, folder: &WorkspaceFolder) -> BackendKind {
+    if let Some(v) = config.backend {
+        return v;
+    }
+
+    if !config.tags.is_empty() || config.single_file.is_some() {
+        return BackendKind::Ctags;
+    }
+
+    let dir = match folder.uri.to_file_path() {
+        Ok(v) => v,
+        Err(_) => return BackendKind::Ctags,
+    };
+
+    if dir.join("GTAGS").exists() && crate::tags::discover(&dir, &config.tags_filename).is_none() {
+        return BackendKind::Global;
+    }
+
+    return BackendKind::Ctags;
+}
+
+/// Get backend that serves the workspace folder.
+///
+/// # Arguments
+///
+/// + `rt`: Snapshot of Runtime.
+/// + `folder`: Workspace folder.
+/// + `cache`: Cache of file contents for current request.
+pub fn select<'a>(
+    rt: &'a crate::Snapshot,
+    folder: &'a WorkspaceFolder,
+    cache: &'a crate::cache::FileCache,
+) -> Box<dyn Backend + Send + Sync + 'a> {
+    match detect(&rt.config, folder) {
+        BackendKind::Ctags => Box::new(ctags::CtagsBackend {
+            tags: &rt.tags,
+            cwd: &folder.uri,
+            low_precision: rt.config.low_precision,
+            address_resolution: rt.config.address_resolution,
+            no_ignore: rt.config.no_ignore,
+            exclude: &rt.exclude,
+            match_mode: rt.config.match_mode,
+            reference_timeout: match rt.config.reference_timeout_ms {
+                0 => None,
+                v => Some(std::time::Duration::from_millis(v)),
+            },
+            max_file_size: match rt.config.max_file_size {
+                0 => None,
+                v => Some(v),
+            },
+            cache,
+        }),
+        BackendKind::Global => Box::new(global::GlobalBackend {
+            cwd: &folder.uri,
+            low_precision: rt.config.low_precision,
+            match_mode: rt.config.match_mode,
+            cache,
+        }),
+    }
+}
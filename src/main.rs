@@ -1,4 +1,20 @@
+#![allow(clippy::needless_return)]
+
+mod cache;
+mod check;
+mod config;
+mod ctags;
+mod error;
+mod grep;
+mod idle;
+mod index;
 mod method;
+mod prefetch;
+mod tags;
+#[cfg(test)]
+mod testing;
+mod walk;
+mod watch;
 
 use std::collections::HashMap;
 use tower_lsp::{lsp_types::*, ClientSocket, LspService};
@@ -25,6 +41,82 @@ impl LspErrorCode {
     }
 }
 
+#[derive(Debug, clap::ValueEnum, Default, Clone, Copy, PartialEq, Eq)]
+enum LogLevel {
+    Off,
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub const fn level_filter(&self) -> tracing::metadata::LevelFilter {
+        match self {
+            LogLevel::Off => tracing::metadata::LevelFilter::OFF,
+            LogLevel::Trace => tracing::metadata::LevelFilter::TRACE,
+            LogLevel::Debug => tracing::metadata::LevelFilter::DEBUG,
+            LogLevel::Info => tracing::metadata::LevelFilter::INFO,
+            LogLevel::Warn => tracing::metadata::LevelFilter::WARN,
+            LogLevel::Error => tracing::metadata::LevelFilter::ERROR,
+        }
+    }
+}
+
+#[derive(Debug, clap::ValueEnum, Default, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, clap::ValueEnum, Default, Clone, Copy, PartialEq, Eq)]
+enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+/// Communication channel with client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Stdio,
+    Socket(std::net::SocketAddr),
+}
+
+/// Choose communication channel from command line arguments.
+///
+/// `--port` selects socket, otherwise stdio is used even if `--stdio` is not
+/// given.
+///
+/// # Arguments
+///
+/// + `config`: Command line arguments.
+fn select_transport(config: &TagsLspConfig) -> Transport {
+    let transport = match config.port {
+        Some(port) => {
+            let host = config
+                .host
+                .unwrap_or(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+            Transport::Socket(std::net::SocketAddr::new(host, port))
+        }
+        None => Transport::Stdio,
+    };
+
+    match (transport, config.stdio) {
+        (Transport::Stdio, false) => {
+            tracing::info!("Transport: stdio, neither `--stdio` nor `--port` is given")
+        }
+        (Transport::Stdio, true) => tracing::info!("Transport: stdio"),
+        (Transport::Socket(v), _) => tracing::info!("Transport: socket on {}", v),
+    }
+
+    return transport;
+}
+
 #[derive(Debug, clap::Parser, Default, Clone)]
 #[command(author, version, about, long_about = None)]
 struct TagsLspConfig {
@@ -39,10 +131,66 @@ struct TagsLspConfig {
         long,
         conflicts_with = "stdio",
         help = "Uses a socket as the communication channel",
-        long_help = "The LSP server start as TCP client and connect to the specified port."
+        long_help = "The LSP server listen on the specified port and serve the first client connected."
     )]
     port: Option<u16>,
 
+    #[arg(
+        long,
+        requires = "port",
+        help = "The address to listen on with `--port`",
+        long_help = "By default `127.0.0.1` is used, so only local clients can connect. Binding to
+`0.0.0.0` exposes the server to the network."
+    )]
+    host: Option<std::net::IpAddr>,
+
+    #[arg(
+        long,
+        value_name = "NUM",
+        default_value_t = 1,
+        requires = "port",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "The maximum number of clients served at the same time with `--port`",
+        long_help = "By default the first client is served and the server stops when it disconnects.
+With a larger number the server keeps accepting connections and each client
+has its own session. Loaded tags are shared, so clients should open the same
+workspace. Connections beyond the limit wait until another client leaves."
+    )]
+    max_clients: u32,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 0,
+        requires = "port",
+        help = "Close connection of `--port` if client sends nothing for this long",
+        long_help = "Frees the session of a client that went away without closing the connection.
+Editors may stay silent for long while user reads code, so choose a generous
+value. By default 0 is used, which never closes idle connections."
+    )]
+    idle_timeout_secs: u64,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["stdio", "port"],
+        help = "Validate tags file and exit",
+        long_help = "Parse the tags file, resolve a sample of entries, print a summary and exit.
+Exit status is non-zero if any problem is found. The LSP server is not started."
+    )]
+    check: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["tags", "check"],
+        help = "Serve symbols of one file only",
+        long_help = "Run ctags on FILE and answer requests with its symbols only. Workspace folders
+from client, tags files and `.tags-lsp.json` are ignored, the directory of FILE
+is used as the only workspace folder."
+    )]
+    single_file: Option<String>,
+
     #[arg(
         long,
         value_name = "DIR",
@@ -52,12 +200,50 @@ struct TagsLspConfig {
 
     #[arg(
         long,
-        value_name = "STRING",
-        help = "Set log leve",
-        long_help = "Possible values are: [OFF | TRACE | DEBUG | INFO | WARN | ERROR]. By default
-`INFO` is used. Case insensitive."
+        value_enum,
+        ignore_case = true,
+        help = "Set log level",
+        long_help = "By default `INFO` is used. Case insensitive."
     )]
-    loglevel: Option<String>,
+    loglevel: Option<LogLevel>,
+
+    #[arg(
+        long,
+        help = "Only log errors and skip the welcome banner",
+        long_help = "Log level becomes `ERROR` unless `--loglevel` is given, which wins."
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read log level from file on SIGHUP",
+        long_help = "When the server receive SIGHUP, read log level from this file and apply it
+without restart. The file contains one of the `--loglevel` values."
+    )]
+    loglevel_file: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Set log format",
+        long_help = "`text` is human readable. `json` writes one JSON object per line, which contains
+timestamp, level and fields."
+    )]
+    log_format: LogFormat,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LogRotation::Never,
+        requires = "logdir",
+        help = "Set log file rotation",
+        long_help = "With `never` logs are written to `<DIR>/tags-lsp.log`. With `hourly` or `daily`
+a new file is created for each period, named `<DIR>/tags-lsp.log.YYYY-MM-DD-HH`
+or `<DIR>/tags-lsp.log.YYYY-MM-DD` respectively."
+    )]
+    log_rotation: LogRotation,
 
     #[arg(
         long,
@@ -68,9 +254,298 @@ costly. Enable `low_precision` mode makes the matching result point to the
 begin of line, which should not affect experience very much."
     )]
     low_precision: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "The ctags program used to generate tags file",
+        long_help = "By default `ctags` is used. Overrides `ctagsBinary` of initialization options."
+    )]
+    ctags_bin: Option<String>,
+
+    #[arg(
+        long = "ctags-arg",
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Extra argument passed to ctags",
+        long_help = "Appended to the ctags command line, e.g. `--ctags-arg=--languages=C,C++`.
+Can be specified multiple times. `-f` and `-o` are ignored because the
+server controls the output path. Overrides `ctagsArgs` of initialization
+options."
+    )]
+    ctags_args: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Do not generate tags file",
+        long_help = "By default ctags is run for workspace folders that have no `tags` file.
+Overrides `autoGenerate` of initialization options."
+    )]
+    no_auto_generate: bool,
+
+    #[arg(
+        long,
+        help = "Do not report malformed lines of tags file",
+        long_help = "By default malformed lines of tags file are published as diagnostics of the
+tags file. Overrides `tagsDiagnostics` of initialization options."
+    )]
+    no_tags_diagnostics: bool,
+
+    #[arg(
+        long,
+        help = "Do not update tags when file is saved",
+        long_help = "By default ctags is run on the saved file and its entries in tags are replaced.
+The tags file on disk is not changed. Overrides `updateOnSave` of
+initialization options."
+    )]
+    no_update_on_save: bool,
+
+    #[arg(
+        long,
+        help = "Read source files that most tags point to after loading tags",
+        long_help = "Warms the page cache of OS in background, so that the first jumps do not wait
+for disk. At most `--prefetch-budget` bytes are read."
+    )]
+    prefetch: bool,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 64 * 1024 * 1024,
+        help = "The maximum number of bytes read by `--prefetch`",
+        long_help = "By default 64 MiB is used. Files larger than what is left of the budget are
+skipped."
+    )]
+    prefetch_budget: u64,
+
+    #[arg(
+        long,
+        help = "Do not resolve symlinks in returned locations",
+        long_help = "By default file paths of locations are canonicalized, so they match the file
+opened by editor when workspace contains symlinks. Paths that cannot be
+canonicalized are returned as is."
+    )]
+    no_canonicalize: bool,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Compare file paths case-insensitively",
+        long_help = "Editors may open `Foo.c` as `foo.c` on case-insensitive file systems, which then
+does not match the path recorded in tags. Enabled by default on macOS and
+Windows, use `--ignore-case-fs=false` to disable."
+    )]
+    ignore_case_fs: Option<bool>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Use the specified tags file, can be repeated",
+        long_help = "Use the specified tags file instead of discovering `tags` in workspace folders.
+Can be repeated to load multiple tags files. Overrides `tagsFiles` of
+initialization options."
+    )]
+    tags: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = tags::TAGS_FILENAME,
+        help = "File name of tags to discover, can be repeated",
+        long_help = "Tags files of these names, and gzip compressed ones with `.gz` appended, are
+searched in workspace folders and their ancestors. Emacs `TAGS` is always
+searched. Tags generated by ctags are written to the first name. By default
+`tags` is used."
+    )]
+    tags_filename: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Do not respect ignore files",
+        long_help = "By default files excluded by `.gitignore`, `.ignore` and global git excludes are
+not scanned for references nor indexed by ctags. Use this option to scan
+everything."
+    )]
+    no_ignore: bool,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Do not scan paths matching GLOB for references",
+        long_help = "Paths are matched relative to workspace folder, e.g. `gen/**` or `**/*_test.c`.
+Applies on top of ignore files. Can be specified multiple times."
+    )]
+    exclude: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Search source files for definitions missing from tags",
+        long_help = "When no tag is found for a symbol, scan source files in workspace folder for
+lines that look like its definition, e.g. `fn NAME` or `def NAME`. Useful
+when tags are out of date, at the cost of a slower lookup."
+    )]
+    grep_fallback: bool,
+
+    #[arg(
+        long,
+        value_name = "NUM",
+        help = "The maximum number of results for one query",
+        long_help = "Bound the number of locations returned by definition, references and workspace
+symbol queries. Raise it for completeness, lower it for responsiveness. By
+default 100 is used."
+    )]
+    max_results: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 10000,
+        help = "Stop scanning for references after this long",
+        long_help = "Reference search of ctags scans every source file in workspace folder. The
+scan stops after this many milliseconds and returns the references found so
+far. Use 0 for no limit."
+    )]
+    reference_timeout_ms: u64,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 4 * 1024 * 1024,
+        help = "Skip files larger than this when scanning for references",
+        long_help = "Reference search and document highlight skip larger files, such as generated
+sources committed by accident. By default 4 MiB is used. Use 0 for no limit."
+    )]
+    max_file_size: u64,
+
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 300,
+        help = "Wait this long after tags file changes before reloading it",
+        long_help = "Generators such as `ctags -R` write tags file in pieces. Changes within this
+many milliseconds of each other are collapsed into one reload."
+    )]
+    watch_debounce_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        ignore_case = true,
+        help = "How to compare the symbol under cursor with tag names",
+        long_help = "`exact` finds tags with the same name, `prefix` finds tags whose name starts
+with the symbol, and `ci` compares names case-insensitively. Workspace symbol
+queries match anywhere in the name, or only at the start in `prefix` mode."
+    )]
+    match_mode: tags::MatchMode,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        ignore_case = true,
+        help = "How to resolve addresses of tags into positions",
+        long_help = "`verify` reads source files to confirm patterns and find symbols in line, so
+results survive edits made after tags are generated. `trust` uses the line
+numbers recorded in tags file without reading source files, which is fast but
+may point to wrong lines after edits. Results point to begin of line in
+`trust` mode."
+    )]
+    address_resolution: tags::AddressResolution,
+
+    #[arg(
+        long,
+        value_name = "LABEL",
+        value_parser = parse_encoding,
+        help = "Encoding of source files",
+        long_help = "Source files are decoded with this encoding when resolving tag addresses and
+showing hover text, e.g. `latin1` or `shift_jis`. Any WHATWG encoding label is
+accepted. By default UTF-8 is used. Bytes that cannot be decoded are replaced
+instead of failing the request. A byte order mark overrides this option."
+    )]
+    file_encoding: Option<&'static encoding_rs::Encoding>,
+
+    #[arg(
+        long,
+        value_name = "NUM",
+        help = "Use binary search for sorted tags files with more entries than this",
+        long_help = "Sorted tags files (`!_TAG_FILE_SORTED 1`, the ctags default) with more entries
+are searched by binary search instead of a hash index, which uses much less
+memory for huge files. By default 1000000 is used. Use 0 to always use hash
+index."
+    )]
+    sorted_index_threshold: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Use the specified tags database",
+        long_help = "By default GNU Global is used for workspace folders that contain `GTAGS` but no
+`tags` file, and ctags is used otherwise."
+    )]
+    backend: Option<index::BackendKind>,
+}
+
+/// Parse `--file-encoding`.
+///
+/// # Arguments
+///
+/// + `label`: WHATWG encoding label.
+fn parse_encoding(label: &str) -> Result<&'static encoding_rs::Encoding, String> {
+    return encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("unknown encoding `{}`", label));
+}
+
+impl TagsLspConfig {
+    /// The log level to start with.
+    pub fn loglevel(&self) -> LogLevel {
+        return match (self.loglevel, self.quiet) {
+            (Some(v), _) => v,
+            (None, true) => LogLevel::Error,
+            (None, false) => LogLevel::default(),
+        };
+    }
+
+    /// Encoding of source files.
+    pub fn file_encoding(&self) -> &'static encoding_rs::Encoding {
+        return self.file_encoding.unwrap_or(encoding_rs::UTF_8);
+    }
+
+    /// The ctags program to execute.
+    pub fn ctags_bin(&self) -> &str {
+        return self.ctags_bin.as_deref().unwrap_or("ctags");
+    }
+
+    /// The maximum number of results for one query.
+    pub fn max_results(&self) -> usize {
+        return self.max_results.unwrap_or(100);
+    }
+
+    /// File name of tags generated by ctags.
+    pub fn tags_filename(&self) -> &str {
+        return self
+            .tags_filename
+            .first()
+            .map(|v| v.as_str())
+            .unwrap_or(tags::TAGS_FILENAME);
+    }
+
+    /// Sorted tags files with more entries are searched by binary search.
+    pub fn sorted_index_threshold(&self) -> usize {
+        return self.sorted_index_threshold.unwrap_or(1_000_000);
+    }
+
+    /// Whether file paths are compared case-insensitively.
+    pub fn ignore_case_fs(&self) -> bool {
+        return self
+            .ignore_case_fs
+            .unwrap_or(cfg!(any(target_os = "macos", target_os = "windows")));
+    }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default)]
 struct Runtime {
     /// Program name.
     prog_name: String,
@@ -78,20 +553,231 @@ struct Runtime {
     /// Program version.
     prog_version: String,
 
-    /// Configuration.
+    /// Configuration, with initialization options and configuration file
+    /// applied.
     config: TagsLspConfig,
 
+    /// Configuration from command line arguments only.
+    cli_config: TagsLspConfig,
+
+    /// Configuration of each workspace folder, with `.tags-lsp.json` of the
+    /// folder applied instead of the first folder.
+    folder_configs: HashMap<Url, TagsLspConfig>,
+
+    /// `initializationOptions` of client, kept for reloading configuration.
+    init_options: Option<serde_json::Value>,
+
+    /// Handle to change log level.
+    log_handle: Option<LogReloadHandle>,
+
     /// Workspace folder list.
     workspace_folders: Vec<WorkspaceFolder>,
 
     /// Opened files.
     open_files: HashMap<Url, String>,
+
+    /// Language ids sent by client for opened files.
+    language_ids: HashMap<Url, String>,
+
+    /// Loaded tags files, read without holding the Runtime lock.
+    tags: tags::SharedTags,
+
+    /// Background tasks to stop on shutdown.
+    tasks: Vec<tokio::task::AbortHandle>,
+
+    /// Watchers of loaded tags files, shared by sessions.
+    watchers: watch::Watchers,
+
+    /// Saved files waiting for tags update.
+    pending_saves: std::collections::HashSet<std::path::PathBuf>,
+
+    /// Ctags kinds to show in `documentSymbol`, all kinds if `None`.
+    document_symbol_kinds: Option<std::collections::HashSet<String>>,
+
+    /// Trace value requested by client.
+    trace: TraceValue,
+
+    /// Positions are UTF-16 offsets as negotiated, otherwise UTF-8 offsets.
+    position_utf16: bool,
+
+    /// Show relative path and line number in `workspace/symbol` results.
+    symbol_detail_path: bool,
+
+    /// Hide or rename anonymous types in symbol results.
+    hide_anonymous: bool,
+
+    /// Order of `workspace/symbol` results.
+    symbol_sort: method::workspace_symbol::SymbolSort,
+
+    /// Compiled `--exclude` patterns.
+    exclude: globset::GlobSet,
+
+    /// Client supports server initiated work done progress.
+    work_done_progress: bool,
+
+    /// Tags files are being regenerated by command.
+    regenerating: bool,
+}
+
+impl Runtime {
+    /// Get configuration of the workspace folder that contains `uri`.
+    ///
+    /// The nearest folder wins, and the global configuration is used if no
+    /// folder contains it.
+    ///
+    /// # Arguments
+    ///
+    /// + `uri`: Workspace folder or file in it.
+    pub fn folder_config(&self, uri: &Url) -> &TagsLspConfig {
+        let path = std::path::Path::new(uri.path());
+        let ignore_case = self.config.ignore_case_fs();
+        return self
+            .workspace_folders
+            .iter()
+            .filter(|v| {
+                tags::path_starts_with(path, std::path::Path::new(v.uri.path()), ignore_case)
+            })
+            .max_by_key(|v| v.uri.path().len())
+            .and_then(|v| self.folder_configs.get(&v.uri))
+            .unwrap_or(&self.config);
+    }
+
+    /// Get configuration of requests that are not about a document.
+    ///
+    /// `.tags-lsp.json` of the first workspace folder applies, like log level
+    /// does, and the global configuration is used if there is no folder.
+    pub fn default_config(&self) -> &TagsLspConfig {
+        return self
+            .workspace_folders
+            .first()
+            .and_then(|v| self.folder_configs.get(&v.uri))
+            .unwrap_or(&self.config);
+    }
+
+    /// Track background task so it is stopped on shutdown.
+    ///
+    /// # Arguments
+    ///
+    /// + `task`: Handle of the task.
+    pub fn track_task(&mut self, task: tokio::task::AbortHandle) {
+        self.tasks.retain(|v| !v.is_finished());
+        self.tasks.push(task);
+    }
+
+    /// Stop all background tasks.
+    ///
+    /// # Returns
+    ///
+    /// Number of tasks stopped.
+    pub fn stop_tasks(&mut self) -> usize {
+        let tasks = std::mem::take(&mut self.tasks);
+        for task in &tasks {
+            task.abort();
+        }
+
+        return tasks.len();
+    }
+
+    /// Take the state needed by a read-only request.
+    ///
+    /// # Arguments
+    ///
+    /// + `uri`: Document of the request, its content is taken if opened and
+    ///   configuration of its folder is used. See [`Runtime::default_config`]
+    ///   if there is no document.
+    pub fn snapshot(&self, uri: Option<&Url>) -> Snapshot {
+        let config = match uri {
+            Some(v) => self.folder_config(v),
+            None => self.default_config(),
+        };
+
+        return Snapshot {
+            config: config.clone(),
+            workspace_folders: self.workspace_folders.clone(),
+            tags: self.tags.load(),
+            exclude: self.exclude.clone(),
+            position_utf16: self.position_utf16,
+            document_symbol_kinds: self.document_symbol_kinds.clone(),
+            symbol_detail_path: self.symbol_detail_path,
+            hide_anonymous: self.hide_anonymous,
+            symbol_sort: self.symbol_sort,
+            document: uri.and_then(|v| self.open_files.get(v).cloned()),
+            language_id: uri.and_then(|v| self.language_ids.get(v).cloned()),
+        };
+    }
+}
+
+/// State of Runtime for a read-only request.
+///
+/// Requests work on a snapshot instead of holding the Runtime lock, so that
+/// they do not wait for each other or for reloads.
+#[derive(Debug)]
+struct Snapshot {
+    /// Same as `Runtime::config`.
+    config: TagsLspConfig,
+
+    /// Same as `Runtime::workspace_folders`.
+    workspace_folders: Vec<WorkspaceFolder>,
+
+    /// Tags files loaded when the snapshot is taken.
+    tags: std::sync::Arc<Vec<std::sync::Arc<tags::TagsFile>>>,
+
+    /// Same as `Runtime::exclude`.
+    exclude: globset::GlobSet,
+
+    /// Same as `Runtime::position_utf16`.
+    position_utf16: bool,
+
+    /// Same as `Runtime::document_symbol_kinds`.
+    document_symbol_kinds: Option<std::collections::HashSet<String>>,
+
+    /// Same as `Runtime::symbol_detail_path`.
+    symbol_detail_path: bool,
+
+    /// Same as `Runtime::hide_anonymous`.
+    hide_anonymous: bool,
+
+    /// Same as `Runtime::symbol_sort`.
+    symbol_sort: method::workspace_symbol::SymbolSort,
+
+    /// Content of the requested document if it is opened.
+    document: Option<String>,
+
+    /// Language id sent by client for the requested document.
+    language_id: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct TagsLspBackend {
     client: tower_lsp::Client,
-    rt: tokio::sync::Mutex<Runtime>,
+    rt: std::sync::Arc<tokio::sync::Mutex<Runtime>>,
+
+    /// Set once `initialized` is received, tags may still be loading.
+    initialized: std::sync::atomic::AtomicBool,
+
+    /// Whether requests are traced, shared with `TraceService`.
+    trace_verbose: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl TagsLspBackend {
+    /// Reject request that arrives before `initialized`.
+    fn check_initialized(&self) -> tower_lsp::jsonrpc::Result<()> {
+        if self.initialized.load(std::sync::atomic::Ordering::Acquire) {
+            return Ok(());
+        }
+
+        return Err(crate::error::TagsLspError::NotInitialized.into());
+    }
+
+    /// Take the state needed by a read-only request, see [`Runtime::snapshot`].
+    async fn snapshot(&self, uri: Option<&Url>) -> Snapshot {
+        return self.rt.lock().await.snapshot(uri);
+    }
+
+    /// Handle `$/setTrace` notification.
+    async fn set_trace(&self, params: SetTraceParams) {
+        return method::set_trace::set_trace(self, params).await;
+    }
 }
 
 #[tower_lsp::async_trait]
@@ -100,152 +786,597 @@ impl tower_lsp::LanguageServer for TagsLspBackend {
         &self,
         params: InitializeParams,
     ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
-        return method::initialize::do_initialize(self, params).await;
+        return method::timing::timed("initialize", async {
+            return method::initialize::do_initialize(self, params).await;
+        })
+        .await;
     }
 
     async fn initialized(&self, params: InitializedParams) {
-        return method::initialized::do_initialized(self, params).await;
+        return method::timing::timed("initialized", async {
+            return method::initialized::do_initialized(self, params).await;
+        })
+        .await;
     }
 
     async fn shutdown(&self) -> tower_lsp::jsonrpc::Result<()> {
-        Ok(())
+        return method::timing::timed("shutdown", async {
+            let mut rt = self.rt.lock().await;
+
+            let count = rt.stop_tasks();
+            tracing::info!("Shutdown, {} background tasks stopped", count);
+            return Ok(());
+        })
+        .await;
     }
 
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
     ) -> tower_lsp::jsonrpc::Result<Option<GotoDefinitionResponse>> {
-        return method::definition::goto_definition(self, params).await;
+        return method::timing::timed("goto_definition", async {
+            self.check_initialized()?;
+            return method::definition::goto_definition(self, params).await;
+        })
+        .await;
+    }
+
+    async fn goto_declaration(
+        &self,
+        params: request::GotoDeclarationParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<request::GotoDeclarationResponse>> {
+        return method::timing::timed("goto_declaration", async {
+            self.check_initialized()?;
+            return method::declaration::goto_declaration(self, params).await;
+        })
+        .await;
     }
 
     async fn references(
         &self,
         params: ReferenceParams,
     ) -> tower_lsp::jsonrpc::Result<Option<Vec<Location>>> {
-        return method::references::do_references(self, params).await;
+        return method::timing::timed("references", async {
+            self.check_initialized()?;
+            return method::references::find_references(self, params).await;
+        })
+        .await;
     }
 
     async fn goto_implementation(
         &self,
         params: request::GotoImplementationParams,
     ) -> tower_lsp::jsonrpc::Result<Option<request::GotoImplementationResponse>> {
-        return method::implementation::goto_implementation(self, params).await;
+        return method::timing::timed("goto_implementation", async {
+            self.check_initialized()?;
+            return method::implementation::goto_implementation(self, params).await;
+        })
+        .await;
     }
 
     async fn symbol(
         &self,
         params: WorkspaceSymbolParams,
     ) -> tower_lsp::jsonrpc::Result<Option<Vec<SymbolInformation>>> {
-        return method::symbol::do_symbol(self, params).await;
+        return method::timing::timed("symbol", async {
+            self.check_initialized()?;
+            return method::workspace_symbol::symbol(self, params).await;
+        })
+        .await;
     }
 
     async fn document_symbol(
         &self,
         params: DocumentSymbolParams,
     ) -> tower_lsp::jsonrpc::Result<Option<DocumentSymbolResponse>> {
-        return method::document_symbol::do_document_symbol(self, params).await;
+        return method::timing::timed("document_symbol", async {
+            self.check_initialized()?;
+            return method::document_symbol::document_symbol(self, params).await;
+        })
+        .await;
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        return method::timing::timed("document_highlight", async {
+            self.check_initialized()?;
+            return method::document_highlight::document_highlight(self, params).await;
+        })
+        .await;
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<SelectionRange>>> {
+        return method::timing::timed("selection_range", async {
+            self.check_initialized()?;
+            return method::selection_range::selection_range(self, params).await;
+        })
+        .await;
+    }
+
+    async fn signature_help(
+        &self,
+        params: SignatureHelpParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<SignatureHelp>> {
+        return method::timing::timed("signature_help", async {
+            self.check_initialized()?;
+            return method::signature_help::signature_help(self, params).await;
+        })
+        .await;
     }
 
     async fn goto_type_definition(
         &self,
         params: request::GotoTypeDefinitionParams,
     ) -> tower_lsp::jsonrpc::Result<Option<request::GotoTypeDefinitionResponse>> {
-        return method::type_definition::goto_type_definition(self, params).await;
+        return method::timing::timed("goto_type_definition", async {
+            self.check_initialized()?;
+            return method::type_definition::goto_type_definition(self, params).await;
+        })
+        .await;
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyItem>>> {
+        return method::timing::timed("prepare_call_hierarchy", async {
+            self.check_initialized()?;
+            return method::call_hierarchy::prepare_call_hierarchy(self, params).await;
+        })
+        .await;
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        return method::timing::timed("incoming_calls", async {
+            self.check_initialized()?;
+            return method::call_hierarchy::incoming_calls(self, params).await;
+        })
+        .await;
+    }
+
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<DocumentLink>>> {
+        return method::timing::timed("document_link", async {
+            self.check_initialized()?;
+            return method::document_link::document_link(self, params).await;
+        })
+        .await;
+    }
+
+    async fn code_lens(
+        &self,
+        params: CodeLensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CodeLens>>> {
+        return method::timing::timed("code_lens", async {
+            self.check_initialized()?;
+            return method::code_lens::code_lens(self, params).await;
+        })
+        .await;
+    }
+
+    async fn code_lens_resolve(&self, params: CodeLens) -> tower_lsp::jsonrpc::Result<CodeLens> {
+        return method::timing::timed("code_lens_resolve", async {
+            self.check_initialized()?;
+            return method::code_lens::code_lens_resolve(self, params).await;
+        })
+        .await;
+    }
+
+    async fn hover(&self, params: HoverParams) -> tower_lsp::jsonrpc::Result<Option<Hover>> {
+        return method::timing::timed("hover", async {
+            self.check_initialized()?;
+            return method::hover::hover(self, params).await;
+        })
+        .await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        return method::timing::timed("did_change_workspace_folders", async {
+            return method::workspace::did_change_workspace_folders(self, params).await;
+        })
+        .await;
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+        return method::timing::timed("execute_command", async {
+            self.check_initialized()?;
+            return method::execute_command::execute_command(self, params).await;
+        })
+        .await;
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        return method::did_open::did_open(self, params).await;
+        return method::timing::timed("did_open", async {
+            return method::did_open::did_open(self, params).await;
+        })
+        .await;
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        return method::did_close::did_close(self, params).await;
+        return method::timing::timed("did_close", async {
+            return method::did_close::did_close(self, params).await;
+        })
+        .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        return method::did_change::did_change(self, params).await;
+        return method::timing::timed("did_change", async {
+            return method::did_change::did_change(self, params).await;
+        })
+        .await;
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        return method::timing::timed("did_save", async {
+            // Notifications are dropped silently.
+            if self.check_initialized().is_err() {
+                return;
+            }
+            return method::did_save::did_save(self, params).await;
+        })
+        .await;
     }
 
     async fn completion(
         &self,
         params: CompletionParams,
     ) -> tower_lsp::jsonrpc::Result<Option<CompletionResponse>> {
-        return method::completion::do_completion(self, params).await;
+        return method::timing::timed("completion", async {
+            self.check_initialized()?;
+            return method::completion::do_completion(self, params).await;
+        })
+        .await;
     }
 }
 
-fn setup_command_line_arguments(prog_name: &str) -> TagsLspConfig {
+/// Handle to change log level at runtime.
+type LogReloadHandle = tracing_subscriber::reload::Handle<
+    tracing::metadata::LevelFilter,
+    tracing_subscriber::Registry,
+>;
+
+fn setup_command_line_arguments(prog_name: &str) -> (TagsLspConfig, LogReloadHandle) {
     use clap::Parser;
+    use tracing_subscriber::fmt::writer::BoxMakeWriter;
+    use tracing_subscriber::prelude::*;
     let args = TagsLspConfig::parse();
 
     // Get log level.
-    let loglevel = match &args.loglevel {
-        Some(v) => v.clone(),
-        None => String::from("INFO"),
-    };
-
-    // Parse log level.
-    let loglevel = match loglevel.to_lowercase().as_str() {
-        "off" => tracing::metadata::LevelFilter::OFF,
-        "trace" => tracing::metadata::LevelFilter::TRACE,
-        "debug" => tracing::metadata::LevelFilter::DEBUG,
-        "info" => tracing::metadata::LevelFilter::INFO,
-        "warn" => tracing::metadata::LevelFilter::WARN,
-        "error" => tracing::metadata::LevelFilter::ERROR,
-        unmatched => panic!(
-            "Parser command line argument failed: unknown option value `{}`",
-            unmatched
-        ),
-    };
+    let loglevel = args.loglevel().level_filter();
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(loglevel);
 
     // Setup logging system.
-    match &args.logdir {
+    let (writer, ansi) = match &args.logdir {
         Some(path) => {
             let logfile = format!("{}.log", prog_name);
-            let file_appender = tracing_appender::rolling::never(path, logfile);
-            tracing_subscriber::fmt()
-                .with_max_level(loglevel)
-                .with_writer(file_appender)
-                .with_ansi(false)
-                .init();
+            let file_appender = match args.log_rotation {
+                LogRotation::Never => tracing_appender::rolling::never(path, logfile),
+                LogRotation::Hourly => tracing_appender::rolling::hourly(path, logfile),
+                LogRotation::Daily => tracing_appender::rolling::daily(path, logfile),
+            };
+            (BoxMakeWriter::new(file_appender), false)
         }
+        None => (BoxMakeWriter::new(std::io::stderr), true),
+    };
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match args.log_format {
+        LogFormat::Text => tracing_subscriber::registry()
+            .with(filter)
+            .with(layer)
+            .init(),
+        LogFormat::Json => tracing_subscriber::registry()
+            .with(filter)
+            .with(layer.json())
+            .init(),
+    }
+
+    return (args, handle);
+}
+
+/// Read log level from file and apply it.
+///
+/// # Arguments
+///
+/// + `handle`: Handle to change log level.
+/// + `path`: File contains log level.
+fn reload_loglevel(handle: &LogReloadHandle, path: Option<&str>) {
+    use clap::ValueEnum;
+
+    let path = match path {
+        Some(v) => v,
         None => {
-            tracing_subscriber::fmt()
-                .with_max_level(loglevel)
-                .with_writer(std::io::stderr)
-                .init();
+            tracing::warn!("Cannot reload log level: `--loglevel-file` is not set");
+            return;
+        }
+    };
+
+    let content = match std::fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("Cannot read `{}`: {}", path, e);
+            return;
+        }
+    };
+
+    let loglevel = match LogLevel::from_str(content.trim(), true) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("Invalid log level in `{}`: {}", path, e);
+            return;
         }
+    };
+
+    match handle.modify(|filter| *filter = loglevel.level_filter()) {
+        Ok(_) => tracing::info!("Log level changed to {:?}", loglevel),
+        Err(e) => tracing::warn!("Cannot change log level: {}", e),
     }
+}
+
+/// Reload log level when receive SIGHUP.
+#[cfg(unix)]
+fn spawn_loglevel_reloader(handle: LogReloadHandle, path: Option<String>) {
+    use tokio::signal::unix::{signal, SignalKind};
 
-    return args;
+    let mut sig = match signal(SignalKind::hangup()) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("Cannot install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+
+    tokio::task::spawn(async move {
+        while sig.recv().await.is_some() {
+            reload_loglevel(&handle, path.as_deref());
+        }
+    });
 }
 
+#[cfg(not(unix))]
+fn spawn_loglevel_reloader(_: LogReloadHandle, _: Option<String>) {}
+
 fn show_welcome(prog_name: &str, prog_version: &str) {
     tracing::info!("{} - v{}", prog_name, prog_version);
     tracing::info!("PID: {}", std::process::id());
 }
 
-async fn start_lsp_using_stdio(service: LspService<TagsLspBackend>, socket: ClientSocket) {
+/// LSP service that signals when client sends `exit`.
+///
+/// The server keeps waiting for input after `exit`, so the signal is used to
+/// stop serving.
+struct ExitNotifyService<S> {
+    inner: S,
+    exit: std::sync::Arc<tokio::sync::Notify>,
+}
+
+impl<S> tower_service::Service<tower_lsp::jsonrpc::Request> for ExitNotifyService<S>
+where
+    S: tower_service::Service<tower_lsp::jsonrpc::Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: tower_lsp::jsonrpc::Request) -> Self::Future {
+        if req.method() == "exit" {
+            self.exit.notify_one();
+        }
+        return self.inner.call(req);
+    }
+}
+
+/// LSP service that logs requests at TRACE level when client asks for
+/// `verbose` trace.
+struct TraceService<S> {
+    inner: S,
+    verbose: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl<S> tower_service::Service<tower_lsp::jsonrpc::Request> for TraceService<S>
+where
+    S: tower_service::Service<tower_lsp::jsonrpc::Request>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        std::pin::Pin<Box<dyn std::future::Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: tower_lsp::jsonrpc::Request) -> Self::Future {
+        if !self.verbose.load(std::sync::atomic::Ordering::Relaxed) {
+            return Box::pin(self.inner.call(req));
+        }
+
+        // Long params such as file content are truncated.
+        const PARAMS_SUMMARY_LIMIT: usize = 256;
+        let method = req.method().to_string();
+        let mut params = req.params().map(|v| v.to_string()).unwrap_or_default();
+        if let Some((idx, _)) = params.char_indices().nth(PARAMS_SUMMARY_LIMIT) {
+            params.truncate(idx);
+            params.push_str("...");
+        }
+        tracing::trace!("Start `{}`: {}", method, params);
+
+        let start = std::time::Instant::now();
+        let fut = self.inner.call(req);
+        return Box::pin(async move {
+            let ret = fut.await;
+            tracing::trace!("Finish `{}` in {:?}", method, start.elapsed());
+            return ret;
+        });
+    }
+}
+
+/// Serve LSP until the connection is closed or client sends `exit`.
+///
+/// # Arguments
+///
+/// + `service`: LSP service.
+/// + `socket`: Client socket of service.
+/// + `input`: Input stream.
+/// + `output`: Output stream.
+/// + `trace_verbose`: Whether requests are traced.
+async fn serve_until_exit<I, O>(
+    service: LspService<TagsLspBackend>,
+    socket: ClientSocket,
+    input: I,
+    output: O,
+    trace_verbose: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) where
+    I: tokio::io::AsyncRead + Unpin,
+    O: tokio::io::AsyncWrite,
+{
+    let exit = std::sync::Arc::new(tokio::sync::Notify::new());
+    let service = ExitNotifyService {
+        inner: TraceService {
+            inner: service,
+            verbose: trace_verbose,
+        },
+        exit: exit.clone(),
+    };
+
+    tokio::select! {
+        _ = tower_lsp::Server::new(input, output, socket).serve(service) => (),
+        _ = exit.notified() => (),
+    }
+}
+
+/// Serve one client with its own session.
+///
+/// Background tasks of the session are stopped when the client leaves, even
+/// without `shutdown`. So are watchers if no other session uses them.
+///
+/// # Arguments
+///
+/// + `rt`: Runtime of the session.
+/// + `input`: Input stream.
+/// + `output`: Output stream.
+async fn serve_session<I, O>(rt: Runtime, input: I, output: O)
+where
+    I: tokio::io::AsyncRead + Unpin,
+    O: tokio::io::AsyncWrite,
+{
+    rt.watchers.join();
+    let rt = std::sync::Arc::new(tokio::sync::Mutex::new(rt));
+    let trace_verbose = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let (service, socket) = tower_lsp::LspService::build(|client| TagsLspBackend {
+        client,
+        rt: rt.clone(),
+        initialized: std::sync::atomic::AtomicBool::new(false),
+        trace_verbose: trace_verbose.clone(),
+    })
+    .custom_method("$/setTrace", TagsLspBackend::set_trace)
+    .finish();
+
+    serve_until_exit(service, socket, input, output, trace_verbose).await;
+
+    let mut rt = rt.lock().await;
+    let count = rt.stop_tasks();
+    let watchers = rt.watchers.leave();
+    tracing::debug!(
+        "Session closed, {} background tasks and {} watchers stopped",
+        count,
+        watchers
+    );
+}
+
+async fn start_lsp_using_stdio(rt: Runtime) {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    tower_lsp::Server::new(stdin, stdout, socket)
-        .serve(service)
-        .await;
+    serve_session(rt, stdin, stdout).await;
 }
 
+/// Serve clients connected to socket.
+///
+/// # Arguments
+///
+/// + `new_runtime`: Create Runtime for session of a new client.
+/// + `addr`: Address to listen on.
+/// + `max_clients`: The maximum number of clients served at the same time.
+/// + `idle_timeout`: Close connection if client sends nothing for this long,
+///   `None` for no limit.
 async fn start_lsp_using_socket(
-    service: LspService<TagsLspBackend>,
-    socket: ClientSocket,
-    port: u16,
+    new_runtime: impl Fn() -> Runtime,
+    addr: std::net::SocketAddr,
+    max_clients: u32,
+    idle_timeout: Option<std::time::Duration>,
 ) {
-    let addr = format!("127.0.0.1:{}", port);
-    let mut stream = match tokio::net::TcpStream::connect(addr).await {
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
         Ok(v) => v,
-        Err(e) => panic!("Cannot connect to port `{}`: {}", port, e.to_string()),
+        Err(e) => panic!("Cannot listen on `{}`: {}", addr, e),
     };
+    tracing::info!("Listening on {}", addr);
 
-    let (r, w) = stream.split();
-    tower_lsp::Server::new(r, w, socket).serve(service).await;
+    // The only client is served in place, so the server stops when it leaves.
+    if max_clients == 1 {
+        let (stream, peer) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => panic!("Cannot accept connection on `{}`: {}", addr, e),
+        };
+        tracing::info!("Accepted connection from {}", peer);
+
+        let (r, w) = stream.into_split();
+        let r = idle::IdleTimeout::new(r, idle_timeout);
+        serve_session(new_runtime(), r, w).await;
+        return;
+    }
+
+    let permits = std::sync::Arc::new(tokio::sync::Semaphore::new(max_clients as usize));
+    loop {
+        // Wait for a free slot before accepting, extra clients queue in backlog.
+        let permit = match permits.clone().acquire_owned().await {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        let (stream, peer) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("Cannot accept connection on `{}`: {}", addr, e);
+                continue;
+            }
+        };
+        tracing::info!("Accepted connection from {}", peer);
+
+        let rt = new_runtime();
+        tokio::task::spawn(async move {
+            let (r, w) = stream.into_split();
+            let r = idle::IdleTimeout::new(r, idle_timeout);
+            serve_session(rt, r, w).await;
+            tracing::info!("Connection from {} closed", peer);
+            drop(permit);
+        });
+    }
 }
 
 #[tokio::main]
@@ -253,25 +1384,168 @@ async fn main() {
     const PROG_NAME: &str = env!("CARGO_PKG_NAME");
     const PROG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-    let config = setup_command_line_arguments(PROG_NAME);
-    show_welcome(PROG_NAME, PROG_VERSION);
+    let (mut config, log_handle) = setup_command_line_arguments(PROG_NAME);
+    if !config.quiet {
+        show_welcome(PROG_NAME, PROG_VERSION);
+    }
+    spawn_loglevel_reloader(log_handle.clone(), config.loglevel_file.clone());
+
+    if let Some(path) = &config.check {
+        let ok = check::check(
+            std::path::Path::new(path),
+            config.sorted_index_threshold(),
+            config.file_encoding(),
+        )
+        .await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if let Some(path) = &config.single_file {
+        let path = std::path::Path::new(path);
+        if !path.is_file() {
+            tracing::error!("`--single-file` {} is not a file", path.display());
+            std::process::exit(1);
+        }
+        let path = std::path::absolute(path).unwrap_or(path.to_path_buf());
+        let path = tags::normalize_path(&path);
+        config.single_file = Some(path.to_string_lossy().to_string());
+    }
+
+    let exclude = match walk::build_exclude(&config.exclude) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("Invalid `--exclude` pattern: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let rt = tokio::sync::Mutex::new(Runtime {
+    // Sessions share loaded tags and their watchers but nothing else.
+    let tags = tags::SharedTags::default();
+    let watchers = watch::Watchers::default();
+    let new_runtime = || Runtime {
         prog_name: PROG_NAME.to_string(),
         prog_version: PROG_VERSION.to_string(),
         workspace_folders: Vec::new(),
         config: config.clone(),
+        cli_config: config.clone(),
+        folder_configs: HashMap::new(),
+        init_options: None,
+        log_handle: Some(log_handle.clone()),
         open_files: HashMap::new(),
-    });
+        language_ids: HashMap::new(),
+        tags: tags.clone(),
+        tasks: Vec::new(),
+        watchers: watchers.clone(),
+        pending_saves: std::collections::HashSet::new(),
+        document_symbol_kinds: None,
+        trace: TraceValue::Off,
+        position_utf16: false,
+        symbol_detail_path: false,
+        hide_anonymous: true,
+        symbol_sort: Default::default(),
+        exclude: exclude.clone(),
+        work_done_progress: false,
+        regenerating: false,
+    };
 
-    let (service, socket) = tower_lsp::LspService::new(|client| TagsLspBackend { client, rt });
+    match select_transport(&config) {
+        Transport::Socket(addr) => {
+            let idle_timeout = match config.idle_timeout_secs {
+                0 => None,
+                v => Some(std::time::Duration::from_secs(v)),
+            };
+            start_lsp_using_socket(new_runtime, addr, config.max_clients, idle_timeout).await;
+        }
+        Transport::Stdio => {
+            start_lsp_using_stdio(new_runtime()).await;
+        }
+    }
 
-    match config.port {
-        Some(port) => {
-            start_lsp_using_socket(service, socket, port).await;
+    // Reading stdin blocks runtime from shutting down, so exit explicitly.
+    tracing::info!("Server stopped");
+    std::process::exit(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// Parse command line arguments.
+    ///
+    /// # Arguments
+    ///
+    /// + `args`: Arguments after program name.
+    fn parse(args: &[&str]) -> Result<TagsLspConfig, clap::Error> {
+        return TagsLspConfig::try_parse_from(
+            std::iter::once("tags-lsp").chain(args.iter().copied()),
+        );
+    }
+
+    #[test]
+    fn transport_from_arguments() {
+        let localhost = std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+        let list = [
+            (&[][..], Transport::Stdio),
+            (&["--stdio"][..], Transport::Stdio),
+            (
+                &["--port", "9257"][..],
+                Transport::Socket(std::net::SocketAddr::new(localhost, 9257)),
+            ),
+            (
+                &["--port", "9257", "--host", "0.0.0.0"][..],
+                Transport::Socket("0.0.0.0:9257".parse().unwrap()),
+            ),
+        ];
+        for (args, expect) in list {
+            assert_eq!(
+                select_transport(&parse(args).unwrap()),
+                expect,
+                "{:?}",
+                args
+            );
         }
-        None => {
-            start_lsp_using_stdio(service, socket).await;
+
+        // Conflicting or incomplete flags.
+        assert!(parse(&["--stdio", "--port", "9257"]).is_err());
+        assert!(parse(&["--host", "0.0.0.0"]).is_err());
+    }
+
+    #[test]
+    fn loglevel_precedence() {
+        let list = [
+            (&[][..], None, LogLevel::Info),
+            (&["--quiet"][..], None, LogLevel::Error),
+            (&["--loglevel", "debug"][..], None, LogLevel::Debug),
+            (
+                &["--quiet", "--loglevel", "debug"][..],
+                None,
+                LogLevel::Debug,
+            ),
+            (&[][..], Some("warn"), LogLevel::Warn),
+            (&["--quiet"][..], Some("warn"), LogLevel::Error),
+            (&["--loglevel", "debug"][..], Some("warn"), LogLevel::Debug),
+            (&[][..], Some("nonsense"), LogLevel::Info),
+        ];
+        for (args, file, expect) in list {
+            let mut config = parse(args).unwrap();
+            config::Config {
+                log_level: file.map(String::from),
+                ..Default::default()
+            }
+            .apply(&mut config);
+            assert_eq!(config.loglevel(), expect, "{:?} {:?}", args, file);
         }
     }
+
+    #[test]
+    fn tags_filename_arguments() {
+        let config = parse(&[]).unwrap();
+        assert_eq!(config.tags_filename, ["tags"]);
+        assert_eq!(config.tags_filename(), "tags");
+
+        let config = parse(&["--tags-filename", ".tags", "--tags-filename", "tags"]).unwrap();
+        assert_eq!(config.tags_filename, [".tags", "tags"]);
+        assert_eq!(config.tags_filename(), ".tags");
+    }
 }
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use tower_lsp::lsp_types::*;
+
+pub async fn prepare_call_hierarchy(
+    backend: &crate::TagsLspBackend,
+    params: CallHierarchyPrepareParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyItem>>> {
+    let doc_uri = &params.text_document_position_params.text_document.uri;
+    let doc_pos = &params.text_document_position_params.position;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+
+    let symbol = crate::method::get_symbol_by_position(&rt, doc_uri, doc_pos).await?;
+    let name = crate::method::tag_name_of(&symbol);
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let mut entry_list = Vec::new();
+    let mut loc_list = Vec::new();
+    for tags_file in rt.tags.iter() {
+        for entry in tags_file.lookup(name) {
+            if entry.is_declaration()
+                || !crate::method::signature_help::is_callable(entry)
+                || !crate::method::tag_target_exists(tags_file, entry).await
+            {
+                continue;
+            }
+
+            let loc = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                rt.config.low_precision,
+                rt.config.address_resolution,
+                &cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            // Several tags files may cover the same code.
+            if loc_list.contains(&loc) {
+                continue;
+            }
+            entry_list.push(entry);
+            loc_list.push(loc);
+        }
+    }
+
+    if loc_list.is_empty() {
+        return Ok(None);
+    }
+
+    crate::method::encode_locations(&mut loc_list, rt.position_utf16, &cache).await;
+    let item_list = entry_list
+        .into_iter()
+        .zip(loc_list)
+        .map(|(entry, loc)| create_item(entry, loc))
+        .collect();
+
+    return Ok(Some(item_list));
+}
+
+/// Find callers of the item.
+///
+/// Every textual reference is a call site, and the caller is the function tag
+/// that starts nearest above it. Tags with `end:` field only enclose lines up
+/// to their end, so calls at file level are grouped under the file.
+pub async fn incoming_calls(
+    backend: &crate::TagsLspBackend,
+    params: CallHierarchyIncomingCallsParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    let item = &params.item;
+    let rt = backend.snapshot(Some(&item.uri)).await;
+    let ignore_case = rt.config.ignore_case_fs();
+    let cwd =
+        crate::method::find_belong_workspace_folder(&rt.workspace_folders, &item.uri, ignore_case)?;
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let index = crate::index::select(&rt, &cwd, &cache);
+    let mut call_list = index.references(&item.name, false).await?;
+    crate::method::limit_results(&mut call_list, rt.config.max_results(), &item.name);
+    crate::method::encode_locations(&mut call_list, rt.position_utf16, &cache).await;
+
+    let mut path_list = Vec::new();
+    for loc in &call_list {
+        if let Ok(v) = loc.uri.to_file_path() {
+            if !path_list.contains(&v) {
+                path_list.push(v);
+            }
+        }
+    }
+
+    // Functions defined in files that contain calls.
+    let mut entry_list = Vec::new();
+    let mut loc_list = Vec::new();
+    for tags_file in rt.tags.iter() {
+        for (idx, entry) in tags_file.entries.iter().enumerate() {
+            // Give cancellation a chance in huge tags files.
+            if idx % 4096 == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            if entry.is_declaration() || !crate::method::signature_help::is_callable(entry) {
+                continue;
+            }
+            let path = tags_file.entry_path(entry);
+            if !path_list
+                .iter()
+                .any(|v| crate::tags::same_path(v, &path, ignore_case))
+            {
+                continue;
+            }
+
+            let loc = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                false,
+                rt.config.address_resolution,
+                &cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            if loc_list.contains(&loc) {
+                continue;
+            }
+            entry_list.push(entry);
+            loc_list.push(loc);
+        }
+    }
+    crate::method::encode_locations(&mut loc_list, rt.position_utf16, &cache).await;
+
+    // Callers in order of first call, with their call sites.
+    let mut caller_list = Vec::<CallHierarchyIncomingCall>::new();
+    let mut caller_index = HashMap::<(Url, u32), usize>::new();
+    for call in call_list {
+        let line = call.range.start.line;
+        let caller = entry_list
+            .iter()
+            .zip(&loc_list)
+            .filter(|(entry, loc)| {
+                loc.uri == call.uri
+                    && loc.range.start.line <= line
+                    && entry.end.is_none_or(|v| line < v)
+            })
+            .max_by_key(|(_, loc)| loc.range.start.line);
+
+        let key = match caller {
+            Some((_, loc)) => (call.uri.clone(), loc.range.start.line),
+            None => (call.uri.clone(), u32::MAX),
+        };
+        if let Some(idx) = caller_index.get(&key) {
+            caller_list[*idx].from_ranges.push(call.range);
+            continue;
+        }
+
+        let from = match caller {
+            Some((entry, loc)) => create_item(entry, loc.clone()),
+            None => create_file_item(&call.uri),
+        };
+        caller_index.insert(key, caller_list.len());
+        caller_list.push(CallHierarchyIncomingCall {
+            from,
+            from_ranges: vec![call.range],
+        });
+    }
+
+    return Ok(Some(caller_list));
+}
+
+/// Create call hierarchy item of function tag.
+///
+/// # Arguments
+///
+/// + `entry`: Tag entry of function.
+/// + `loc`: Location of the function name.
+fn create_item(entry: &crate::tags::TagEntry, loc: Location) -> CallHierarchyItem {
+    return CallHierarchyItem {
+        name: entry.name.clone(),
+        kind: crate::tags::symbol_kind(entry.kind.as_deref()),
+        tags: None,
+        detail: entry.scope.clone(),
+        uri: loc.uri,
+        range: loc.range,
+        selection_range: loc.range,
+        data: None,
+    };
+}
+
+/// Create call hierarchy item for calls outside of any function.
+///
+/// # Arguments
+///
+/// + `uri`: File that contains the calls.
+fn create_file_item(uri: &Url) -> CallHierarchyItem {
+    let name = uri
+        .path_segments()
+        .and_then(|mut v| v.next_back())
+        .unwrap_or_default()
+        .to_string();
+
+    return CallHierarchyItem {
+        name,
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: Range::default(),
+        selection_range: Range::default(),
+        data: None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn incoming_calls_of_callee() {
+        let (mut client, uri) = crate::testing::start().await;
+
+        // `add` of `return add(1, 2);`.
+        let params = CallHierarchyPrepareParams {
+            text_document_position_params: crate::testing::position(&uri, 7, 12),
+            work_done_progress_params: Default::default(),
+        };
+        let item_list = client
+            .request::<request::CallHierarchyPrepare>(params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item_list.len(), 1);
+        assert_eq!(item_list[0].name, "add");
+        assert_eq!(item_list[0].kind, SymbolKind::FUNCTION);
+        assert_eq!(item_list[0].selection_range.start, Position::new(0, 4));
+
+        let params = CallHierarchyIncomingCallsParams {
+            item: item_list[0].clone(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let call_list = client
+            .request::<request::CallHierarchyIncomingCalls>(params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(call_list.len(), 1);
+        assert_eq!(call_list[0].from.name, "main");
+        assert_eq!(call_list[0].from.selection_range.start, Position::new(5, 4));
+        let expect = Range::new(Position::new(7, 11), Position::new(7, 14));
+        assert_eq!(call_list[0].from_ranges, [expect]);
+
+        client.shutdown().await;
+    }
+}
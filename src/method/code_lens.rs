@@ -0,0 +1,178 @@
+use tower_lsp::lsp_types::*;
+
+/// Data for resolving code lens, kept by client between requests.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct LensData {
+    /// Document that contains the definition.
+    uri: Url,
+
+    /// Tag name of the definition.
+    name: String,
+}
+
+pub async fn code_lens(
+    backend: &crate::TagsLspBackend,
+    params: CodeLensParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<CodeLens>>> {
+    let doc_uri = &params.text_document.uri;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+    let doc_path = match doc_uri.to_file_path() {
+        Ok(v) => v,
+        Err(_) => return Ok(None),
+    };
+    let ignore_case = rt.config.ignore_case_fs();
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let mut seen = std::collections::HashSet::new();
+    let mut name_list = Vec::new();
+    let mut loc_list = Vec::new();
+    for tags_file in rt.tags.iter() {
+        for (idx, entry) in tags_file.entries.iter().enumerate() {
+            // Give cancellation a chance in huge tags files.
+            if idx % 4096 == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            if !crate::tags::same_path(&tags_file.entry_path(entry), &doc_path, ignore_case) {
+                continue;
+            }
+
+            // Locals and anonymous types are not worth a lens.
+            let kind = entry.kind.as_deref().map(crate::tags::kind_name);
+            if entry.is_declaration()
+                || kind == Some("local")
+                || crate::tags::is_anonymous(&entry.name)
+            {
+                continue;
+            }
+
+            let loc = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                rt.config.low_precision,
+                rt.config.address_resolution,
+                &cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            // Several tags files may cover the same code.
+            if !seen.insert((&entry.name, loc.range.start.line)) {
+                continue;
+            }
+            name_list.push(entry.name.clone());
+            loc_list.push(loc);
+        }
+    }
+
+    crate::method::encode_locations(&mut loc_list, rt.position_utf16, &cache).await;
+
+    let lens_list = name_list
+        .into_iter()
+        .zip(loc_list)
+        .map(|(name, loc)| CodeLens {
+            range: loc.range,
+            command: None,
+            data: serde_json::to_value(LensData {
+                uri: doc_uri.clone(),
+                name,
+            })
+            .ok(),
+        })
+        .collect();
+
+    return Ok(Some(lens_list));
+}
+
+/// Count references of the definition under lens.
+///
+/// Counting scans the workspace, so it is done only for lenses that client
+/// shows.
+pub async fn code_lens_resolve(
+    backend: &crate::TagsLspBackend,
+    params: CodeLens,
+) -> tower_lsp::jsonrpc::Result<CodeLens> {
+    let data = params
+        .data
+        .clone()
+        .and_then(|v| serde_json::from_value::<LensData>(v).ok());
+    let data = match data {
+        Some(v) => v,
+        None => {
+            return Err(crate::error::TagsLspError::InvalidParams {
+                message: String::from("code lens has no symbol to resolve"),
+                data: params.data,
+            }
+            .into())
+        }
+    };
+
+    let rt = backend.snapshot(Some(&data.uri)).await;
+    let cwd = crate::method::find_belong_workspace_folder(
+        &rt.workspace_folders,
+        &data.uri,
+        rt.config.ignore_case_fs(),
+    )?;
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let index = crate::index::select(&rt, &cwd, &cache);
+    let count = index.references(&data.name, false).await?.len();
+
+    let title = match count {
+        1 => String::from("1 reference"),
+        v => format!("{} references", v),
+    };
+    return Ok(CodeLens {
+        range: params.range,
+        command: Some(Command {
+            title,
+            command: String::from(crate::method::execute_command::COMMAND_FIND_SYMBOL),
+            arguments: Some(vec![serde_json::json!(data.name)]),
+        }),
+        data: params.data,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lens_for_each_definition() {
+        let (mut client, uri) = crate::testing::start().await;
+
+        let params = CodeLensParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let lens_list = client
+            .request::<request::CodeLensRequest>(params)
+            .await
+            .unwrap()
+            .unwrap();
+        let lines: Vec<_> = lens_list.iter().map(|v| v.range.start.line).collect();
+        assert_eq!(lines, [0, 5]);
+        assert!(lens_list.iter().all(|v| v.command.is_none()));
+
+        let mut title_list = Vec::new();
+        for lens in lens_list {
+            let ret = client
+                .request::<request::CodeLensResolve>(lens)
+                .await
+                .unwrap();
+            let command = ret.command.unwrap();
+            assert_eq!(
+                command.command,
+                crate::method::execute_command::COMMAND_FIND_SYMBOL
+            );
+            title_list.push(command.title);
+        }
+        assert_eq!(title_list, ["1 reference", "0 references"]);
+
+        client.shutdown().await;
+    }
+}
@@ -1,32 +1,129 @@
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 
 use tower_lsp::lsp_types::*;
 
+/// The maximum limit of return symbols.
+const SYMBOL_LIMIT: usize = 64;
+
 pub async fn do_completion(
     backend: &crate::TagsLspBackend,
     params: CompletionParams,
 ) -> tower_lsp::jsonrpc::Result<Option<CompletionResponse>> {
-    /// The maximum limit of return symbols.
-    const SYMBOL_LIMIT: usize = 16;
-
-    let mut item_list = Vec::<CompletionItem>::new();
-    let rt = backend.rt.lock().await;
-
     let doc_uri = params.text_document_position.text_document.uri;
     let doc_pos = params.text_document_position.position;
-    let doc_dat = rt.open_files.get(&doc_uri).unwrap();
-    let cwd = crate::method::find_belong_workspace_folder(&rt.workspace_folders, &doc_uri)?;
+    let rt = backend.snapshot(Some(&doc_uri)).await;
+    let doc_dat = match &rt.document {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let cwd = crate::method::find_belong_workspace_folder(
+        &rt.workspace_folders,
+        &doc_uri,
+        rt.config.ignore_case_fs(),
+    )?;
+
+    let lang = crate::method::document_language_id(&rt, &doc_uri);
+
+    match crate::index::detect(&rt.config, &cwd) {
+        crate::index::BackendKind::Ctags => {
+            let prefix = match word_before_cursor(doc_dat, &doc_pos, &lang, rt.position_utf16) {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+            let prefix = crate::method::tag_name_of(&prefix);
+            return Ok(Some(complete_tags(&rt.tags, prefix)));
+        }
+        crate::index::BackendKind::Global => {
+            let symbol = crate::method::get_symbol_by_pos_from_dat(
+                doc_dat,
+                &doc_pos,
+                &lang,
+                rt.position_utf16,
+            )?;
+            let symbol = crate::method::tag_name_of(&symbol);
+            return complete_gtags(&cwd.uri, symbol).await;
+        }
+    }
+}
+
+/// Get the part of word before cursor.
+///
+/// # Arguments
+///
+/// + `data`: File content.
+/// + `pos`: Cursor position.
+/// + `lang`: LSP language id, decides the word boundaries.
+/// + `utf16`: `pos` counts UTF-16 code units instead of bytes.
+fn word_before_cursor(data: &str, pos: &Position, lang: &str, utf16: bool) -> Option<String> {
+    let line = data.lines().nth(pos.line as usize)?;
+    let character = match utf16 {
+        true => crate::method::utf16_to_byte_offset(line, pos.character),
+        false => (pos.character as usize).min(line.len()),
+    };
+
+    let (word, range) = crate::method::word_at(line, character, lang)?;
+    let end = character.min(range.end) - range.start;
+
+    return word.get(..end).map(|v| v.to_string());
+}
+
+/// Complete symbol from loaded tags files.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+/// + `prefix`: The partial symbol.
+fn complete_tags(
+    tags: &[std::sync::Arc<crate::tags::TagsFile>],
+    prefix: &str,
+) -> CompletionResponse {
+    // Sorted and without duplication.
+    let mut entry_map = BTreeMap::<&str, &crate::tags::TagEntry>::new();
+    for tags_file in tags {
+        for entry in tags_file.lookup_with(prefix, crate::tags::MatchMode::Prefix) {
+            entry_map.entry(entry.name.as_str()).or_insert(entry);
+        }
+    }
+
+    let is_incomplete = entry_map.len() > SYMBOL_LIMIT;
+    let item_list = entry_map
+        .into_values()
+        .take(SYMBOL_LIMIT)
+        .map(|entry| CompletionItem {
+            label: entry.name.clone(),
+            kind: crate::tags::completion_kind(entry.kind.as_deref()),
+            detail: Some(entry.file.clone()),
+            ..Default::default()
+        })
+        .collect();
+
+    return CompletionResponse::List(CompletionList {
+        is_incomplete,
+        items: item_list,
+    });
+}
 
-    let symbol = crate::method::get_symbol_by_pos_from_dat(doc_dat, &doc_pos)?;
+/// Complete symbol using GNU Global.
+///
+/// # Arguments
+///
+/// + `cwd`: Workspace folder.
+/// + `symbol`: The partial symbol.
+async fn complete_gtags(
+    cwd: &Url,
+    symbol: &str,
+) -> tower_lsp::jsonrpc::Result<Option<CompletionResponse>> {
+    let mut item_list = Vec::<CompletionItem>::new();
 
     // Execute `gtags -c`
-    let args = vec!["-c", symbol.as_str()];
-    let future_completion = crate::method::execute_and_split_lines("global", &cwd.uri, &args);
+    let args = vec!["-c", symbol];
+    let future_completion = crate::method::execute_and_split_lines("global", cwd, &args);
 
     // Execute `gtags -sx`
-    let args = format!("{}.", symbol.as_str());
+    let args = format!("{}.", symbol);
     let args = vec!["-sx", &args];
-    let future_symbol = crate::method::execute_and_split_lines("global", &cwd.uri, &args);
+    let future_symbol = crate::method::execute_and_split_lines("global", cwd, &args);
 
     let lines_completion = future_completion.await?;
     let lines_symbol = future_symbol.await?;
@@ -56,3 +153,58 @@ pub async fn do_completion(
 
     return Ok(Some(CompletionResponse::Array(item_list)));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_by_prefix() {
+        let lines = [
+            "add\tmath.c\t1;\"\tf",
+            "add_all\tmath.c\t5;\"\tf",
+            "add\tother.c\t1;\"\tf",
+            "count\tmath.c\t9;\"\tv",
+        ];
+        let entries = lines
+            .iter()
+            .filter_map(|v| crate::tags::parse_line(v))
+            .collect();
+        let tags = crate::tags::TagsFile::new(std::path::PathBuf::from("tags"), entries);
+        let tags = vec![std::sync::Arc::new(tags)];
+
+        let list = match complete_tags(&tags, "ad") {
+            CompletionResponse::List(v) => v,
+            v => panic!("unexpected response: {:?}", v),
+        };
+        assert!(!list.is_incomplete);
+
+        let items: Vec<_> = list
+            .items
+            .iter()
+            .map(|v| (v.label.as_str(), v.kind, v.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                ("add", Some(CompletionItemKind::FUNCTION), Some("math.c")),
+                (
+                    "add_all",
+                    Some(CompletionItemKind::FUNCTION),
+                    Some("math.c")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefix_before_cursor() {
+        let data = "int x = add_all(1);\n";
+        let word =
+            |character: u32| word_before_cursor(data, &Position::new(0, character), "c", false);
+        assert_eq!(word(11).as_deref(), Some("add"));
+        assert_eq!(word(15).as_deref(), Some("add_all"));
+        assert_eq!(word(8).as_deref(), Some(""));
+        assert_eq!(word(6), None);
+    }
+}
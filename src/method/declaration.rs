@@ -0,0 +1,73 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn goto_declaration(
+    backend: &crate::TagsLspBackend,
+    params: request::GotoDeclarationParams,
+) -> tower_lsp::jsonrpc::Result<Option<request::GotoDeclarationResponse>> {
+    let params = &params.text_document_position_params;
+    return crate::method::goto_symbol(backend, params, |index, name, _, lang| {
+        index.declarations(name, lang)
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn declaration_and_definition() {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-declaration-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = "int helper(int x);\n\nint helper(int x)\n{\n    return x;\n}\n\nint main(void)\n{\n    return helper(1);\n}\n";
+        std::fs::write(dir.join("a.c"), content).unwrap();
+        let tags = "helper\ta.c\t/^int helper(int x);$/;\"\tp\n\
+                    helper\ta.c\t/^int helper(int x)$/;\"\tf\n\
+                    main\ta.c\t/^int main(void)$/;\"\tf\n";
+        std::fs::write(dir.join("tags-lsp-test-tags"), tags).unwrap();
+        let root = Url::from_directory_path(&dir).unwrap();
+        let uri = Url::from_file_path(dir.join("a.c")).unwrap();
+
+        let mut client = crate::testing::Client::start(&[
+            "--no-auto-generate",
+            "--tags-filename",
+            "tags-lsp-test-tags",
+        ]);
+        client.initialize(&root).await;
+
+        // `helper` of `return helper(1);`.
+        let params = request::GotoDeclarationParams {
+            text_document_position_params: crate::testing::position(&uri, 9, 12),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let declaration = client
+            .request::<request::GotoDeclaration>(params)
+            .await
+            .unwrap();
+        let params = GotoDefinitionParams {
+            text_document_position_params: crate::testing::position(&uri, 9, 12),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let definition = client
+            .request::<request::GotoDefinition>(params)
+            .await
+            .unwrap();
+
+        let expect = Location::new(
+            uri.clone(),
+            Range::new(Position::new(0, 4), Position::new(0, 10)),
+        );
+        assert_eq!(
+            declaration,
+            Some(request::GotoDeclarationResponse::Scalar(expect))
+        );
+        let expect = Location::new(uri, Range::new(Position::new(2, 4), Position::new(2, 10)));
+        assert_eq!(definition, Some(GotoDefinitionResponse::Scalar(expect)));
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,11 +2,125 @@ use tower_lsp::lsp_types::*;
 
 pub async fn did_change(backend: &crate::TagsLspBackend, params: DidChangeTextDocumentParams) {
     let mut rt = backend.rt.lock().await;
+    let utf16 = rt.position_utf16;
 
     let doc_uri = params.text_document.uri;
-    let doc_dat = &params.content_changes[0].text;
+    let doc_dat = match rt.open_files.get_mut(&doc_uri) {
+        Some(v) => v,
+        None => return,
+    };
 
-    rt.open_files
-        .entry(doc_uri)
-        .and_modify(|e| *e = doc_dat.clone());
+    // Changes are applied in order.
+    for change in params.content_changes {
+        match change.range {
+            Some(range) => {
+                let start = position_to_offset(doc_dat, &range.start, utf16);
+                let end = position_to_offset(doc_dat, &range.end, utf16).max(start);
+                doc_dat.replace_range(start..end, &change.text);
+            }
+            None => *doc_dat = change.text,
+        }
+    }
+}
+
+/// Convert position to byte offset in text.
+///
+/// Positions past end of line or end of text are clamped.
+///
+/// # Arguments
+///
+/// + `text`: Document content.
+/// + `pos`: Position in document.
+/// + `utf16`: `pos` counts UTF-16 code units instead of bytes.
+fn position_to_offset(text: &str, pos: &Position, utf16: bool) -> usize {
+    let mut line_start = 0;
+    for _ in 0..pos.line {
+        match text[line_start..].find('\n') {
+            Some(v) => line_start += v + 1,
+            None => return text.len(),
+        }
+    }
+
+    let rest = &text[line_start..];
+    let line = rest.split('\n').next().unwrap_or(rest);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let mut offset = match utf16 {
+        true => crate::method::utf16_to_byte_offset(line, pos.character),
+        false => (pos.character as usize).min(line.len()),
+    };
+    while !line.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    return line_start + offset;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn track_document_content() {
+        let service = crate::testing::backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///src/a.c").unwrap();
+        let content = |rt: &crate::Runtime| rt.open_files.get(&uri).cloned();
+
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: String::from("c"),
+                version: 1,
+                text: String::from("int foo;\r\nint bar;\n"),
+            },
+        };
+        crate::method::did_open::did_open(backend, params).await;
+        let expect = "int foo;\r\nint bar;\n";
+        assert_eq!(content(&*backend.rt.lock().await).as_deref(), Some(expect));
+
+        let change = |line: u32, start: u32, end: u32, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(line, start),
+                Position::new(line, end),
+            )),
+            range_length: None,
+            text: String::from(text),
+        };
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: 2,
+            },
+            content_changes: vec![
+                change(0, 4, 7, "baz"),
+                change(1, 100, 100, " // end"),
+                change(5, 0, 0, "int qux;\n"),
+            ],
+        };
+        did_change(backend, params).await;
+        let expect = "int baz;\r\nint bar; // end\nint qux;\n";
+        assert_eq!(content(&*backend.rt.lock().await).as_deref(), Some(expect));
+
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: 3,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: String::from("int all;\n"),
+            }],
+        };
+        did_change(backend, params).await;
+        let expect = "int all;\n";
+        assert_eq!(content(&*backend.rt.lock().await).as_deref(), Some(expect));
+
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+        };
+        crate::method::did_close::did_close(backend, params).await;
+        assert_eq!(content(&*backend.rt.lock().await), None);
+    }
 }
@@ -6,4 +6,5 @@ pub async fn did_close(backend: &crate::TagsLspBackend, params: DidCloseTextDocu
     let doc_uri = params.text_document.uri;
 
     rt.open_files.remove(&doc_uri);
+    rt.language_ids.remove(&doc_uri);
 }
@@ -6,5 +6,11 @@ pub async fn did_open(backend: &crate::TagsLspBackend, params: DidOpenTextDocume
     let doc_uri = params.text_document.uri;
     let doc_dat = params.text_document.text;
 
+    // Some clients send empty language id, fall back to file extension.
+    let language_id = params.text_document.language_id;
+    if !language_id.is_empty() {
+        rt.language_ids.insert(doc_uri.clone(), language_id);
+    }
+
     rt.open_files.insert(doc_uri, doc_dat);
 }
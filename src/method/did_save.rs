@@ -0,0 +1,102 @@
+use tower_lsp::lsp_types::*;
+
+/// How long to wait for more saves before updating tags.
+const DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+pub async fn did_save(backend: &crate::TagsLspBackend, params: DidSaveTextDocumentParams) {
+    let mut rt = backend.rt.lock().await;
+    if rt.config.no_update_on_save {
+        return;
+    }
+
+    let path = match params.text_document.uri.to_file_path() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+
+    // Only the file itself is indexed in single file mode.
+    if let Some(v) = &rt.config.single_file {
+        let ignore_case = rt.config.ignore_case_fs();
+        if !crate::tags::same_path(std::path::Path::new(v), &path, ignore_case) {
+            return;
+        }
+    }
+
+    // An update is already scheduled for this file.
+    if !rt.pending_saves.insert(path.clone()) {
+        return;
+    }
+
+    let backend_rt = backend.rt.clone();
+    let task = tokio::task::spawn(async move {
+        tokio::time::sleep(DEBOUNCE).await;
+        update_file_tags(&backend_rt, path).await;
+    });
+    rt.track_task(task.abort_handle());
+}
+
+/// Run ctags on file and replace its entries in the tags file it belongs to.
+///
+/// # Arguments
+///
+/// + `rt`: Runtime to update.
+/// + `path`: Path of saved file.
+async fn update_file_tags(rt: &tokio::sync::Mutex<crate::Runtime>, path: std::path::PathBuf) {
+    let (ctags_bin, ctags_args, encoding, dir, ignore_case) = {
+        let mut rt = rt.lock().await;
+        rt.pending_saves.remove(&path);
+
+        // The nearest tags file wins.
+        let ignore_case = rt.config.ignore_case_fs();
+        let tags = rt.tags.load();
+        let dir = tags
+            .iter()
+            .filter_map(|v| v.path.parent())
+            .filter(|v| crate::tags::path_starts_with(&path, v, ignore_case))
+            .max_by_key(|v| v.components().count())
+            .map(|v| v.to_path_buf());
+
+        // Settings of the workspace folder that contains the file.
+        let folder_config = match crate::tags::path_to_uri(&path) {
+            Some(v) => rt.folder_config(&v),
+            None => &rt.config,
+        };
+
+        match dir {
+            Some(v) => (
+                folder_config.ctags_bin().to_string(),
+                folder_config.ctags_args.clone(),
+                folder_config.file_encoding(),
+                v,
+                ignore_case,
+            ),
+            None => return,
+        }
+    };
+
+    let entries = match crate::ctags::run_ctags_file(
+        &crate::ctags::SystemRunner,
+        &ctags_bin,
+        &dir,
+        &path,
+        &ctags_args,
+        encoding,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("Update tags for {} failed: {}", path.display(), e);
+            return;
+        }
+    };
+
+    tracing::debug!("Update {} tags for {}", entries.len(), path.display());
+
+    let rt = rt.lock().await;
+    rt.tags.update(|tags| {
+        if let Some(v) = tags.iter_mut().find(|v| v.path.parent() == Some(&dir)) {
+            std::sync::Arc::make_mut(v).replace_file_entries(&path, entries, ignore_case);
+        }
+    });
+}
@@ -0,0 +1,85 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn document_highlight(
+    backend: &crate::TagsLspBackend,
+    params: DocumentHighlightParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+    let doc_uri = &params.text_document_position_params.text_document.uri;
+    let doc_pos = &params.text_document_position_params.position;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+    let doc_dat = match &rt.document {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let limit = rt.config.max_file_size;
+    if limit != 0 && doc_dat.len() as u64 > limit {
+        tracing::debug!("Skip {}: larger than {} bytes", doc_uri.path(), limit);
+        return Ok(None);
+    }
+
+    let lang = crate::method::document_language_id(&rt, doc_uri);
+    let symbol =
+        crate::method::get_symbol_by_pos_from_dat(doc_dat, doc_pos, &lang, rt.position_utf16)?;
+    let symbol = crate::method::tag_name_of(&symbol);
+
+    let lines: Vec<&str> = doc_dat.lines().collect();
+    let mut highlight_list = Vec::new();
+    for mut range in crate::method::find_symbol_in_content(doc_dat, symbol, &lang) {
+        if rt.position_utf16 {
+            let line = lines[range.start.line as usize];
+            let start = range.start.character as usize;
+            let end = range.end.character as usize;
+            range.start.character = crate::method::byte_to_utf16_offset(line, start);
+            range.end.character = crate::method::byte_to_utf16_offset(line, end);
+        }
+
+        highlight_list.push(DocumentHighlight {
+            range,
+            kind: Some(DocumentHighlightKind::TEXT),
+        });
+    }
+
+    return Ok(Some(highlight_list));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn highlight_every_occurrence() {
+        let (mut client, uri) = crate::testing::start().await;
+        client
+            .open(&uri, "int foo = 1;\nint foobar = foo + foo;\n")
+            .await;
+
+        let params = DocumentHighlightParams {
+            text_document_position_params: crate::testing::position(&uri, 0, 5),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::DocumentHighlightRequest>(params)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let ranges: Vec<_> = ret
+            .iter()
+            .map(|v| {
+                (
+                    v.range.start.line,
+                    v.range.start.character,
+                    v.range.end.character,
+                )
+            })
+            .collect();
+        assert_eq!(ranges, [(0, 4, 7), (1, 13, 16), (1, 19, 22)]);
+        assert!(ret
+            .iter()
+            .all(|v| v.kind == Some(DocumentHighlightKind::TEXT)));
+
+        client.shutdown().await;
+    }
+}
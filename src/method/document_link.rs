@@ -0,0 +1,162 @@
+use regex::Regex;
+use tower_lsp::lsp_types::*;
+
+lazy_static::lazy_static! {
+    /// Pattern for match `#include "foo.h"` and `#import <foo.h>`.
+    static ref RE_INCLUDE: Regex =
+        Regex::new(r#"^\s*#\s*(?:include|import)\s*(?:"([^"]+)"|<([^>]+)>)"#).unwrap();
+}
+
+pub async fn document_link(
+    backend: &crate::TagsLspBackend,
+    params: DocumentLinkParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<DocumentLink>>> {
+    let doc_uri = &params.text_document.uri;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+
+    let lang = crate::method::document_language_id(&rt, doc_uri);
+    if !matches!(lang.as_str(), "c" | "cpp" | "objective-c" | "objective-cpp") {
+        return Ok(None);
+    }
+
+    let content = match &rt.document {
+        Some(v) => v.clone(),
+        None => crate::method::read_file_content(doc_uri.path(), rt.config.file_encoding()).await?,
+    };
+
+    // Quoted paths are relative to the including file first.
+    let dir = std::path::Path::new(doc_uri.path())
+        .parent()
+        .map(|v| v.to_path_buf());
+    let roots: Vec<_> = rt
+        .workspace_folders
+        .iter()
+        .filter_map(|v| v.uri.to_file_path().ok())
+        .collect();
+
+    let mut link_list = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let cap = match RE_INCLUDE.captures(line) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (path, quoted) = match (cap.get(1), cap.get(2)) {
+            (Some(v), _) => (v, true),
+            (None, Some(v)) => (v, false),
+            (None, None) => continue,
+        };
+
+        let search = dir.iter().filter(|_| quoted).chain(roots.iter());
+        let target = match resolve(search, path.as_str()).await {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let (start, end) = match rt.position_utf16 {
+            true => (
+                crate::method::byte_to_utf16_offset(line, path.start()),
+                crate::method::byte_to_utf16_offset(line, path.end()),
+            ),
+            false => (path.start() as u32, path.end() as u32),
+        };
+        link_list.push(DocumentLink {
+            range: Range::new(
+                Position::new(line_no as u32, start),
+                Position::new(line_no as u32, end),
+            ),
+            target: Some(target),
+            tooltip: None,
+            data: None,
+        });
+    }
+
+    return Ok(Some(link_list));
+}
+
+/// Find the first directory that contains included file.
+///
+/// # Arguments
+///
+/// + `dir_list`: Directories to search in order.
+/// + `path`: Path written in directive.
+async fn resolve<'a>(
+    dir_list: impl Iterator<Item = &'a std::path::PathBuf>,
+    path: &str,
+) -> Option<Url> {
+    for dir in dir_list {
+        let file = dir.join(path);
+        if tokio::fs::metadata(&file).await.is_ok_and(|v| v.is_file()) {
+            return crate::tags::path_to_uri(&file);
+        }
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn link_to_included_file() {
+        let root = std::env::temp_dir().join(format!("tags-lsp-test-link-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("include")).unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("include/util.h"), "").unwrap();
+        std::fs::write(root.join("src/local.h"), "").unwrap();
+        let root = std::fs::canonicalize(&root).unwrap();
+
+        let service = crate::testing::backend();
+        let backend = service.inner();
+        let uri = Url::from_file_path(root.join("src/main.c")).unwrap();
+        let content = [
+            "#include \"local.h\"",
+            "#include <include/util.h>",
+            "  #  include \"include/util.h\"",
+            "#include <local.h>",
+            "#include \"missing.h\"",
+        ];
+        {
+            let mut rt = backend.rt.lock().await;
+            rt.workspace_folders.push(WorkspaceFolder {
+                uri: Url::from_directory_path(&root).unwrap(),
+                name: String::from("root"),
+            });
+            rt.open_files.insert(uri.clone(), content.join("\n"));
+        }
+
+        let params = DocumentLinkParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = document_link(backend, params).await.unwrap().unwrap();
+        let links: Vec<_> = ret
+            .into_iter()
+            .map(|v| {
+                let path = v.target.unwrap().to_file_path().unwrap();
+                let path = path
+                    .strip_prefix(&root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                (
+                    v.range.start.line,
+                    v.range.start.character,
+                    v.range.end.character,
+                    path,
+                )
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (0, 10, 17, String::from("src/local.h")),
+                (1, 10, 24, String::from("include/util.h")),
+                (2, 14, 28, String::from("include/util.h")),
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
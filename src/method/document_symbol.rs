@@ -1,34 +1,230 @@
 use tower_lsp::lsp_types::*;
 
-pub async fn do_document_symbol(
+pub async fn document_symbol(
     backend: &crate::TagsLspBackend,
     params: DocumentSymbolParams,
 ) -> tower_lsp::jsonrpc::Result<Option<DocumentSymbolResponse>> {
+    let doc_uri = &params.text_document.uri;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+    let low_precision = rt.config.low_precision;
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+
+    let kinds = rt.document_symbol_kinds.as_ref();
+    let ignore_case = rt.config.ignore_case_fs();
+    let mut loc_list = search_tags(
+        &rt.tags,
+        doc_uri,
+        kinds,
+        &rt.config,
+        rt.hide_anonymous,
+        &cache,
+    )
+    .await;
+    if loc_list.is_empty() {
+        let cwd = crate::method::find_belong_workspace_folder(
+            &rt.workspace_folders,
+            doc_uri,
+            ignore_case,
+        )?;
+        loc_list = search_gtags(&cwd.uri, doc_uri, low_precision, &cache).await?;
+    }
+
+    let utf16 = rt.position_utf16;
+    crate::method::encode_symbol_locations(&mut loc_list, utf16, &cache).await;
+
+    // Outline reads top-to-bottom.
+    loc_list.sort_by_key(|v| {
+        (
+            v.location.range.start.line,
+            v.location.range.start.character,
+        )
+    });
+
+    return Ok(Some(DocumentSymbolResponse::Flat(loc_list)));
+}
+
+/// Search loaded tags files for symbols defined in file.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+/// + `doc_uri`: File uri.
+/// + `kinds`: Full names of ctags kinds to keep, keep all if `None`.
+/// + `config`: Configuration, decides how addresses are resolved and paths
+///   are compared.
+/// + `hide_anonymous`: Hide or rename anonymous types.
+/// + `cache`: Cache of file contents for current request.
+async fn search_tags(
+    tags: &[std::sync::Arc<crate::tags::TagsFile>],
+    doc_uri: &Url,
+    kinds: Option<&std::collections::HashSet<String>>,
+    config: &crate::TagsLspConfig,
+    hide_anonymous: bool,
+    cache: &crate::cache::FileCache,
+) -> Vec<SymbolInformation> {
     let mut loc_list = Vec::<SymbolInformation>::new();
 
-    let rt = backend.rt.lock().await;
-    let low_precision = rt.config.low_precision;
+    let doc_path = match doc_uri.to_file_path() {
+        Ok(v) => v,
+        Err(_) => return loc_list,
+    };
 
-    let doc_uri = &params.text_document.uri;
-    let cwd = crate::method::find_belong_workspace_folder(&rt.workspace_folders, doc_uri)?;
+    for tags_file in tags {
+        for (idx, entry) in tags_file.entries.iter().enumerate() {
+            // Give cancellation a chance in huge tags files.
+            if idx % 4096 == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            if !crate::tags::same_path(
+                &tags_file.entry_path(entry),
+                &doc_path,
+                config.ignore_case_fs(),
+            ) {
+                continue;
+            }
+
+            if hide_anonymous && tags_file.readable_name(&entry.name).is_none() {
+                continue;
+            }
+
+            if let Some(kinds) = kinds {
+                let kind = entry.kind.as_deref().map(crate::tags::kind_name);
+                if !kind.is_some_and(|v| kinds.contains(v)) {
+                    continue;
+                }
+            }
+
+            let loc = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                config.low_precision,
+                config.address_resolution,
+                cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let info = crate::method::create_tag_symbol_information(entry, loc);
+            let info = match hide_anonymous {
+                true => match crate::method::deanonymize_symbol(tags_file, info) {
+                    Some(v) => v,
+                    None => continue,
+                },
+                false => info,
+            };
+            loc_list.push(info);
+        }
+    }
+
+    return loc_list;
+}
+
+/// Search GNU Global database for symbols defined in file.
+///
+/// # Arguments
+///
+/// + `cwd`: Workspace folder.
+/// + `doc_uri`: File uri.
+/// + `low_precision`: Do not find precise position, just point to begin of line.
+/// + `cache`: Cache of file contents for current request.
+async fn search_gtags(
+    cwd: &Url,
+    doc_uri: &Url,
+    low_precision: bool,
+    cache: &crate::cache::FileCache,
+) -> Result<Vec<SymbolInformation>, tower_lsp::jsonrpc::Error> {
+    let mut loc_list = Vec::<SymbolInformation>::new();
 
     let args = vec!["-x", "-f", doc_uri.path()];
-    let lines = crate::method::execute_and_split_lines("global", &cwd.uri, &args).await?;
+    let lines = crate::method::execute_and_split_lines("global", cwd, &args).await?;
 
     for line in lines {
         let (symbol_name, line_number, file_path, rest_string) = crate::method::parse_cxref(&line)?;
-        let file_path = crate::method::join_workspace_path(&cwd.uri, &file_path)?;
+        let file_path = crate::method::join_workspace_path(cwd, &file_path)?;
 
         let loc = crate::method::find_symbol_in_line(
             &file_path,
             line_number - 1,
             &symbol_name,
             low_precision,
+            cache,
         )
         .await?;
         let info = crate::method::create_symbol_information(symbol_name, loc, &rest_string);
         loc_list.push(info);
     }
 
-    return Ok(Some(DocumentSymbolResponse::Flat(loc_list)));
+    return Ok(loc_list);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Search symbols of a temporary C file.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Unique name of temporary file.
+    /// + `kinds`: Full names of ctags kinds to keep.
+    /// + `hide_anonymous`: Hide or rename anonymous types.
+    async fn symbols(name: &str, kinds: Option<&[&str]>, hide_anonymous: bool) -> Vec<String> {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = "int count;\nint add(int a, int b) {}\ntypedef struct {\n} point_t;\nstruct {\n} origin;\n";
+        std::fs::write(dir.join("a.c"), content).unwrap();
+
+        let lines = [
+            "count\ta.c\t1;\"\tv",
+            "add\ta.c\t2;\"\tf",
+            "__anon1\ta.c\t3;\"\ts",
+            "point_t\ta.c\t4;\"\tt\ttyperef:struct:__anon1",
+            "__anon2\ta.c\t5;\"\ts",
+        ];
+        let entries = lines
+            .iter()
+            .filter_map(|v| crate::tags::parse_line(v))
+            .collect();
+        let tags = crate::tags::TagsFile::new(dir.join("tags"), entries);
+
+        let kinds: Option<std::collections::HashSet<String>> =
+            kinds.map(|v| v.iter().map(|v| v.to_string()).collect());
+        let ret = search_tags(
+            &[std::sync::Arc::new(tags)],
+            &Url::from_file_path(dir.join("a.c")).unwrap(),
+            kinds.as_ref(),
+            &crate::TagsLspConfig::default(),
+            hide_anonymous,
+            &crate::cache::FileCache::new(encoding_rs::UTF_8),
+        )
+        .await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        return ret.into_iter().map(|v| v.name).collect();
+    }
+
+    #[tokio::test]
+    async fn filter_by_kind() {
+        let ret = symbols("kind-all", None, false).await;
+        assert_eq!(ret, ["count", "add", "__anon1", "point_t", "__anon2"]);
+
+        let ret = symbols("kind-function", Some(&["function"]), false).await;
+        assert_eq!(ret, ["add"]);
+    }
+
+    #[tokio::test]
+    async fn hide_anonymous_types() {
+        let ret = symbols("anonymous", Some(&["struct"]), false).await;
+        assert_eq!(ret, ["__anon1", "__anon2"]);
+
+        // Named by typedef, or dropped.
+        let ret = symbols("anonymous-hidden", Some(&["struct"]), true).await;
+        assert_eq!(ret, ["point_t"]);
+    }
 }
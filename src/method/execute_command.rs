@@ -0,0 +1,427 @@
+use tower_lsp::lsp_types::*;
+
+/// Command that dumps runtime state.
+pub const COMMAND_STATUS: &str = "tags-lsp.status";
+
+/// Command that regenerates tags files and reloads them.
+pub const COMMAND_REGENERATE: &str = "tags-lsp.regenerate";
+
+/// Command that builds configuration again.
+pub const COMMAND_RELOAD_CONFIG: &str = "tags-lsp.reloadConfig";
+
+/// Command that finds definitions of symbol by name.
+pub const COMMAND_FIND_SYMBOL: &str = "tags-lsp.findSymbol";
+
+/// All supported commands.
+pub const COMMAND_LIST: &[&str] = &[
+    COMMAND_STATUS,
+    COMMAND_REGENERATE,
+    COMMAND_RELOAD_CONFIG,
+    COMMAND_FIND_SYMBOL,
+];
+
+pub async fn execute_command(
+    backend: &crate::TagsLspBackend,
+    params: ExecuteCommandParams,
+) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+    match params.command.as_str() {
+        COMMAND_STATUS => return do_status(backend).await,
+        COMMAND_REGENERATE => return do_regenerate(backend).await,
+        COMMAND_RELOAD_CONFIG => return do_reload_config(backend, params.arguments).await,
+        COMMAND_FIND_SYMBOL => return do_find_symbol(backend, params.arguments).await,
+        _ => {
+            return Err(crate::error::TagsLspError::InvalidParams {
+                message: format!("unknown command `{}`", params.command),
+                data: Some(serde_json::json!({
+                    "command": params.command,
+                })),
+            }
+            .into())
+        }
+    }
+}
+
+/// Run ctags for all workspace folders that use it, then reload tags.
+///
+/// Tags watcher does not reload while regenerating, so half written tags
+/// files are never loaded.
+async fn do_regenerate(
+    backend: &crate::TagsLspBackend,
+) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+    let (folder_list, config, folder_configs, progress, guard) = {
+        let mut rt = backend.rt.lock().await;
+        if rt.regenerating {
+            return Err(crate::error::TagsLspError::Failed(String::from(
+                "regeneration in progress",
+            ))
+            .into());
+        }
+        rt.regenerating = true;
+        let guard = RegeneratingGuard {
+            rt: Some(backend.rt.clone()),
+        };
+
+        let folder_list: Vec<_> = rt
+            .workspace_folders
+            .iter()
+            .filter(|v| {
+                crate::index::detect(rt.folder_config(&v.uri), v)
+                    == crate::index::BackendKind::Ctags
+            })
+            .cloned()
+            .collect();
+        (
+            folder_list,
+            rt.config.clone(),
+            rt.folder_configs.clone(),
+            rt.work_done_progress,
+            guard,
+        )
+    };
+
+    tracing::info!("Regenerating tags for {} folders", folder_list.len());
+
+    for ele in &folder_list {
+        if let Ok(dir) = ele.uri.to_file_path() {
+            let folder_config = folder_configs.get(&ele.uri).unwrap_or(&config);
+            crate::method::initialized::generate_tags(
+                &backend.client,
+                folder_config,
+                &dir,
+                progress,
+            )
+            .await;
+        }
+    }
+    let tags = crate::method::initialized::load_tags(&config, &folder_list, &folder_configs).await;
+
+    let mut rt = backend.rt.lock().await;
+    guard.finish(&mut rt);
+    for tags_file in &tags {
+        crate::method::publish_tags_diagnostics(&backend.client, &rt.config, tags_file).await;
+    }
+    let entries: usize = tags.iter().map(|v| v.entries.len()).sum();
+    rt.tags.replace(&[], tags);
+
+    tracing::info!("Regenerated {} tags", entries);
+
+    return Ok(Some(serde_json::json!({
+        "entries": entries,
+    })));
+}
+
+/// Clear `regenerating` of Runtime if regeneration does not finish, e.g.
+/// client cancelled the request.
+struct RegeneratingGuard {
+    /// Runtime to clear, taken when regeneration finished.
+    rt: Option<std::sync::Arc<tokio::sync::Mutex<crate::Runtime>>>,
+}
+
+impl RegeneratingGuard {
+    /// Mark regeneration as finished.
+    ///
+    /// # Arguments
+    ///
+    /// + `rt`: The locked Runtime.
+    fn finish(mut self, rt: &mut crate::Runtime) {
+        rt.regenerating = false;
+        self.rt = None;
+    }
+}
+
+impl Drop for RegeneratingGuard {
+    fn drop(&mut self) {
+        let rt = match self.rt.take() {
+            Some(v) => v,
+            None => return,
+        };
+
+        tracing::debug!("Regeneration cut short: request cancelled");
+        if let Ok(mut v) = rt.try_lock() {
+            v.regenerating = false;
+            return;
+        }
+        tokio::spawn(async move {
+            rt.lock().await.regenerating = false;
+        });
+    }
+}
+
+/// Read `.tags-lsp.json` and build configuration again.
+///
+/// The first argument, if any, replaces initialization options. Tags files
+/// are reloaded if the list of them changed, other options apply to the
+/// next request.
+///
+/// # Returns
+///
+/// The effective configuration, of the first workspace folder if any.
+async fn do_reload_config(
+    backend: &crate::TagsLspBackend,
+    arguments: Vec<serde_json::Value>,
+) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+    let (config, folders, folder_configs, old_config, old_folder_configs) = {
+        let mut rt = backend.rt.lock().await;
+        if let Some(v) = arguments.into_iter().next() {
+            rt.init_options = Some(v);
+        }
+
+        let old_config = rt.config.clone();
+        let old_folder_configs = rt.folder_configs.clone();
+        crate::method::initialize::configure(&mut rt);
+        tracing::info!("Configuration reloaded");
+
+        (
+            rt.config.clone(),
+            rt.workspace_folders.clone(),
+            rt.folder_configs.clone(),
+            old_config,
+            old_folder_configs,
+        )
+    };
+
+    // Folders may name other tags files in `.tags-lsp.json`.
+    let tags_paths = |config, folder_configs| -> Vec<_> {
+        return crate::method::initialized::tags_paths(config, &folders, folder_configs)
+            .into_iter()
+            .map(|(v, _)| v)
+            .collect();
+    };
+    let stale = tags_paths(&old_config, &old_folder_configs);
+    let reload = stale != tags_paths(&config, &folder_configs) && config.single_file.is_none();
+
+    if reload {
+        let tags = crate::method::initialized::load_tags(&config, &folders, &folder_configs).await;
+
+        let mut rt = backend.rt.lock().await;
+        for tags_file in &tags {
+            crate::method::publish_tags_diagnostics(&backend.client, &rt.config, tags_file).await;
+        }
+        rt.tags.replace(&stale, tags);
+
+        // Old watchers would put tags files no longer configured back.
+        rt.watchers.clear();
+        crate::watch::watch_folders(&backend.client, &backend.rt, &mut rt, &folders);
+    }
+
+    let rt = backend.rt.lock().await;
+    let config = rt.default_config();
+    let loglevel = config.loglevel.unwrap_or_default();
+    return Ok(Some(serde_json::json!({
+        "tagsFiles": config.tags,
+        "ctagsBinary": config.ctags_bin(),
+        "ctagsArgs": config.ctags_args,
+        "logLevel": format!("{:?}", loglevel).to_lowercase(),
+        "maxResults": config.max_results(),
+        "autoGenerate": !config.no_auto_generate,
+        "tagsDiagnostics": !config.no_tags_diagnostics,
+        "updateOnSave": !config.no_update_on_save,
+        "documentSymbolKinds": rt.document_symbol_kinds,
+        "symbolDetailPath": rt.symbol_detail_path,
+        "hideAnonymous": rt.hide_anonymous,
+        "symbolSort": rt.symbol_sort,
+        "tagsReloaded": reload,
+    })));
+}
+
+/// Find definitions of the symbol given as the first argument, the same way
+/// as `textDocument/definition` does.
+///
+/// All workspace folders are searched since there is no document to pick
+/// one. Qualified names such as `Foo::bar` prefer tags in that scope.
+///
+/// # Returns
+///
+/// Array of locations.
+async fn do_find_symbol(
+    backend: &crate::TagsLspBackend,
+    arguments: Vec<serde_json::Value>,
+) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+    let symbol = match arguments.first().and_then(|v| v.as_str()) {
+        Some(v) if !v.is_empty() => v.to_string(),
+        _ => {
+            return Err(crate::error::TagsLspError::InvalidParams {
+                message: format!("`{}` expects a symbol name", COMMAND_FIND_SYMBOL),
+                data: Some(serde_json::json!({
+                    "arguments": arguments,
+                })),
+            }
+            .into())
+        }
+    };
+
+    let rt = backend.snapshot(None).await;
+
+    // Tags of `--single-file` are not in any workspace folder.
+    let mut folders = rt.workspace_folders.clone();
+    if folders.is_empty() {
+        let dir = rt.tags.first().and_then(|v| v.path.parent());
+        if let Some(uri) = dir.and_then(crate::tags::path_to_uri) {
+            folders.push(WorkspaceFolder {
+                uri,
+                name: String::new(),
+            });
+        }
+    }
+
+    let name = crate::method::tag_name_of(&symbol);
+    let qualifier = symbol
+        .rsplit_once("::")
+        .map(|(v, _)| v)
+        .filter(|v| !v.is_empty());
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let mut loc_list = Vec::<Location>::new();
+    for folder in &folders {
+        let index = crate::index::select(&rt, folder, &cache);
+        let found = match qualifier {
+            Some(v) => index.lookup_scoped(name, v, None).await?,
+            None => index.lookup(name, None).await?,
+        };
+
+        // Folders served by ctags share the same tags.
+        for loc in found {
+            if !loc_list.contains(&loc) {
+                loc_list.push(loc);
+            }
+        }
+    }
+
+    crate::method::limit_results(&mut loc_list, rt.config.max_results(), name);
+    crate::method::encode_locations(&mut loc_list, rt.position_utf16, &cache).await;
+    crate::method::canonicalize_locations(&mut loc_list, &rt.config).await;
+
+    return Ok(Some(serde_json::json!(loc_list)));
+}
+
+/// Summarize Runtime state.
+async fn do_status(
+    backend: &crate::TagsLspBackend,
+) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
+    let rt = backend.rt.lock().await;
+
+    let tags: Vec<serde_json::Value> = rt
+        .tags
+        .load()
+        .iter()
+        .map(|v| {
+            serde_json::json!({
+                "path": v.path,
+                "entries": v.entries.len(),
+                "meta": v.meta,
+            })
+        })
+        .collect();
+
+    return Ok(Some(serde_json::json!({
+        "name": rt.prog_name,
+        "version": rt.prog_version,
+        "ctags_bin": rt.config.ctags_bin(),
+        "ctags_args": rt.config.ctags_args,
+        "workspace_folders": rt.workspace_folders,
+        "tags": tags,
+    })));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn find_symbol_by_name() {
+        let (mut client, uri) = crate::testing::start().await;
+        let mut find = async |arguments: Vec<serde_json::Value>| {
+            let params = ExecuteCommandParams {
+                command: String::from(COMMAND_FIND_SYMBOL),
+                arguments,
+                work_done_progress_params: Default::default(),
+            };
+            return client.request::<request::ExecuteCommand>(params).await;
+        };
+
+        let ret = find(vec![serde_json::json!("add")]).await.unwrap();
+        let expect = Location::new(uri, Range::new(Position::new(0, 4), Position::new(0, 7)));
+        assert_eq!(ret, Some(serde_json::json!([expect])));
+
+        let ret = find(vec![serde_json::json!("missing")]).await.unwrap();
+        assert_eq!(ret, Some(serde_json::json!([])));
+
+        let e = find(Vec::new()).await.unwrap_err();
+        assert_eq!(e["code"], -32602);
+
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn reload_changed_config_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "tags-lsp-test-reload-config-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.c"), "int add;\nint sub;\n").unwrap();
+        std::fs::write(dir.join("tags-lsp-test-tags"), "add\ta.c\t1;\"\tv\n").unwrap();
+        std::fs::write(dir.join("other-tags"), "sub\ta.c\t2;\"\tv\n").unwrap();
+        let root = Url::from_directory_path(&dir).unwrap();
+
+        let mut client = crate::testing::Client::start(&[
+            "--no-auto-generate",
+            "--tags-filename",
+            "tags-lsp-test-tags",
+        ]);
+        client.initialize(&root).await;
+        let mut execute = async |command: &str, arguments: Vec<serde_json::Value>| {
+            let params = ExecuteCommandParams {
+                command: String::from(command),
+                arguments,
+                work_done_progress_params: Default::default(),
+            };
+            return client
+                .request::<request::ExecuteCommand>(params)
+                .await
+                .unwrap()
+                .unwrap();
+        };
+
+        let ret = execute(COMMAND_RELOAD_CONFIG, Vec::new()).await;
+        assert_eq!(ret["maxResults"], 100);
+        assert_eq!(ret["tagsReloaded"], false);
+
+        let config = r#"{ "maxResults": 5, "tagsFiles": ["other-tags"] }"#;
+        std::fs::write(dir.join(crate::config::CONFIG_FILENAME), config).unwrap();
+        let ret = execute(COMMAND_RELOAD_CONFIG, Vec::new()).await;
+        assert_eq!(ret["maxResults"], 5);
+        assert_eq!(ret["tagsFiles"], serde_json::json!(["other-tags"]));
+        assert_eq!(ret["tagsReloaded"], true);
+
+        let ret = execute(COMMAND_FIND_SYMBOL, vec![serde_json::json!("sub")]).await;
+        assert_eq!(ret.as_array().unwrap().len(), 1);
+        let ret = execute(COMMAND_FIND_SYMBOL, vec![serde_json::json!("add")]).await;
+        assert_eq!(ret, serde_json::json!([]));
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn clear_regenerating_when_cut_short() {
+        let service = crate::testing::backend();
+        let backend = service.inner();
+
+        backend.rt.lock().await.regenerating = true;
+        drop(RegeneratingGuard {
+            rt: Some(backend.rt.clone()),
+        });
+        assert!(!backend.rt.lock().await.regenerating);
+
+        // Dropped while Runtime is locked, e.g. waiting to publish tags.
+        let mut rt = backend.rt.lock().await;
+        rt.regenerating = true;
+        drop(RegeneratingGuard {
+            rt: Some(backend.rt.clone()),
+        });
+        drop(rt);
+        tokio::task::yield_now().await;
+        assert!(!backend.rt.lock().await.regenerating);
+    }
+}
@@ -0,0 +1,142 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn hover(
+    backend: &crate::TagsLspBackend,
+    params: HoverParams,
+) -> tower_lsp::jsonrpc::Result<Option<Hover>> {
+    /// The maximum number of definitions to show.
+    const HOVER_LIMIT: usize = 3;
+
+    let doc_uri = &params.text_document_position_params.text_document.uri;
+    let doc_pos = &params.text_document_position_params.position;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+    let symbol = crate::method::get_symbol_by_position(&rt, doc_uri, doc_pos).await?;
+    let language_id = crate::method::document_language_id(&rt, doc_uri);
+    let variants = crate::method::tag_name_variants(&symbol, &language_id);
+    let symbol = variants
+        .iter()
+        .find(|v| rt.tags.iter().any(|t| t.lookup(v).next().is_some()))
+        .unwrap_or(&variants[0]);
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let mut section_list = Vec::<String>::new();
+    for tags_file in rt.tags.iter() {
+        for entry in tags_file.lookup(symbol) {
+            if !crate::method::tag_target_exists(tags_file, entry).await {
+                continue;
+            }
+            let loc = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                false,
+                crate::tags::AddressResolution::Verify,
+                &cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let content = cache.read(loc.uri.path()).await?;
+            let line = match content.lines().nth(loc.range.start.line as usize) {
+                Some(v) => v.trim(),
+                None => continue,
+            };
+
+            let lang = crate::method::language_id_from_uri(&loc.uri).unwrap_or_default();
+            let kind = match &entry.kind {
+                Some(v) => crate::tags::kind_name(v),
+                None => "symbol",
+            };
+
+            section_list.push(format!(
+                "```{}\n{}\n```\n{} in `{}:{}`",
+                lang,
+                line,
+                kind,
+                loc.uri.path(),
+                loc.range.start.line + 1
+            ));
+
+            if section_list.len() >= HOVER_LIMIT {
+                break;
+            }
+        }
+
+        if section_list.len() >= HOVER_LIMIT {
+            break;
+        }
+    }
+
+    if section_list.is_empty() {
+        return Ok(None);
+    }
+
+    return Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: section_list.join("\n\n---\n\n"),
+        }),
+        range: None,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn hover_in_latin1_file() {
+        let dir = std::env::temp_dir().join(format!("tags-lsp-test-latin1-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (content, _, _) =
+            encoding_rs::WINDOWS_1252.encode("int size; /* Größe */\n\nint x = size;\n");
+        let (tags, _, _) =
+            encoding_rs::WINDOWS_1252.encode("size\ta.c\t/^int size; \\/* Größe *\\/$/;\"\tv\n");
+        std::fs::write(dir.join("a.c"), content).unwrap();
+        std::fs::write(dir.join("tags-lsp-test-tags"), tags).unwrap();
+
+        let args = [
+            "--no-auto-generate",
+            "--tags-filename",
+            "tags-lsp-test-tags",
+            "--file-encoding",
+            "latin1",
+        ];
+        let mut client = crate::testing::Client::start(&args);
+        client
+            .initialize(&Url::from_directory_path(&dir).unwrap())
+            .await;
+        let uri = Url::from_file_path(dir.join("a.c")).unwrap();
+
+        let params = GotoDefinitionParams {
+            text_document_position_params: crate::testing::position(&uri, 2, 9),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::GotoDefinition>(params)
+            .await
+            .unwrap();
+        let expect = Location::new(
+            uri.clone(),
+            Range::new(Position::new(0, 4), Position::new(0, 8)),
+        );
+        assert_eq!(ret, Some(GotoDefinitionResponse::Scalar(expect)));
+
+        let params = HoverParams {
+            text_document_position_params: crate::testing::position(&uri, 2, 9),
+            work_done_progress_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::HoverRequest>(params)
+            .await
+            .unwrap();
+        let text = serde_json::to_string(&ret.expect("no hover").contents).unwrap();
+        assert!(text.contains("int size; /* Größe */"), "{}", text);
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
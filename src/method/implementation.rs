@@ -4,6 +4,9 @@ pub async fn goto_implementation(
     backend: &crate::TagsLspBackend,
     params: request::GotoImplementationParams,
 ) -> tower_lsp::jsonrpc::Result<Option<request::GotoImplementationResponse>> {
-    let ret = crate::method::definition::goto_definition(backend, params).await?;
-    return Ok(ret);
+    let params = &params.text_document_position_params;
+    return crate::method::goto_symbol(backend, params, |index, name, _, lang| {
+        index.implementations(name, lang)
+    })
+    .await;
 }
@@ -1,5 +1,43 @@
 use tower_lsp::lsp_types::*;
 
+/// Options from `initializationOptions` of initialize request.
+///
+/// Command line arguments take precedence over these options.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InitOptions {
+    /// Same as `--tags`.
+    pub tags_files: Option<Vec<String>>,
+
+    /// Same as `--ctags-bin`.
+    pub ctags_binary: Option<String>,
+
+    /// Same as `--ctags-arg`.
+    pub ctags_args: Option<Vec<String>>,
+
+    /// Opposite of `--no-auto-generate`.
+    pub auto_generate: Option<bool>,
+
+    /// Opposite of `--no-tags-diagnostics`.
+    pub tags_diagnostics: Option<bool>,
+
+    /// Opposite of `--no-update-on-save`.
+    pub update_on_save: Option<bool>,
+
+    /// Ctags kinds to show in `documentSymbol`, either letters or full names.
+    pub document_symbol_kinds: Option<Vec<String>>,
+
+    /// Show `path:line` of `workspace/symbol` results.
+    pub symbol_detail_path: Option<bool>,
+
+    /// Hide or rename anonymous types in `workspace/symbol` and
+    /// `documentSymbol`, `true` by default.
+    pub hide_anonymous: Option<bool>,
+
+    /// Order of `workspace/symbol` results, `score` by default.
+    pub symbol_sort: Option<crate::method::workspace_symbol::SymbolSort>,
+}
+
 pub async fn do_initialize(
     backend: &crate::TagsLspBackend,
     params: InitializeParams,
@@ -7,41 +45,100 @@ pub async fn do_initialize(
     let mut rt = backend.rt.lock().await;
 
     copy_workspace_folder(&mut rt, &params);
-    if check_position_encoding_kind(&params) == false {
-        return Err(tower_lsp::jsonrpc::Error {
-            code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                crate::LspErrorCode::RequestFailed.code(),
-            ),
-            message: std::borrow::Cow::Borrowed("server only support utf-8 encoding"),
-            data: None,
-        });
+    if let Some(v) = params.trace {
+        crate::method::set_trace::apply_trace(backend, &mut rt, v);
     }
+    rt.init_options = params.initialization_options.clone();
+    configure(&mut rt);
+    rt.position_utf16 = !client_supports_utf8(&params);
+    rt.work_done_progress = params
+        .capabilities
+        .window
+        .as_ref()
+        .and_then(|v| v.work_done_progress)
+        .unwrap_or(false);
+
+    // GNU Global is only required by workspace folders that use it.
+    let require_gtags = rt.workspace_folders.iter().any(|v| {
+        crate::index::detect(rt.folder_config(&v.uri), v) == crate::index::BackendKind::Global
+    });
 
-    match check_executable_gtags().await {
+    match !require_gtags || check_executable("gtags").await {
         true => (),
         false => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("gtags: command not found"),
-                data: None,
-            })
+            return Err(crate::error::TagsLspError::Failed(String::from(
+                "gtags: command not found",
+            ))
+            .into())
         }
     }
 
+    let has_index = require_gtags || has_tags_index(&rt).await;
+    if !has_index {
+        backend
+            .client
+            .show_message(
+                MessageType::WARNING,
+                "No tags file found and none can be generated, symbol navigation is \
+                disabled. Install ctags and enable auto generation, or run `ctags -R` in \
+                workspace folder and restart the server.",
+            )
+            .await;
+    }
+
     return Ok(InitializeResult {
         server_info: Some(ServerInfo {
             name: rt.prog_name.clone(),
             version: Some(rt.prog_version.clone()),
         }),
-        capabilities: get_server_capacity(),
-        ..Default::default()
+        capabilities: get_server_capacity(rt.position_utf16, has_index),
     });
 }
 
-async fn check_executable_gtags() -> bool {
-    let output = tokio::process::Command::new("gtags")
+/// Whether tags file is found or can be generated by ctags.
+///
+/// Tags are never generated if `no_auto_generate` is set.
+///
+/// # Arguments
+///
+/// + `rt`: Runtime with configuration applied.
+async fn has_tags_index(rt: &crate::Runtime) -> bool {
+    if !rt.config.tags.is_empty() {
+        return true;
+    }
+
+    let found = rt.workspace_folders.iter().any(|v| {
+        let config = rt.folder_config(&v.uri);
+        return !config.tags.is_empty()
+            || v.uri.to_file_path().is_ok_and(|path| {
+                !crate::tags::discover_upward(&path, &config.tags_filename).is_empty()
+            });
+    });
+    if found {
+        return true;
+    }
+
+    // Nothing to load, tags may be generated by ctags of some folder.
+    if rt.workspace_folders.is_empty() {
+        return !rt.config.no_auto_generate && check_executable(rt.config.ctags_bin()).await;
+    }
+    for v in &rt.workspace_folders {
+        let config = rt.folder_config(&v.uri);
+        if !config.no_auto_generate && check_executable(config.ctags_bin()).await {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+/// Check if program can be executed.
+///
+/// # Arguments
+///
+/// + `program`: Program name or path.
+async fn check_executable(program: &str) -> bool {
+    let output = tokio::process::Command::new(program)
         .arg("--version")
         .output();
     let output = output.await;
@@ -59,45 +156,212 @@ async fn check_executable_gtags() -> bool {
 /// + `dst` - A mut reference to Runtime.
 /// + `src` - Reference to InitializeParams
 fn copy_workspace_folder(dst: &mut crate::Runtime, src: &InitializeParams) {
-    match &src.root_uri {
-        Some(value) => dst.workspace_folders.push(WorkspaceFolder {
+    if let Some(path) = &dst.config.single_file {
+        let dir = std::path::Path::new(path).parent();
+        if let Some(uri) = dir.and_then(crate::tags::path_to_uri) {
+            dst.workspace_folders = vec![WorkspaceFolder {
+                name: String::from(""),
+                uri,
+            }];
+        }
+        return;
+    }
+
+    if let Some(value) = &src.root_uri {
+        dst.workspace_folders.push(WorkspaceFolder {
             name: String::from(""),
             uri: value.clone(),
-        }),
-        None => (),
-    };
+        });
+    }
 
-    match &src.workspace_folders {
-        Some(value) => dst.workspace_folders = value.clone(),
-        None => (),
-    };
+    if let Some(value) = &src.workspace_folders {
+        dst.workspace_folders = value.clone();
+    }
 }
 
-fn check_position_encoding_kind(src: &InitializeParams) -> bool {
-    let kind_utf_8 = "utf-8";
-
-    match &src.capabilities.general {
-        Some(cap) => match &cap.position_encodings {
-            Some(kinds) => {
-                for ele in kinds {
-                    if ele.as_str() == kind_utf_8 {
-                        return true;
-                    }
-                }
-            }
-            None => (),
-        },
-        None => (),
+/// Build configuration from command line arguments, initialization options
+/// and `.tags-lsp.json`, in order of precedence.
+///
+/// # Arguments
+///
+/// + `rt`: Runtime with `cli_config` and `init_options` set.
+pub fn configure(rt: &mut crate::Runtime) {
+    rt.config = rt.cli_config.clone();
+    rt.document_symbol_kinds = None;
+    rt.symbol_detail_path = false;
+    rt.hide_anonymous = true;
+    rt.symbol_sort = Default::default();
+
+    if let Some(mut v) = parse_init_options(rt.init_options.clone()) {
+        rt.document_symbol_kinds = v.document_symbol_kinds.take().map(|list| {
+            list.iter()
+                .map(|kind| crate::tags::kind_name(kind).to_string())
+                .collect()
+        });
+        rt.symbol_detail_path = v.symbol_detail_path.unwrap_or(false);
+        rt.hide_anonymous = v.hide_anonymous.unwrap_or(true);
+        rt.symbol_sort = v.symbol_sort.unwrap_or_default();
+        apply_init_options(&mut rt.config, v);
+    }
+    apply_config_file(rt);
+}
+
+/// Parse `initializationOptions`.
+///
+/// Invalid options are ignored so that initialization does not fail.
+///
+/// # Arguments
+///
+/// + `value`: Initialization options from client.
+fn parse_init_options(value: Option<serde_json::Value>) -> Option<InitOptions> {
+    let value = value?;
+    if value.is_null() {
+        return None;
+    }
+
+    match serde_json::from_value::<InitOptions>(value) {
+        Ok(v) => return Some(v),
+        Err(e) => {
+            tracing::warn!("Ignore invalid initializationOptions: {}", e);
+            return None;
+        }
+    }
+}
+
+/// Fill configuration that not set by command line arguments.
+///
+/// # Arguments
+///
+/// + `config`: Configuration from command line arguments.
+/// + `options`: Initialization options.
+fn apply_init_options(config: &mut crate::TagsLspConfig, options: InitOptions) {
+    if config.tags.is_empty() {
+        config.tags = options.tags_files.unwrap_or_default();
+    }
+
+    if config.ctags_bin.is_none() {
+        config.ctags_bin = options.ctags_binary;
+    }
+
+    if config.ctags_args.is_empty() {
+        config.ctags_args = options.ctags_args.unwrap_or_default();
+    }
+
+    if !config.no_auto_generate {
+        config.no_auto_generate = !options.auto_generate.unwrap_or(true);
+    }
+
+    if !config.no_tags_diagnostics {
+        config.no_tags_diagnostics = !options.tags_diagnostics.unwrap_or(true);
+    }
+
+    if !config.no_update_on_save {
+        config.no_update_on_save = !options.update_on_save.unwrap_or(true);
+    }
+}
+
+/// Fill configuration from `.tags-lsp.json` of workspace folders.
+///
+/// Each folder gets its own configuration, the global configuration is kept
+/// for files outside of folders. Options that are not per folder, like log
+/// level, are taken from the first folder.
+///
+/// # Arguments
+///
+/// + `rt`: A mut reference to Runtime.
+fn apply_config_file(rt: &mut crate::Runtime) {
+    rt.folder_configs.clear();
+    if rt.config.single_file.is_some() {
+        return;
+    }
+
+    for folder in rt.workspace_folders.clone() {
+        let dir = match folder.uri.to_file_path() {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let mut config = rt.config.clone();
+        if let Some(v) = crate::config::load(&dir) {
+            v.apply(&mut config);
+        }
+        rt.folder_configs.insert(folder.uri, config);
+    }
+
+    if rt.config.loglevel.is_some() {
+        return;
+    }
+
+    // Log level from configuration file takes effect now.
+    let loglevel = rt
+        .workspace_folders
+        .first()
+        .and_then(|v| rt.folder_configs.get(&v.uri))
+        .and_then(|v| v.loglevel);
+    if let (Some(level), Some(handle)) = (loglevel, &rt.log_handle) {
+        if let Err(e) = handle.modify(|filter| *filter = level.level_filter()) {
+            tracing::warn!("Cannot change log level: {}", e);
+        }
+    }
+    rt.config.loglevel = loglevel;
+}
+
+/// Whether client accepts UTF-8 positions.
+///
+/// UTF-16 is used otherwise, which every client must support.
+///
+/// # Arguments
+///
+/// + `src`: Initialize parameters.
+fn client_supports_utf8(src: &InitializeParams) -> bool {
+    if let Some(cap) = &src.capabilities.general {
+        if let Some(kinds) = &cap.position_encodings {
+            return kinds.contains(&PositionEncodingKind::UTF8);
+        }
     }
 
     return false;
 }
 
 /// Get server capacity
-fn get_server_capacity() -> ServerCapabilities {
-    return ServerCapabilities {
-        position_encoding: Some(PositionEncodingKind::UTF8),
-        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+///
+/// # Arguments
+///
+/// + `utf16`: Whether positions are UTF-16 offsets.
+/// + `has_index`: Whether symbol index is available, only features that work
+///   on document content are advertised if not.
+fn get_server_capacity(utf16: bool, has_index: bool) -> ServerCapabilities {
+    let position_encoding = match utf16 {
+        true => PositionEncodingKind::UTF16,
+        false => PositionEncodingKind::UTF8,
+    };
+
+    let capabilities = ServerCapabilities {
+        position_encoding: Some(position_encoding),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::INCREMENTAL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(true),
+        }),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec![String::from("(")]),
+            retrigger_characters: Some(vec![String::from(",")]),
+            ..Default::default()
+        }),
         completion_provider: Some(CompletionOptions {
             ..Default::default()
         }),
@@ -121,10 +385,17 @@ fn get_server_capacity() -> ServerCapabilities {
             resolve_provider: Some(false),
         })),
         declaration_provider: Some(DeclarationCapability::Simple(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: crate::method::execute_command::COMMAND_LIST
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+            ..Default::default()
+        }),
         workspace: Some(WorkspaceServerCapabilities {
             workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                 supported: Some(true),
-                change_notifications: Some(OneOf::Left(false)),
+                change_notifications: Some(OneOf::Left(true)),
             }),
             file_operations: Some(WorkspaceFileOperationsServerCapabilities {
                 did_rename: Some(FileOperationRegistrationOptions {
@@ -150,4 +421,148 @@ fn get_server_capacity() -> ServerCapabilities {
         }),
         ..ServerCapabilities::default()
     };
+
+    // Navigation would always fail.
+    if !has_index {
+        return ServerCapabilities {
+            position_encoding: capabilities.position_encoding,
+            text_document_sync: capabilities.text_document_sync,
+            selection_range_provider: capabilities.selection_range_provider,
+            document_highlight_provider: capabilities.document_highlight_provider,
+            document_link_provider: capabilities.document_link_provider,
+            execute_command_provider: capabilities.execute_command_provider,
+            workspace: capabilities.workspace,
+            ..ServerCapabilities::default()
+        };
+    }
+
+    return capabilities;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reduced_capabilities_without_index() {
+        let cap = get_server_capacity(true, false);
+        assert!(cap.definition_provider.is_none());
+        assert!(cap.references_provider.is_none());
+        assert!(cap.workspace_symbol_provider.is_none());
+        assert!(cap.document_highlight_provider.is_some());
+        assert!(cap.text_document_sync.is_some());
+
+        let cap = get_server_capacity(true, true);
+        assert!(cap.definition_provider.is_some());
+        assert!(cap.references_provider.is_some());
+    }
+
+    #[tokio::test]
+    async fn no_index_in_empty_workspace_without_auto_generate() {
+        let dir = std::env::temp_dir().join("tags-lsp-test-empty-workspace");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut rt = crate::Runtime::default();
+        rt.config.no_auto_generate = true;
+        // Not found in parent directories either.
+        rt.config.tags_filename = vec![String::from("tags-lsp-test-missing")];
+        rt.workspace_folders = vec![WorkspaceFolder {
+            uri: Url::from_directory_path(&dir).unwrap(),
+            name: String::from("empty"),
+        }];
+        assert!(!has_tags_index(&rt).await);
+
+        rt.config.tags = vec![String::from("tags")];
+        assert!(has_tags_index(&rt).await);
+    }
+
+    #[test]
+    fn config_of_each_folder() {
+        let root =
+            std::env::temp_dir().join(format!("tags-lsp-test-folders-{}", std::process::id()));
+        let folders = [
+            ("c", r#"{"ctagsArgs": ["--c-kinds=+p"], "maxResults": 10}"#),
+            ("py", r#"{"ctagsArgs": ["--python-kinds=-i"]}"#),
+            ("plain", ""),
+        ];
+        let mut rt = crate::Runtime::default();
+        rt.config.ctags_bin = Some(String::from("/usr/bin/ctags"));
+        for (name, content) in folders {
+            let dir = root.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            if !content.is_empty() {
+                std::fs::write(dir.join(crate::config::CONFIG_FILENAME), content).unwrap();
+            }
+            rt.workspace_folders.push(WorkspaceFolder {
+                uri: Url::from_directory_path(&dir).unwrap(),
+                name: String::from(name),
+            });
+        }
+        apply_config_file(&mut rt);
+
+        let config = |path: &str| rt.folder_config(&Url::from_file_path(root.join(path)).unwrap());
+        assert_eq!(config("c/main.c").ctags_args, ["--c-kinds=+p"]);
+        assert_eq!(config("c/main.c").max_results(), 10);
+        assert_eq!(config("py/src/main.py").ctags_args, ["--python-kinds=-i"]);
+        assert!(config("plain/a.c").ctags_args.is_empty());
+
+        // Command line arguments are defaults of every folder.
+        for path in ["c/main.c", "py/main.py", "plain/a.c"] {
+            assert_eq!(config(path).ctags_bin(), "/usr/bin/ctags", "{}", path);
+        }
+        let other = rt.folder_config(&Url::parse("file:///elsewhere/a.c").unwrap());
+        assert!(std::ptr::eq(other, &rt.config));
+        assert!(rt.config.ctags_args.is_empty());
+        assert!(rt.config.max_results.is_none());
+
+        // Requests without document use the first folder.
+        assert_eq!(rt.snapshot(None).config.max_results(), 10);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn generate_with_config_of_each_folder() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Fake ctags records its arguments and writes one tag.
+        let root =
+            std::env::temp_dir().join(format!("tags-lsp-test-generate-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let ctags = root.join("ctags");
+        let script = "#!/bin/sh\n\
+                      [ \"$1\" = --version ] && exit 0\n\
+                      cat > /dev/null\n\
+                      echo \"$@\" > ctags-args\n\
+                      printf 'main\\ta.c\\t1;\"\\tf\\n' > \"$2\"\n";
+        std::fs::write(&ctags, script).unwrap();
+        std::fs::set_permissions(&ctags, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut folders = Vec::new();
+        for (name, arg) in [("c", "--c-kinds=+p"), ("py", "--python-kinds=-i")] {
+            let dir = root.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("a.c"), "int main(void) {}\n").unwrap();
+            let config = serde_json::json!({
+                "ctagsBinary": ctags,
+                "ctagsArgs": [arg],
+            });
+            std::fs::write(dir.join(crate::config::CONFIG_FILENAME), config.to_string()).unwrap();
+            folders.push(Url::from_directory_path(&dir).unwrap());
+        }
+
+        let mut client = crate::testing::Client::start(&["--tags-filename", "tags-lsp-test-tags"]);
+        // Index is available with ctags of folders, even without `ctags` in PATH.
+        let ret = client.initialize_folders(&folders).await;
+        assert!(ret.capabilities.definition_provider.is_some());
+
+        for (name, arg) in [("c", "--c-kinds=+p"), ("py", "--python-kinds=-i")] {
+            let args = std::fs::read_to_string(root.join(name).join("ctags-args")).unwrap();
+            assert!(args.split_whitespace().any(|v| v == arg), "{}", args);
+        }
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -6,29 +6,429 @@ pub async fn do_initialized(backend: &crate::TagsLspBackend, _: InitializedParam
         .log_message(MessageType::INFO, "server initialized!")
         .await;
 
-    let rt = backend.rt.lock().await;
-    for ele in &rt.workspace_folders {
-        let path = ele.uri.clone();
-        let client = backend.client.clone();
-        let token = match client.next_request_id() {
-            tower_lsp::jsonrpc::Id::Number(v) => v as i32,
-            _ => panic!("unexcept type."),
+    // Requests are served with the tags loaded so far.
+    backend
+        .initialized
+        .store(true, std::sync::atomic::Ordering::Release);
+
+    let client = backend.client.clone();
+    let shared = backend.rt.clone();
+    let task = tokio::task::spawn(async move {
+        load_workspace(&client, &shared).await;
+    });
+    backend.rt.lock().await.track_task(task.abort_handle());
+}
+
+/// Generate and load tags of workspace folders, then watch them.
+///
+/// # Arguments
+///
+/// + `client`: LSP client.
+/// + `shared`: Runtime to update.
+async fn load_workspace(
+    client: &tower_lsp::Client,
+    shared: &std::sync::Arc<tokio::sync::Mutex<crate::Runtime>>,
+) {
+    let (folder_list, config, folder_configs, progress) = {
+        let rt = shared.lock().await;
+        let folder_list: Vec<_> = rt
+            .workspace_folders
+            .iter()
+            .map(|v| {
+                let config = rt.folder_config(&v.uri).clone();
+                (v.clone(), crate::index::detect(&config, v), config)
+            })
+            .collect();
+        (
+            folder_list,
+            rt.config.clone(),
+            rt.folder_configs.clone(),
+            rt.work_done_progress,
+        )
+    };
+
+    if let Some(path) = &config.single_file {
+        let tags = load_single_file(&config, std::path::Path::new(path)).await;
+        shared.lock().await.tags.store(tags);
+        return;
+    }
+
+    // Another client of this server may have loaded tags of some folders.
+    let all_folders: Vec<_> = folder_list.iter().map(|(v, _, _)| v.clone()).collect();
+    let loaded = shared.lock().await.tags.load();
+    let mut folder_list = folder_list;
+    if !loaded.is_empty() {
+        tracing::info!("Reuse {} tags files loaded by another client", loaded.len());
+        match config.tags.is_empty() {
+            true => folder_list.retain(|(v, _, config)| !is_folder_loaded(v, config, &loaded)),
+            false => folder_list.clear(),
+        }
+    }
+
+    let mut task_list = Vec::new();
+    for (ele, kind, folder_config) in &folder_list {
+        let dir = match ele.uri.to_file_path() {
+            Ok(v) => v,
+            Err(_) => continue,
         };
 
-        tokio::task::spawn(async move {
-            do_update_gtags(&client, token, path).await;
-        });
+        match kind {
+            // Keep GNU Global database up to date.
+            crate::index::BackendKind::Global => {
+                let path = ele.uri.clone();
+                let client = client.clone();
+                let token = match client.next_request_id() {
+                    tower_lsp::jsonrpc::Id::Number(v) => v as i32,
+                    _ => panic!("unexcept type."),
+                };
+
+                let task = tokio::task::spawn(async move {
+                    do_update_gtags(&client, token, path).await;
+                });
+                task_list.push(task.abort_handle());
+            }
+            crate::index::BackendKind::Ctags => {
+                if !folder_config.no_auto_generate
+                    && folder_config.tags.is_empty()
+                    && crate::tags::discover_upward(&dir, &folder_config.tags_filename).is_empty()
+                {
+                    generate_tags(client, folder_config, &dir, progress).await;
+                }
+            }
+        }
+    }
+
+    let folders: Vec<_> = folder_list.into_iter().map(|(v, _, _)| v).collect();
+    let tags = match folders.is_empty() && !loaded.is_empty() {
+        true => Vec::new(),
+        false => load_tags(&config, &folders, &folder_configs).await,
+    };
+
+    let mut rt = shared.lock().await;
+    for task in task_list {
+        rt.track_task(task);
+    }
+
+    for tags_file in &tags {
+        crate::method::publish_tags_diagnostics(client, &rt.config, tags_file).await;
+    }
+
+    rt.tags.update(|v| {
+        for tags_file in tags {
+            if !v.iter().any(|t| t.path == tags_file.path) {
+                v.push(std::sync::Arc::new(tags_file));
+            }
+        }
+    });
+    if config.prefetch {
+        let task = crate::prefetch::spawn_prefetch(rt.tags.load(), config.prefetch_budget);
+        rt.track_task(task);
+    }
+    crate::watch::watch_folders(client, shared, &mut rt, &all_folders);
+}
+
+/// Whether tags files of workspace folder are all loaded.
+///
+/// A folder without tags file is not loaded, its tags may be generated.
+///
+/// # Arguments
+///
+/// + `folder`: Workspace folder.
+/// + `config`: Configuration of the folder.
+/// + `loaded`: Loaded tags files.
+fn is_folder_loaded(
+    folder: &WorkspaceFolder,
+    config: &crate::TagsLspConfig,
+    loaded: &[std::sync::Arc<crate::tags::TagsFile>],
+) -> bool {
+    let dir = match folder.uri.to_file_path() {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+
+    let paths = crate::tags::discover_upward(&dir, &config.tags_filename);
+    return !paths.is_empty() && paths.iter().all(|v| loaded.iter().any(|t| t.path == *v));
+}
+
+/// Generate `tags` file for workspace folder.
+///
+/// Progress is reported while ctags is running, or a message is shown if
+/// client does not support progress. Failures are reported to client instead
+/// of abort, so that server keeps serving without generated tags.
+///
+/// # Arguments
+///
+/// + `client`: LSP client.
+/// + `config`: Configuration.
+/// + `dir`: Workspace folder path.
+/// + `progress`: Client supports work done progress.
+pub async fn generate_tags(
+    client: &tower_lsp::Client,
+    config: &crate::TagsLspConfig,
+    dir: &std::path::Path,
+    progress: bool,
+) {
+    tracing::info!("Generating tags for {}", dir.display());
+
+    let message = dir.display().to_string();
+    let token = match progress {
+        true => match client.next_request_id() {
+            tower_lsp::jsonrpc::Id::Number(v) => Some(v as i32),
+            _ => None,
+        },
+        false => None,
+    };
+    let token = match token {
+        Some(v) if do_work_done_progress_create(client, v).await => Some(v),
+        _ => None,
+    };
+
+    match token {
+        Some(v) => {
+            let title = String::from("Generating tags");
+            do_notification_progress_begin(client, v, title, message.clone(), None).await;
+        }
+        None => {
+            client
+                .show_message(
+                    MessageType::INFO,
+                    format!("Generating tags for {}", message),
+                )
+                .await;
+        }
+    }
+
+    let task = crate::ctags::run_ctags(
+        &crate::ctags::SystemRunner,
+        config.ctags_bin(),
+        dir,
+        config.tags_filename(),
+        config.no_ignore,
+        &config.ctags_args,
+    );
+    tokio::pin!(task);
+
+    // ctags does not tell how far it goes, so report elapsed time.
+    let start = std::time::Instant::now();
+    let mut ticker = tokio::time::interval(tokio::time::Duration::from_secs(1));
+    ticker.tick().await;
+    let ret = loop {
+        tokio::select! {
+            ret = &mut task => break ret,
+            _ = ticker.tick() => {
+                if let Some(v) = token {
+                    let elapsed = start.elapsed().as_secs();
+                    let message = format!("{} ({}s)", message, elapsed);
+                    do_notification_progress_report(client, v, message, None).await;
+                }
+            }
+        }
+    };
+
+    if let Some(v) = token {
+        do_notification_progress_end(client, v).await;
+    }
+
+    match ret {
+        Ok(v) => tracing::info!("Generated {}", v.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::warn!("Generate tags for {} skipped: {}", dir.display(), e);
+            client
+                .show_message(MessageType::ERROR, format!("Cannot generate tags: {}", e))
+                .await;
+        }
+        Err(e) => {
+            tracing::warn!("Generate tags for {} failed: {}", dir.display(), e);
+            client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("Generate tags for {} failed: {}", dir.display(), e),
+                )
+                .await;
+        }
+    }
+}
+
+/// Load tags files.
+///
+/// Files are parsed concurrently.
+///
+/// # Arguments
+///
+/// + `config`: Configuration.
+/// + `folders`: Workspace folders.
+/// + `folder_configs`: Configuration of each folder, `config` is used for
+///   folders not in it.
+pub async fn load_tags(
+    config: &crate::TagsLspConfig,
+    folders: &[WorkspaceFolder],
+    folder_configs: &std::collections::HashMap<Url, crate::TagsLspConfig>,
+) -> Vec<crate::tags::TagsFile> {
+    let path_list = tags_paths(config, folders, folder_configs);
+
+    let start = std::time::Instant::now();
+    let task_list = path_list.into_iter().map(|(v, config)| {
+        let limit = config.sorted_index_threshold();
+        let encoding = config.file_encoding();
+        tokio::task::spawn_blocking(move || load_tags_file(&v, limit, encoding))
+    });
+    let ret: Vec<_> = futures::future::join_all(task_list)
+        .await
+        .into_iter()
+        .filter_map(|v| v.ok().flatten())
+        .collect();
+
+    tracing::info!("Loaded {} tags files in {:?}", ret.len(), start.elapsed());
+    return ret;
+}
+
+/// Find tags files to load, with configuration of each one.
+///
+/// Tags files listed by `--tags` are used if any, otherwise `tagsFiles` of
+/// `.tags-lsp.json` or `tags` files found from each workspace folder, with
+/// configuration of the folder.
+///
+/// # Arguments
+///
+/// + `config`: Configuration.
+/// + `folders`: Workspace folders.
+/// + `folder_configs`: Configuration of each folder, `config` is used for
+///   folders not in it.
+pub fn tags_paths<'a>(
+    config: &'a crate::TagsLspConfig,
+    folders: &[WorkspaceFolder],
+    folder_configs: &'a std::collections::HashMap<Url, crate::TagsLspConfig>,
+) -> Vec<(std::path::PathBuf, &'a crate::TagsLspConfig)> {
+    let mut path_list = Vec::new();
+    if !config.tags.is_empty() {
+        for path in &config.tags {
+            let path = std::path::absolute(path).unwrap_or(path.into());
+            path_list.push((path, config));
+        }
+    } else {
+        for ele in folders {
+            let dir = match ele.uri.to_file_path() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let folder_config = folder_configs.get(&ele.uri).unwrap_or(config);
+            let found = match folder_config.tags.is_empty() {
+                true => crate::tags::discover_upward(&dir, &folder_config.tags_filename),
+                false => folder_config.tags.iter().map(|v| dir.join(v)).collect(),
+            };
+            for v in found {
+                // Nested folders may share tags file of a parent directory.
+                if !path_list.iter().any(|(path, _)| *path == v) {
+                    path_list.push((v, folder_config));
+                }
+            }
+        }
+    }
+
+    return path_list;
+}
+
+/// Generate tags for file of `--single-file`.
+///
+/// The tags are kept in memory as if they were read from `tags` file next to
+/// source file, nothing is written to disk.
+///
+/// # Arguments
+///
+/// + `config`: Configuration.
+/// + `path`: Absolute path of source file.
+async fn load_single_file(
+    config: &crate::TagsLspConfig,
+    path: &std::path::Path,
+) -> Vec<crate::tags::TagsFile> {
+    let dir = path.parent().unwrap_or(std::path::Path::new("/"));
+
+    let ctags_bin = config.ctags_bin();
+    match crate::ctags::run_ctags_file(
+        &crate::ctags::SystemRunner,
+        ctags_bin,
+        dir,
+        path,
+        &config.ctags_args,
+        config.file_encoding(),
+    )
+    .await
+    {
+        Ok(v) => {
+            tracing::info!("Loaded {} tags from {}", v.len(), path.display());
+            let tags_path = dir.join(crate::tags::TAGS_FILENAME);
+            return vec![crate::tags::TagsFile::new(tags_path, v)];
+        }
+        Err(e) => {
+            tracing::warn!("Generate tags for {} failed: {}", path.display(), e);
+            return Vec::new();
+        }
+    }
+}
+
+/// Load `tags` files of workspace folder.
+///
+/// Tags files are searched from the folder root upward, see
+/// [`crate::tags::discover_upward`].
+///
+/// # Arguments
+///
+/// + `config`: Configuration.
+/// + `folder`: Workspace folder.
+pub fn load_folder_tags(
+    config: &crate::TagsLspConfig,
+    folder: &WorkspaceFolder,
+) -> Vec<crate::tags::TagsFile> {
+    let dir = match folder.uri.to_file_path() {
+        Ok(v) => v,
+        Err(_) => return Vec::new(),
+    };
+
+    return crate::tags::discover_upward(&dir, &config.tags_filename)
+        .iter()
+        .filter_map(|v| load_tags_file(v, config.sorted_index_threshold(), config.file_encoding()))
+        .collect();
+}
+
+/// Load tags file.
+///
+/// # Arguments
+///
+/// + `path`: Path to tags file.
+/// + `hash_index_limit`: See [`crate::tags::parse`].
+/// + `encoding`: Encoding of source files.
+pub fn load_tags_file(
+    path: &std::path::Path,
+    hash_index_limit: usize,
+    encoding: &'static encoding_rs::Encoding,
+) -> Option<crate::tags::TagsFile> {
+    let start = std::time::Instant::now();
+    match crate::tags::parse(path, hash_index_limit, encoding) {
+        Ok(v) => {
+            tracing::info!(
+                "Loaded {} tags from {} in {:?}",
+                v.entries.len(),
+                path.display(),
+                start.elapsed()
+            );
+            return Some(v);
+        }
+        Err(e) => {
+            tracing::warn!("Cannot load {}: {}", path.display(), e);
+            return None;
+        }
     }
 }
 
 async fn do_update_gtags(client: &tower_lsp::Client, token: i32, path: Url) {
-    do_work_done_progress_create(&client, token).await;
+    do_work_done_progress_create(client, token).await;
 
     do_notification_progress_begin(
-        &client,
+        client,
         token,
         String::from("Indexing"),
         String::from(path.path()),
+        Some(0),
     )
     .await;
 
@@ -38,36 +438,40 @@ async fn do_update_gtags(client: &tower_lsp::Client, token: i32, path: Url) {
         .stdin(std::process::Stdio::null())
         .stdout(std::process::Stdio::null())
         .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
         .spawn()
         .expect("failed to spawn `gtags -i`");
 
     for i in 1..=99 {
-        do_notification_progress_report(&client, token, String::from(path.path()), i).await;
+        do_notification_progress_report(client, token, String::from(path.path()), Some(i)).await;
 
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
 
         match child.try_wait() {
-            Ok(v) => match v {
-                Some(_) => break,
-                None => (),
-            },
+            Ok(Some(_)) => break,
+            Ok(None) => (),
             Err(_) => break,
         }
     }
 
     child.wait().await.expect("wait child failed.");
 
-    do_notification_progress_report(&client, token, String::from(path.path()), 100).await;
-    do_notification_progress_end(&client, token).await;
+    do_notification_progress_report(client, token, String::from(path.path()), Some(100)).await;
+    do_notification_progress_end(client, token).await;
 }
 
-async fn do_work_done_progress_create(client: &tower_lsp::Client, token: i32) {
-    client
+/// Ask client to create progress, return whether it succeeds.
+async fn do_work_done_progress_create(client: &tower_lsp::Client, token: i32) -> bool {
+    let ret = client
         .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
             token: ProgressToken::Number(token),
         })
-        .await
-        .expect("WorkDoneProgressCreate failed");
+        .await;
+
+    if let Err(e) = &ret {
+        tracing::warn!("WorkDoneProgressCreate failed: {}", e);
+    }
+    return ret.is_ok();
 }
 
 /// Start progress reporting
@@ -76,14 +480,15 @@ async fn do_notification_progress_begin(
     token: i32,
     title: String,
     message: String,
+    percentage: Option<u32>,
 ) {
     client
         .send_notification::<notification::Progress>(ProgressParams {
             token: ProgressToken::Number(token),
             value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(WorkDoneProgressBegin {
-                title: title,
+                title,
                 message: Some(message),
-                percentage: Some(0),
+                percentage,
                 ..Default::default()
             })),
         })
@@ -95,7 +500,7 @@ async fn do_notification_progress_report(
     client: &tower_lsp::Client,
     token: i32,
     message: String,
-    percentage: u32,
+    percentage: Option<u32>,
 ) {
     client
         .send_notification::<notification::Progress>(ProgressParams {
@@ -104,7 +509,7 @@ async fn do_notification_progress_report(
                 WorkDoneProgressReport {
                     cancellable: Some(false),
                     message: Some(message),
-                    percentage: Some(percentage),
+                    percentage,
                 },
             )),
         })
@@ -122,3 +527,82 @@ async fn do_notification_progress_end(client: &tower_lsp::Client, token: i32) {
         })
         .await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn load_tags_of_several_folders() {
+        let dir = std::env::temp_dir().join(format!("tags-lsp-test-load-{}", std::process::id()));
+        let config = crate::TagsLspConfig {
+            tags_filename: vec![String::from("tags-lsp-test-tags")],
+            ..Default::default()
+        };
+
+        let mut folders = Vec::new();
+        for name in ["a", "b", "c", "a/nested"] {
+            let path = dir.join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            if name != "a/nested" {
+                let content = format!("{}_1\t{}.c\t1\n{}_2\t{}.c\t2\n", name, name, name, name);
+                std::fs::write(path.join("tags-lsp-test-tags"), content).unwrap();
+            }
+            folders.push(WorkspaceFolder {
+                uri: Url::from_file_path(&path).unwrap(),
+                name: String::from(name),
+            });
+        }
+
+        let ret = load_tags(&config, &folders, &Default::default()).await;
+        let mut names: Vec<_> = ret
+            .iter()
+            .flat_map(|v| v.entries.iter().map(|v| v.name.as_str()))
+            .collect();
+        names.sort();
+
+        // Nested folder shares tags file of its parent.
+        assert_eq!(ret.len(), 3);
+        assert_eq!(names, ["a_1", "a_2", "b_1", "b_2", "c_1", "c_2"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn resolve_symbol_in_single_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Fake ctags prints tags of fixture workspace.
+        let dir = std::env::temp_dir().join(format!("tags-lsp-test-single-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ctags = dir.join("ctags");
+        std::fs::write(&ctags, "#!/bin/sh\ncat tags\n").unwrap();
+        std::fs::set_permissions(&ctags, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let uri = crate::testing::fixture().join("main.c").unwrap();
+        let path = uri.to_file_path().unwrap();
+        let mut client = crate::testing::Client::start(&[
+            "--single-file",
+            &path.to_string_lossy(),
+            "--ctags-bin",
+            &ctags.to_string_lossy(),
+        ]);
+        client.initialize(&crate::testing::fixture()).await;
+
+        let params = GotoDefinitionParams {
+            text_document_position_params: crate::testing::position(&uri, 7, 12),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::GotoDefinition>(params)
+            .await
+            .unwrap();
+        let expect = Location::new(uri, Range::new(Position::new(0, 4), Position::new(0, 7)));
+        assert_eq!(ret, Some(GotoDefinitionResponse::Scalar(expect)));
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,15 +1,28 @@
+pub mod call_hierarchy;
+pub mod code_lens;
 pub mod completion;
+pub mod declaration;
 pub mod definition;
 pub mod did_change;
 pub mod did_close;
 pub mod did_open;
+pub mod did_save;
+pub mod document_highlight;
+pub mod document_link;
 pub mod document_symbol;
+pub mod execute_command;
+pub mod hover;
 pub mod implementation;
 pub mod initialize;
 pub mod initialized;
 pub mod references;
-pub mod symbol;
+pub mod selection_range;
+pub mod set_trace;
+pub mod signature_help;
+pub mod timing;
 pub mod type_definition;
+pub mod workspace;
+pub mod workspace_symbol;
 
 use regex::Regex;
 use tokio::io::AsyncReadExt;
@@ -23,82 +36,352 @@ lazy_static::lazy_static! {
     /// Pattern for matching symbol.
     static ref RE_SYMBOL: Regex = Regex::new(r"[_0-9a-zA-Z]+").unwrap();
 
+    /// Pattern for matching Lisp symbol, which allows `-` and friends.
+    static ref RE_SYMBOL_LISP: Regex = Regex::new(r"[-_0-9a-zA-Z*+!?<>=/]+").unwrap();
+
+    /// Pattern for matching PHP symbol, variables start with `$`.
+    static ref RE_SYMBOL_PHP: Regex = Regex::new(r"\$?[_0-9a-zA-Z]+").unwrap();
+
+    /// Pattern for matching C++ symbol, including scope qualifiers and
+    /// destructors.
+    static ref RE_SYMBOL_CPP: Regex = Regex::new(r"~?[_0-9a-zA-Z]+(::~?[_0-9a-zA-Z]+)*").unwrap();
+
     /// Pattern for matching C macro.
     static ref RE_MACRO: Regex = Regex::new(r"#\s*define").unwrap();
 }
 
+/// Get LSP language id of file from its extension.
+///
+/// # Arguments
+///
+/// + `uri`: File uri.
+pub fn language_id_from_uri(uri: &Url) -> Option<String> {
+    let path = std::path::Path::new(uri.path());
+    return crate::tags::language_id_from_path(path).map(String::from);
+}
+
+/// Get LSP language id of the requested document.
+///
+/// The language id sent by client is preferred, and the file extension is
+/// used if client did not send one.
+///
+/// # Arguments
+///
+/// + `rt`: Snapshot of Runtime taken for `uri`.
+/// + `uri`: Document uri.
+pub fn document_language_id(rt: &crate::Snapshot, uri: &Url) -> String {
+    if let Some(v) = &rt.language_id {
+        return v.clone();
+    }
+
+    return language_id_from_uri(uri).unwrap_or_default();
+}
+
+/// Get ctags language name of the requested document.
+///
+/// The language id sent by client is preferred over the file extension, so
+/// that a `.h` file opened as `cpp` is looked up as C++.
+///
+/// # Arguments
+///
+/// + `rt`: Snapshot of Runtime taken for `uri`.
+/// + `uri`: Document uri.
+pub fn document_language(rt: &crate::Snapshot, uri: &Url) -> Option<&'static str> {
+    let path = std::path::Path::new(uri.path());
+    let from_path = crate::tags::language_from_path(path);
+
+    let id = match &rt.language_id {
+        Some(v) => v,
+        None => return from_path,
+    };
+    // Dialects share a language id, the extension tells which one.
+    if crate::tags::language_id_from_path(path) == Some(id.as_str()) {
+        return from_path;
+    }
+
+    return crate::tags::language_from_id(id).or(from_path);
+}
+
 /// Find workspace folder that contains this file.
 ///
 /// # Arguments
 ///
 /// + `workspace_folders`: Workspace folder list.
 /// + `uri`: File uri
+/// + `ignore_case`: Compare file paths case-insensitively.
 pub fn find_belong_workspace_folder(
     workspace_folders: &Vec<WorkspaceFolder>,
     uri: &Url,
+    ignore_case: bool,
 ) -> Result<WorkspaceFolder, tower_lsp::jsonrpc::Error> {
     for folder in workspace_folders {
-        let workspace_folder_path = folder.uri.path();
-        let file_path = uri.path();
+        let workspace_folder_path = std::path::Path::new(folder.uri.path());
+        let file_path = std::path::Path::new(uri.path());
 
-        if file_path.starts_with(workspace_folder_path) {
+        if crate::tags::path_starts_with(file_path, workspace_folder_path, ignore_case) {
             return Ok(folder.clone());
         }
     }
 
-    return Err(tower_lsp::jsonrpc::Error {
-        code: tower_lsp::jsonrpc::ErrorCode::InvalidParams,
-        message: std::borrow::Cow::Borrowed("cannot find matching workspace folder"),
+    return Err(crate::error::TagsLspError::InvalidParams {
+        message: format!("cannot find workspace folder of {}", uri),
         data: Some(serde_json::json!({
             "workspace_folders": workspace_folders,
             "uri": uri.to_string(),
         })),
-    });
+    }
+    .into());
+}
+
+/// Get pattern for matching symbol in language.
+///
+/// # Arguments
+///
+/// + `lang`: LSP language id.
+fn symbol_pattern(lang: &str) -> &'static Regex {
+    match lang {
+        "lisp" | "scheme" | "clojure" => &RE_SYMBOL_LISP,
+        "php" => &RE_SYMBOL_PHP,
+        "cpp" => &RE_SYMBOL_CPP,
+        _ => &RE_SYMBOL,
+    }
+}
+
+/// Find the word under cursor.
+///
+/// # Arguments
+///
+/// + `line`: Line content.
+/// + `character`: Cursor offset in line, clamped to the line length.
+/// + `lang`: LSP language id, decides the word boundaries.
+///
+/// # Returns
+///
+/// The word and its offset range in line.
+pub fn word_at(
+    line: &str,
+    character: usize,
+    lang: &str,
+) -> Option<(String, std::ops::Range<usize>)> {
+    // Client may send position past end of line during rapid edits.
+    let character = character.min(line.len());
+
+    for mat in symbol_pattern(lang).find_iter(line) {
+        if mat.start() <= character && mat.end() >= character {
+            return Some((mat.as_str().to_string(), mat.range()));
+        }
+    }
+
+    return None;
+}
+
+/// Convert word under cursor into the name recorded in tags.
+///
+/// Tags record symbols without scope qualifiers or sigils.
+///
+/// # Arguments
+///
+/// + `word`: Word under cursor.
+pub fn tag_name_of(word: &str) -> &str {
+    let word = word.rsplit("::").next().unwrap_or(word);
+    return word.strip_prefix('$').unwrap_or(word);
+}
+
+/// Rewrite tag name into another one that may be recorded instead, `None`
+/// if not applicable.
+type NameTransform = fn(&str) -> Option<String>;
+
+/// Extra tag names to try for each LSP language id.
+const NAME_TRANSFORMS: &[(&str, NameTransform)] = &[
+    // Destructor `~Foo` is recorded as `Foo` by some ctags.
+    ("cpp", |v| v.strip_prefix('~').map(String::from)),
+];
+
+/// Get tag names to look up for word under cursor, in order of preference.
+///
+/// The first one is always [`tag_name_of`] the word, and the others are
+/// produced by `NAME_TRANSFORMS` of the language.
+///
+/// # Arguments
+///
+/// + `word`: Word under cursor.
+/// + `lang`: LSP language id.
+pub fn tag_name_variants(word: &str, lang: &str) -> Vec<String> {
+    let name = tag_name_of(word);
+
+    let mut ret = vec![name.to_string()];
+    for (_, transform) in NAME_TRANSFORMS.iter().filter(|(k, _)| *k == lang) {
+        if let Some(v) = transform(name) {
+            if !v.is_empty() && !ret.contains(&v) {
+                ret.push(v);
+            }
+        }
+    }
+
+    return ret;
 }
 
+/// Get symbol from content by position.
+///
+/// # Arguments
+///
+/// + `data`: File content.
+/// + `pos`: Symbol position.
+/// + `lang`: LSP language id, decides the word boundaries.
+/// + `utf16`: `pos` counts UTF-16 code units instead of bytes.
 pub fn get_symbol_by_pos_from_dat(
-    data: &String,
+    data: &str,
     pos: &Position,
+    lang: &str,
+    utf16: bool,
 ) -> Result<String, tower_lsp::jsonrpc::Error> {
     let line_no = pos.line as usize;
-    let column_no = pos.character as usize;
-
-    let lines = split_string_by_lines(data);
-    let line = &lines[line_no];
 
-    let mut word = String::new();
-    for mat in RE_SYMBOL.find_iter(line.as_str()) {
-        if mat.start() <= column_no && mat.end() >= column_no {
-            word = mat.as_str().to_string();
-            break;
+    let line = match data.lines().nth(line_no) {
+        Some(v) => v,
+        None => {
+            return Err(crate::error::TagsLspError::Lookup(format!(
+                "line {} out of range",
+                line_no + 1
+            ))
+            .into())
         }
-    }
+    };
 
-    if word.is_empty() {
-        return Err(tower_lsp::jsonrpc::Error {
-            code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                crate::LspErrorCode::RequestFailed.code(),
-            ),
-            message: std::borrow::Cow::Borrowed("No word found at the specified position"),
-            data: None,
-        });
-    }
+    let column_no = match utf16 {
+        true => utf16_to_byte_offset(line, pos.character),
+        false => pos.character as usize,
+    };
 
-    return Ok(word);
+    match word_at(line, column_no, lang) {
+        Some((word, _)) => return Ok(word),
+        None => {
+            return Err(crate::error::TagsLspError::Lookup(format!(
+                "no word found at line {}, character {}",
+                pos.line + 1,
+                pos.character
+            ))
+            .into())
+        }
+    }
 }
 
 /// Get symbol from file by position.
 ///
+/// Content of opened file is used if possible, so unsaved edits are seen.
+///
 /// # Arguments
 ///
+/// + `rt`: Snapshot of Runtime taken for `path`.
 /// + `path`: File path.
 /// + `pos`: Symbol position.
 pub async fn get_symbol_by_position(
+    rt: &crate::Snapshot,
     path: &Url,
     pos: &Position,
 ) -> Result<String, tower_lsp::jsonrpc::Error> {
-    let content = read_file_content(path.path()).await?;
-    return get_symbol_by_pos_from_dat(&content, pos);
+    let lang = document_language_id(rt, path);
+    if let Some(content) = &rt.document {
+        return get_symbol_by_pos_from_dat(content, pos, &lang, rt.position_utf16);
+    }
+
+    let content = read_file_content(path.path(), rt.config.file_encoding()).await?;
+    return get_symbol_by_pos_from_dat(&content, pos, &lang, rt.position_utf16);
+}
+
+/// Convert UTF-16 offset in line to byte offset.
+///
+/// Offsets past end of line are clamped to the line length.
+///
+/// # Arguments
+///
+/// + `line`: Line content.
+/// + `character`: Offset in UTF-16 code units.
+pub fn utf16_to_byte_offset(line: &str, character: u32) -> usize {
+    let mut count = 0;
+    for (idx, c) in line.char_indices() {
+        if count >= character as usize {
+            return idx;
+        }
+        count += c.len_utf16();
+    }
+
+    return line.len();
+}
+
+/// Convert byte offset in line to UTF-16 offset.
+///
+/// # Arguments
+///
+/// + `line`: Line content.
+/// + `offset`: Offset in bytes.
+pub fn byte_to_utf16_offset(line: &str, offset: usize) -> u32 {
+    let offset = offset.min(line.len());
+    let count: usize = line
+        .char_indices()
+        .take_while(|(idx, _)| *idx < offset)
+        .map(|(_, c)| c.len_utf16())
+        .sum();
+    return count as u32;
+}
+
+/// Convert byte offsets of locations to UTF-16 offsets.
+///
+/// Locations are built with byte offsets, so they must be converted before
+/// returning to client that negotiated UTF-16.
+///
+/// # Arguments
+///
+/// + `list`: Location list.
+/// + `utf16`: Whether client uses UTF-16 offsets.
+/// + `cache`: Cache of file contents for current request.
+pub async fn encode_locations(list: &mut [Location], utf16: bool, cache: &crate::cache::FileCache) {
+    if !utf16 {
+        return;
+    }
+
+    for loc in list {
+        encode_location(loc, cache).await;
+    }
+}
+
+/// Same as [`encode_locations`], but for locations of symbols.
+///
+/// # Arguments
+///
+/// + `list`: Symbol list.
+/// + `utf16`: Whether client uses UTF-16 offsets.
+/// + `cache`: Cache of file contents for current request.
+pub async fn encode_symbol_locations(
+    list: &mut [SymbolInformation],
+    utf16: bool,
+    cache: &crate::cache::FileCache,
+) {
+    if !utf16 {
+        return;
+    }
+
+    for info in list {
+        encode_location(&mut info.location, cache).await;
+    }
+}
+
+async fn encode_location(loc: &mut Location, cache: &crate::cache::FileCache) {
+    // Nothing to convert at begin of line.
+    if loc.range.start.character == 0 && loc.range.end.character == 0 {
+        return;
+    }
+
+    let content = match cache.read(loc.uri.path()).await {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+
+    for pos in [&mut loc.range.start, &mut loc.range.end] {
+        if let Some(line) = content.lines().nth(pos.line as usize) {
+            pos.character = byte_to_utf16_offset(line, pos.character as usize);
+        }
+    }
 }
 
 /// Find symbol in specific file line.
@@ -109,11 +392,13 @@ pub async fn get_symbol_by_position(
 /// + `line_no`: Line number, starting from 0.
 /// + `symbol`: The symbol to find.
 /// + `low_precision`: Do not find precise position, just point to begin of line.
+/// + `cache`: Cache of file contents for current request.
 pub async fn find_symbol_in_line(
     path: &Url,
     line_no: u32,
     symbol: &str,
     low_precision: bool,
+    cache: &crate::cache::FileCache,
 ) -> Result<Location, tower_lsp::jsonrpc::Error> {
     let line_no = line_no as usize;
 
@@ -124,23 +409,34 @@ pub async fn find_symbol_in_line(
         return Ok(Location::new(path.clone(), range));
     }
 
-    let lines = match get_file_as_lines(path.path()).await {
+    let content = match cache.read(path.path()).await {
         Ok(v) => v,
         Err(e) => return Err(e),
     };
+    let lines = split_string_by_lines(&content);
 
-    let line = &lines[line_no];
+    let line = match lines.get(line_no) {
+        Some(v) => v,
+        None => {
+            return Err(crate::error::TagsLspError::Lookup(format!(
+                "line {} out of range of {}",
+                line_no + 1,
+                path.path()
+            ))
+            .into())
+        }
+    };
 
     let start_off = match line.find(symbol) {
         Some(pos) => Position::new(line_no as u32, pos as u32),
         None => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("symbol not found in line"),
-                data: None,
-            })
+            return Err(crate::error::TagsLspError::Lookup(format!(
+                "`{}` not found in line {} of {}",
+                symbol,
+                line_no + 1,
+                path.path()
+            ))
+            .into())
         }
     };
 
@@ -151,6 +447,327 @@ pub async fn find_symbol_in_line(
     return Ok(Location::new(path.clone(), ret_range));
 }
 
+/// Get location of tag entry.
+///
+/// # Arguments
+///
+/// + `tags_file`: The tags file that contains this entry.
+/// + `entry`: Tag entry.
+/// + `low_precision`: Do not find precise position, just point to begin of line.
+/// + `resolution`: Whether to confirm address against file content.
+/// + `cache`: Cache of file contents for current request.
+pub async fn find_tag_location(
+    tags_file: &crate::tags::TagsFile,
+    entry: &crate::tags::TagEntry,
+    low_precision: bool,
+    resolution: crate::tags::AddressResolution,
+    cache: &crate::cache::FileCache,
+) -> Result<Location, tower_lsp::jsonrpc::Error> {
+    // Names made up by ctags do not appear in source.
+    let low_precision = low_precision || crate::tags::is_anonymous(&entry.name);
+
+    let path = tags_file.entry_path(entry);
+    let path = match crate::tags::path_to_uri(&path) {
+        Some(v) => v,
+        None => {
+            return Err(crate::error::TagsLspError::Lookup(format!(
+                "path {} is not absolute",
+                path.display()
+            ))
+            .into())
+        }
+    };
+
+    // Recorded line is used as is, file is not read.
+    if resolution == crate::tags::AddressResolution::Trust {
+        let line = match &entry.address {
+            crate::tags::TagAddress::Line(v) => Some(*v),
+            crate::tags::TagAddress::Pattern(_) => entry.line,
+        };
+        if let Some(v) = line {
+            return find_symbol_in_line(&path, v.saturating_sub(1), &entry.name, true, cache).await;
+        }
+    }
+
+    return match &entry.address {
+        crate::tags::TagAddress::Line(v) => {
+            find_symbol_in_line(
+                &path,
+                v.saturating_sub(1),
+                &entry.name,
+                low_precision,
+                cache,
+            )
+            .await
+        }
+        crate::tags::TagAddress::Pattern(pattern) => {
+            let content = cache.read(path.path()).await?;
+            let pattern = crate::tags::SearchPattern::new(pattern);
+
+            match pattern.find_line_near(&content, entry.line) {
+                Some(line_no) => {
+                    find_symbol_in_line(&path, line_no, &entry.name, low_precision, cache).await
+                }
+                None => {
+                    tracing::warn!(
+                        "Pattern of `{}` no longer matches {}, tags may be out of date",
+                        entry.name,
+                        path.path()
+                    );
+                    find_symbol_in_line(&path, 0, &entry.name, true, cache).await
+                }
+            }
+        }
+    };
+}
+
+/// Check if the file of tag entry still exists.
+///
+/// Files may be deleted or renamed after tags are generated.
+///
+/// # Arguments
+///
+/// + `tags_file`: The tags file that contains this entry.
+/// + `entry`: Tag entry.
+pub async fn tag_target_exists(
+    tags_file: &crate::tags::TagsFile,
+    entry: &crate::tags::TagEntry,
+) -> bool {
+    let path = tags_file.entry_path(entry);
+    let exists = tokio::fs::metadata(&path).await.is_ok_and(|v| v.is_file());
+    if !exists {
+        tracing::debug!("Skip `{}`: {} no longer exists", entry.name, path.display());
+    }
+
+    return exists;
+}
+
+/// Log if work of a request is dropped before finishing.
+///
+/// tower-lsp drops the handler future when client sends `$/cancelRequest`,
+/// so long loops should `yield_now` from time to time to be dropped early.
+pub struct CancelGuard<'a> {
+    /// What is being done, for logging.
+    what: &'a str,
+
+    /// Set when work finished.
+    done: bool,
+}
+
+impl<'a> CancelGuard<'a> {
+    pub fn new(what: &'a str) -> CancelGuard<'a> {
+        return CancelGuard { what, done: false };
+    }
+
+    /// Mark work as finished.
+    pub fn finish(mut self) {
+        self.done = true;
+    }
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            tracing::debug!("{} cut short: request cancelled", self.what);
+        }
+    }
+}
+
+/// Keep at most `max_results` items of list.
+///
+/// # Arguments
+///
+/// + `list`: Result list.
+/// + `max_results`: The maximum number of results.
+/// + `symbol`: The symbol queried, for logging.
+pub fn limit_results<T>(list: &mut Vec<T>, max_results: usize, symbol: &str) {
+    if list.len() > max_results {
+        tracing::debug!(
+            "Return {} of {} results for `{}`",
+            max_results,
+            list.len(),
+            symbol
+        );
+        list.truncate(max_results);
+    }
+}
+
+/// Resolve symlinks in file paths of locations.
+///
+/// Paths that cannot be canonicalized, e.g. the file was deleted, are kept.
+///
+/// # Arguments
+///
+/// + `list`: Location list.
+/// + `config`: Configuration.
+pub async fn canonicalize_locations(list: &mut [Location], config: &crate::TagsLspConfig) {
+    if config.no_canonicalize {
+        return;
+    }
+
+    for loc in list {
+        let path = match loc.uri.to_file_path() {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let path = match tokio::fs::canonicalize(&path).await {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(v) = crate::tags::path_to_uri(&path) {
+            loc.uri = v;
+        }
+    }
+}
+
+/// Answer definition, declaration or implementation request.
+///
+/// Names of the symbol under cursor are tried in order until `lookup` finds
+/// any location. Client is warned if tags are found but their files no longer
+/// exist, and source files are searched if no location is found.
+///
+/// # Arguments
+///
+/// + `backend`: Backend of the session.
+/// + `params`: Document and position of the request.
+/// + `lookup`: Find locations of name, given the scope written in code and
+///   the language of document.
+pub async fn goto_symbol<F>(
+    backend: &crate::TagsLspBackend,
+    params: &TextDocumentPositionParams,
+    lookup: F,
+) -> tower_lsp::jsonrpc::Result<Option<GotoDefinitionResponse>>
+where
+    F: for<'a> Fn(
+        &'a (dyn crate::index::Backend + Send + Sync + 'a),
+        &'a str,
+        Option<&'a str>,
+        Option<&'a str>,
+    ) -> futures::future::BoxFuture<'a, tower_lsp::jsonrpc::Result<Vec<Location>>>,
+{
+    let file_uri = &params.text_document.uri;
+    let rt = backend.snapshot(Some(file_uri)).await;
+    let cwd =
+        find_belong_workspace_folder(&rt.workspace_folders, file_uri, rt.config.ignore_case_fs())?;
+
+    // Get symbol from position.
+    let symbol = get_symbol_by_position(&rt, file_uri, &params.position).await?;
+    let language_id = document_language_id(&rt, file_uri);
+    let variants = tag_name_variants(&symbol, &language_id);
+    let lang = document_language(&rt, file_uri);
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let index = crate::index::select(&rt, &cwd, &cache);
+
+    // Scope written in code, e.g. `Foo` of `Foo::bar`.
+    let qualifier = symbol
+        .rsplit_once("::")
+        .map(|(v, _)| v)
+        .filter(|v| !v.is_empty());
+
+    // Try other names only if the word itself is not found.
+    let mut loc_list = Vec::new();
+    let mut symbol = variants[0].as_str();
+    for name in &variants {
+        loc_list = lookup(index.as_ref(), name, qualifier, lang).await?;
+        if !loc_list.is_empty() {
+            symbol = name;
+            break;
+        }
+    }
+
+    // Tags are found but some of them point to files that no longer exist.
+    if loc_list.is_empty() && any_target_missing(&rt.tags, &variants).await {
+        backend
+            .client
+            .show_message(
+                MessageType::WARNING,
+                format!(
+                    "Files of `{}` no longer exist, tags may be out of date",
+                    symbol
+                ),
+            )
+            .await;
+    }
+
+    // Tags know nothing about the symbol, guess from source files.
+    if loc_list.is_empty() && rt.config.grep_fallback {
+        loc_list = crate::grep::find_definitions(&rt, &cwd.uri, symbol, lang).await;
+    }
+    limit_results(&mut loc_list, rt.config.max_results(), symbol);
+    encode_locations(&mut loc_list, rt.position_utf16, &cache).await;
+    canonicalize_locations(&mut loc_list, &rt.config).await;
+
+    // Keep single location as scalar for client compatibility.
+    if loc_list.len() == 1 {
+        return Ok(Some(GotoDefinitionResponse::Scalar(loc_list.remove(0))));
+    }
+
+    return Ok(Some(GotoDefinitionResponse::Array(loc_list)));
+}
+
+/// Check if any tag entry of names points to a file that no longer exists.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+/// + `names`: Tag names to check.
+async fn any_target_missing(
+    tags: &[std::sync::Arc<crate::tags::TagsFile>],
+    names: &[String],
+) -> bool {
+    for tags_file in tags {
+        for name in names {
+            for entry in tags_file.lookup(name) {
+                if !tag_target_exists(tags_file, entry).await {
+                    return true;
+                }
+            }
+        }
+    }
+
+    return false;
+}
+
+/// Publish malformed lines of tags file as diagnostics.
+///
+/// An empty list is published if there is no malformed line, so diagnostics
+/// of the previous load are cleared.
+///
+/// # Arguments
+///
+/// + `client`: LSP client.
+/// + `config`: Configuration.
+/// + `tags_file`: The loaded tags file.
+pub async fn publish_tags_diagnostics(
+    client: &tower_lsp::Client,
+    config: &crate::TagsLspConfig,
+    tags_file: &crate::tags::TagsFile,
+) {
+    if config.no_tags_diagnostics {
+        return;
+    }
+
+    let uri = match crate::tags::path_to_uri(&tags_file.path) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let diagnostics = tags_file
+        .malformed
+        .iter()
+        .map(|v| Diagnostic {
+            range: Range::new(Position::new(*v, 0), Position::new(*v + 1, 0)),
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some(String::from("tags-lsp")),
+            message: String::from("Malformed tag line, skipped"),
+            ..Default::default()
+        })
+        .collect();
+
+    client.publish_diagnostics(uri, diagnostics, None).await;
+}
+
 /// Execute command and get output as lines.
 ///
 /// # Arguments
@@ -172,30 +789,20 @@ pub async fn execute_and_split_lines(
     let output = match output.await {
         Ok(v) => v,
         Err(e) => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("execute `global` failed"),
-                data: Some(serde_json::json!({
-                    "error": e.to_string(),
-                })),
-            })
+            return Err(crate::error::TagsLspError::io(
+                format!("execute `{}` in {}", program, cwd),
+                e,
+            )
+            .into())
         }
     };
 
     let content = match String::from_utf8(output.stdout) {
         Ok(v) => v,
         Err(e) => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("execute `global` failed"),
-                data: Some(serde_json::json!({
-                    "error": e.to_string(),
-                })),
-            })
+            return Err(
+                crate::error::TagsLspError::parse(format!("output of `{}`", program), e).into(),
+            )
         }
     };
 
@@ -225,13 +832,7 @@ pub fn parse_cxref(data: &str) -> Result<(String, u32, String, String), tower_ls
         Some(v) => v,
         None => {
             tracing::error!("Capture group failed. cxref:{}", data);
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("Capture group failed"),
-                data: None,
-            });
+            return Err(crate::error::TagsLspError::parse("cxref line", data).into());
         }
     };
 
@@ -245,15 +846,11 @@ pub fn parse_cxref(data: &str) -> Result<(String, u32, String, String), tower_ls
                 caps[3].to_string(),
                 caps[4].to_string()
             );
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("Not a valid u32"),
-                data: Some(serde_json::json!({
-                    "error": e.to_string(),
-                })),
-            });
+            return Err(crate::error::TagsLspError::parse(
+                format!("line number `{}`", &caps[2]),
+                e,
+            )
+            .into());
         }
     };
 
@@ -272,21 +869,17 @@ pub fn parse_cxref(data: &str) -> Result<(String, u32, String, String), tower_ls
 /// + `cwd`: Workspace folder Url,
 /// + `path`: File path in that folder.
 pub fn join_workspace_path(cwd: &Url, path: &str) -> Result<Url, tower_lsp::jsonrpc::Error> {
-    let path = format!("{}/{}", cwd.path(), path);
+    let dir = cwd.to_file_path().unwrap_or_default();
+    let path = dir.join(path);
 
-    let path = match Url::from_file_path(&path) {
-        Ok(v) => v,
-        Err(_) => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("Path is not absolute"),
-                data: Some(serde_json::json! ({
-                    "cwd": cwd.path(),
-                    "path": path,
-                })),
-            })
+    let path = match crate::tags::path_to_uri(&path) {
+        Some(v) => v,
+        None => {
+            return Err(crate::error::TagsLspError::Lookup(format!(
+                "path {} is not absolute",
+                path.display()
+            ))
+            .into())
         }
     };
 
@@ -301,7 +894,7 @@ pub fn create_symbol_information(
 ) -> SymbolInformation {
     return SymbolInformation {
         name: symbol_name,
-        kind: check_symbol_kind(&rest_string),
+        kind: check_symbol_kind(rest_string),
         tags: None,
         deprecated: None,
         location: loc,
@@ -309,6 +902,40 @@ pub fn create_symbol_information(
     };
 }
 
+#[allow(deprecated)]
+pub fn create_tag_symbol_information(
+    entry: &crate::tags::TagEntry,
+    loc: Location,
+) -> SymbolInformation {
+    return SymbolInformation {
+        name: entry.name.clone(),
+        kind: crate::tags::symbol_kind(entry.kind.as_deref()),
+        tags: None,
+        deprecated: None,
+        location: loc,
+        container_name: entry.scope.clone(),
+    };
+}
+
+/// Replace names made up by ctags for anonymous types in symbol.
+///
+/// # Arguments
+///
+/// + `tags_file`: The tags file that contains the symbol.
+/// + `info`: Symbol information.
+///
+/// # Returns
+///
+/// `None` if the symbol itself is anonymous and has no readable name.
+pub fn deanonymize_symbol(
+    tags_file: &crate::tags::TagsFile,
+    mut info: SymbolInformation,
+) -> Option<SymbolInformation> {
+    info.name = tags_file.readable_name(&info.name)?.to_string();
+    info.container_name = info.container_name.map(|v| tags_file.readable_scope(&v));
+    return Some(info);
+}
+
 fn check_symbol_kind(context: &str) -> SymbolKind {
     match RE_MACRO.find(context) {
         Some(_) => return SymbolKind::NULL,
@@ -316,51 +943,101 @@ fn check_symbol_kind(context: &str) -> SymbolKind {
     };
 }
 
-async fn read_file_content(path: &str) -> Result<String, tower_lsp::jsonrpc::Error> {
+/// Find every whole-word occurrence of symbol in content.
+///
+/// # Arguments
+///
+/// + `content`: File content.
+/// + `symbol`: The symbol to find.
+/// + `lang`: LSP language id, decides the word boundaries.
+pub fn find_symbol_in_content(content: &str, symbol: &str, lang: &str) -> Vec<Range> {
+    let mut ret = Vec::new();
+
+    // C++ words are not joined by `::` and do not include `~`, so `Foo::bar`
+    // is found as `bar` and destructor `~Foo` is found as `Foo`.
+    let pattern = match lang {
+        "cpp" => &RE_SYMBOL,
+        _ => symbol_pattern(lang),
+    };
+    let symbol = symbol.trim_start_matches('~');
+
+    for (line_no, line) in content.lines().enumerate() {
+        for mat in pattern.find_iter(line) {
+            if mat.as_str() != symbol {
+                continue;
+            }
+
+            let start = Position::new(line_no as u32, mat.start() as u32);
+            let end = Position::new(line_no as u32, mat.end() as u32);
+            ret.push(Range::new(start, end));
+        }
+    }
+
+    return ret;
+}
+
+/// List source files in workspace folder recursively.
+///
+/// Hidden files and files not written in a known tag language are skipped.
+///
+/// # Arguments
+///
+/// + `cwd`: Workspace folder Url.
+/// + `no_ignore`: Do not respect ignore files.
+/// + `exclude`: Skip paths matching it, relative to workspace folder.
+pub async fn list_source_files(
+    cwd: &Url,
+    no_ignore: bool,
+    exclude: &globset::GlobSet,
+) -> Vec<String> {
+    let dir = std::path::PathBuf::from(cwd.path());
+    let exclude = exclude.clone();
+    let files = tokio::task::spawn_blocking(move || crate::walk::walk(&dir, no_ignore, exclude))
+        .await
+        .unwrap_or_default();
+
+    return files
+        .into_iter()
+        .filter(|path| crate::tags::language_from_path(path).is_some())
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+}
+
+/// Read file content and decode it.
+///
+/// Bytes that cannot be decoded are replaced with U+FFFD.
+///
+/// # Arguments
+///
+/// + `path`: File path.
+/// + `encoding`: Encoding of file, unless it starts with a byte order mark.
+pub async fn read_file_content(
+    path: &str,
+    encoding: &'static encoding_rs::Encoding,
+) -> Result<String, tower_lsp::jsonrpc::Error> {
     // Open file.
     let mut file = match tokio::fs::File::open(path).await {
         Ok(v) => v,
         Err(e) => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("open file failed"),
-                data: Some(serde_json::json!({
-                    "error": e.to_string(),
-                })),
-            });
+            return Err(crate::error::TagsLspError::io(format!("cannot open {}", path), e).into());
         }
     };
 
     // Read content.
-    let mut content = String::new();
-    match file.read_to_string(&mut content).await {
+    let mut content = Vec::new();
+    match file.read_to_end(&mut content).await {
         Ok(_) => (),
         Err(e) => {
-            return Err(tower_lsp::jsonrpc::Error {
-                code: tower_lsp::jsonrpc::ErrorCode::ServerError(
-                    crate::LspErrorCode::RequestFailed.code(),
-                ),
-                message: std::borrow::Cow::Borrowed("cannot read file"),
-                data: Some(serde_json::json!({
-                    "error": e.to_string(),
-                })),
-            })
+            return Err(crate::error::TagsLspError::io(format!("cannot read {}", path), e).into())
         }
     }
 
-    return Ok(content);
-}
+    let (content, _, malformed) = encoding.decode(&content);
+    if malformed {
+        tracing::debug!("{} is not valid {}", path, encoding.name());
+    }
 
-/// Read content of file and return it as lines.
-///
-/// # Arguments
-///
-/// + `path`: File path.
-async fn get_file_as_lines(path: &str) -> Result<Vec<String>, tower_lsp::jsonrpc::Error> {
-    let content = read_file_content(path).await?;
-    return Ok(split_string_by_lines(&content));
+    return Ok(content.into_owned());
 }
 
 /// Split string into lines, return the Vec of content copy.
@@ -368,7 +1045,257 @@ async fn get_file_as_lines(path: &str) -> Result<Vec<String>, tower_lsp::jsonrpc
 /// # Arguments
 ///
 /// + `content`: String content.
-fn split_string_by_lines(content: &String) -> Vec<String> {
+fn split_string_by_lines(content: &str) -> Vec<String> {
     let ret = content.lines().map(|line| line.to_string()).collect();
     return ret;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_id_of_document() {
+        let uri = Url::parse("file:///src/a.h").unwrap();
+        let mut rt = crate::Runtime::default().snapshot(None);
+        assert_eq!(language_id_from_uri(&uri).as_deref(), Some("c"));
+        assert_eq!(document_language(&rt, &uri), Some("C"));
+
+        // Header opened as C++.
+        rt.language_id = Some(String::from("cpp"));
+        assert_eq!(document_language_id(&rt, &uri), "cpp");
+        assert_eq!(document_language(&rt, &uri), Some("C++"));
+
+        // Dialect is taken from extension.
+        let uri = Url::parse("file:///src/init.el").unwrap();
+        rt.language_id = Some(String::from("lisp"));
+        assert_eq!(document_language(&rt, &uri), Some("EmacsLisp"));
+
+        // Unknown language id.
+        rt.language_id = Some(String::from("plaintext"));
+        assert_eq!(document_language(&rt, &uri), Some("EmacsLisp"));
+    }
+
+    #[test]
+    fn word_under_cursor() {
+        let word = |line: &str, character: usize, lang: &str| {
+            return word_at(line, character, lang).map(|(v, range)| (v, range.start, range.end));
+        };
+
+        // Start, middle and end of word.
+        assert_eq!(word("int foo;", 4, "c"), Some((String::from("foo"), 4, 7)));
+        assert_eq!(word("int foo;", 5, "c"), Some((String::from("foo"), 4, 7)));
+        assert_eq!(word("int foo;", 7, "c"), Some((String::from("foo"), 4, 7)));
+        assert_eq!(word("a = b;", 5, "c"), Some((String::from("b"), 4, 5)));
+        assert_eq!(word("a  b", 2, "c"), None);
+
+        // Past end of line.
+        assert_eq!(word("foo", 100, "c"), Some((String::from("foo"), 0, 3)));
+        assert_eq!(word("foo ", 100, "c"), None);
+
+        // Offsets are bytes, non-ASCII letters are not part of word.
+        assert_eq!(word("é foo", 3, "c"), Some((String::from("foo"), 3, 6)));
+        assert_eq!(word("café", 1, "c"), Some((String::from("caf"), 0, 3)));
+
+        // Language specific boundaries.
+        assert_eq!(
+            word("Foo::bar();", 6, "cpp"),
+            Some((String::from("Foo::bar"), 0, 8))
+        );
+        assert_eq!(
+            word("Foo::~Foo()", 7, "cpp"),
+            Some((String::from("Foo::~Foo"), 0, 9))
+        );
+        assert_eq!(
+            word("Foo::bar();", 6, "c"),
+            Some((String::from("bar"), 5, 8))
+        );
+        assert_eq!(
+            word("(foo-bar 1)", 3, "lisp"),
+            Some((String::from("foo-bar"), 1, 8))
+        );
+        assert_eq!(
+            word("echo $var;", 7, "php"),
+            Some((String::from("$var"), 5, 9))
+        );
+    }
+
+    #[test]
+    fn utf16_column_of_non_ascii_line() {
+        let line = "let café = foo;";
+        assert_eq!(utf16_to_byte_offset(line, 11), 12);
+        assert_eq!(byte_to_utf16_offset(line, 12), 11);
+        assert_eq!(utf16_to_byte_offset(line, 100), line.len());
+
+        // `foo` is at UTF-16 column 11 and byte column 12.
+        let symbol = get_symbol_by_pos_from_dat(line, &Position::new(0, 11), "rust", true);
+        assert_eq!(symbol.unwrap(), "foo");
+        let symbol = get_symbol_by_pos_from_dat(line, &Position::new(0, 12), "rust", false);
+        assert_eq!(symbol.unwrap(), "foo");
+    }
+
+    #[tokio::test]
+    async fn encode_locations_as_utf16() {
+        let path =
+            std::env::temp_dir().join(format!("tags-lsp-test-utf16-{}.rs", std::process::id()));
+        std::fs::write(&path, "fn main() {\n    let café = foo;\n}\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+        let range = Range::new(Position::new(1, 16), Position::new(1, 19));
+        let cache = crate::cache::FileCache::new(encoding_rs::UTF_8);
+
+        let mut list = [Location::new(uri.clone(), range)];
+        encode_locations(&mut list, false, &cache).await;
+        assert_eq!(list[0].range, range);
+
+        encode_locations(&mut list, true, &cache).await;
+        let expect = Range::new(Position::new(1, 15), Position::new(1, 18));
+        assert_eq!(list[0].range, expect);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tag_names_of_word() {
+        assert_eq!(tag_name_variants("foo", "c"), ["foo"]);
+        assert_eq!(tag_name_variants("Foo::bar", "cpp"), ["bar"]);
+        assert_eq!(tag_name_variants("~Foo", "cpp"), ["~Foo", "Foo"]);
+        assert_eq!(tag_name_variants("Foo::~Foo", "cpp"), ["~Foo", "Foo"]);
+        assert_eq!(tag_name_variants("$var", "php"), ["var"]);
+
+        // Destructors only exist in C++.
+        assert_eq!(tag_name_variants("~Foo", "c"), ["~Foo"]);
+        assert_eq!(tag_name_variants("~", "cpp"), ["~"]);
+    }
+
+    #[test]
+    fn container_name_from_scope() {
+        let uri = Url::parse("file:///src/a.py").unwrap();
+        let info = |line: &str| {
+            let entry = crate::tags::parse_line(line).unwrap();
+            let loc = Location::new(uri.clone(), Range::default());
+            return create_tag_symbol_information(&entry, loc);
+        };
+
+        let json = info("parse\ta.py\t/^    def parse(self):$/;\"\tm\tclass:Json");
+        let xml = info("parse\ta.py\t/^    def parse(self):$/;\"\tm\tclass:Xml");
+        assert_eq!(json.name, "parse");
+        assert_eq!(json.container_name.as_deref(), Some("Json"));
+        assert_eq!(xml.container_name.as_deref(), Some("Xml"));
+
+        let free = info("parse\ta.py\t/^def parse():$/;\"\tf");
+        assert_eq!(free.container_name, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn resolve_symlink_of_location() {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-symlink-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+        std::fs::write(dir.join("real/a.c"), "int a;\n").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+        let dir = std::fs::canonicalize(&dir).unwrap();
+        let link = Url::from_file_path(dir.join("link/a.c")).unwrap();
+        let gone = Url::from_file_path(dir.join("link/gone.c")).unwrap();
+        let list = vec![
+            Location::new(link.clone(), Range::default()),
+            Location::new(gone.clone(), Range::default()),
+        ];
+
+        let mut config = crate::TagsLspConfig::default();
+        let mut ret = list.clone();
+        canonicalize_locations(&mut ret, &config).await;
+        assert_eq!(
+            ret[0].uri,
+            Url::from_file_path(dir.join("real/a.c")).unwrap()
+        );
+        assert_eq!(ret[1].uri, gone);
+
+        config.no_canonicalize = true;
+        let mut ret = list.clone();
+        canonicalize_locations(&mut ret, &config).await;
+        assert_eq!(ret[0].uri, link);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symbol_at_out_of_range_position() {
+        let data = "int foo;\nint bar;\n";
+        let symbol = |line: u32, character: u32| {
+            return get_symbol_by_pos_from_dat(data, &Position::new(line, character), "c", true)
+                .ok();
+        };
+
+        assert_eq!(symbol(0, 0).as_deref(), Some("int"));
+        assert_eq!(symbol(1, 100), None);
+        assert_eq!(symbol(1, 7).as_deref(), Some("bar"));
+        assert_eq!(symbol(2, 0), None);
+        assert_eq!(symbol(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn find_symbol_by_language_boundaries() {
+        let find = |content: &str, symbol: &str, lang: &str| -> Vec<(u32, u32, u32)> {
+            return find_symbol_in_content(content, symbol, lang)
+                .iter()
+                .map(|v| (v.start.line, v.start.character, v.end.character))
+                .collect();
+        };
+
+        let lisp = "(defun foo-bar (x)\n  (foo x))\n";
+        assert_eq!(find(lisp, "foo-bar", "lisp"), [(0, 7, 14)]);
+        assert_eq!(find(lisp, "foo", "lisp"), [(1, 3, 6)]);
+
+        let php = "<?php\n$count = count($list);\n";
+        assert_eq!(find(php, "$count", "php"), [(1, 0, 6)]);
+        assert_eq!(find(php, "count", "php"), [(1, 9, 14)]);
+
+        let cpp = "Foo::~Foo() {}\nvoid Foo::bar() {}\n";
+        assert_eq!(find(cpp, "bar", "cpp"), [(1, 10, 13)]);
+        assert_eq!(find(cpp, "~Foo", "cpp"), [(0, 0, 3), (0, 6, 9), (1, 5, 8)]);
+    }
+
+    #[tokio::test]
+    async fn warn_missing_files_then_grep() {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-missing-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = "int helper(int x)\n{\n    return x;\n}\n\nint main(void)\n{\n    return helper(1);\n}\n";
+        std::fs::write(dir.join("a.c"), content).unwrap();
+        std::fs::write(dir.join("tags-lsp-test-tags"), "helper\tgone.c\t1;\"\tf\n").unwrap();
+        let root = Url::from_directory_path(&dir).unwrap();
+        let uri = Url::from_file_path(dir.join("a.c")).unwrap();
+
+        let mut client = crate::testing::Client::start(&[
+            "--no-auto-generate",
+            "--tags-filename",
+            "tags-lsp-test-tags",
+            "--grep-fallback",
+        ]);
+        client.initialize(&root).await;
+
+        // `helper` of `return helper(1);`.
+        let params = GotoDefinitionParams {
+            text_document_position_params: crate::testing::position(&uri, 7, 12),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::GotoDefinition>(params)
+            .await
+            .unwrap();
+        let expect = Location::new(uri, Range::new(Position::new(0, 4), Position::new(0, 10)));
+        assert_eq!(ret, Some(GotoDefinitionResponse::Scalar(expect)));
+
+        let warnings = client
+            .wait_notifications(|v| v["method"] == "window/showMessage", 1)
+            .await;
+        let message = warnings[0]["params"]["message"].as_str().unwrap();
+        assert!(message.contains("`helper`"), "{}", message);
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
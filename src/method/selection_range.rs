@@ -0,0 +1,275 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn selection_range(
+    backend: &crate::TagsLspBackend,
+    params: SelectionRangeParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<SelectionRange>>> {
+    let doc_uri = &params.text_document.uri;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+    let doc_dat = match &rt.document {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let lang = crate::method::document_language_id(&rt, doc_uri);
+    let lines: Vec<&str> = doc_dat.split('\n').collect();
+    let mut line_starts = Vec::with_capacity(lines.len());
+    let mut offset = 0;
+    for line in &lines {
+        line_starts.push(offset);
+        offset += line.len() + 1;
+    }
+
+    let mut ret = Vec::new();
+    for pos in &params.positions {
+        let line_no = pos.line as usize;
+        let line = match lines.get(line_no) {
+            Some(v) => v.trim_end_matches('\r'),
+            None => {
+                ret.push(SelectionRange {
+                    range: Range::new(*pos, *pos),
+                    parent: None,
+                });
+                continue;
+            }
+        };
+        let column = match rt.position_utf16 {
+            true => crate::method::utf16_to_byte_offset(line, pos.character),
+            false => (pos.character as usize).min(line.len()),
+        };
+        let line_start = line_starts[line_no];
+
+        // From innermost to outermost.
+        let mut spans = Vec::new();
+        if let Some((_, range)) = crate::method::word_at(line, column, &lang) {
+            spans.push(line_start + range.start..line_start + range.end);
+        }
+        spans.push(trimmed_span(line, line_start));
+
+        let blocks = brace_blocks(doc_dat, line_start + column);
+        match blocks.is_empty() {
+            true => {
+                for (first, last) in indent_blocks(&lines, line_no) {
+                    let start = trimmed_span(lines[first], line_starts[first]).start;
+                    let end = trimmed_span(lines[last], line_starts[last]).end;
+                    spans.push(start..end);
+                }
+            }
+            false => spans.extend(blocks),
+        }
+
+        let to_position = |offset: usize| {
+            let line_no = line_starts.partition_point(|v| *v <= offset) - 1;
+            let line = lines[line_no];
+            let character = offset - line_starts[line_no];
+            let character = match rt.position_utf16 {
+                true => crate::method::byte_to_utf16_offset(line, character),
+                false => character as u32,
+            };
+            return Position::new(line_no as u32, character);
+        };
+
+        // Parent must strictly contain its child.
+        let mut chain: Option<SelectionRange> = None;
+        let mut outer: Option<std::ops::Range<usize>> = None;
+        for span in spans.into_iter().rev() {
+            if let Some(v) = &outer {
+                if span.start < v.start || span.end > v.end || span == *v {
+                    continue;
+                }
+            }
+            chain = Some(SelectionRange {
+                range: Range::new(to_position(span.start), to_position(span.end)),
+                parent: chain.map(Box::new),
+            });
+            outer = Some(span);
+        }
+
+        ret.push(chain.unwrap_or(SelectionRange {
+            range: Range::new(*pos, *pos),
+            parent: None,
+        }));
+    }
+
+    return Ok(Some(ret));
+}
+
+/// Get byte span of line without leading and trailing whitespace.
+///
+/// # Arguments
+///
+/// + `line`: Line content.
+/// + `line_start`: Byte offset of line in document.
+fn trimmed_span(line: &str, line_start: usize) -> std::ops::Range<usize> {
+    let trimmed = line.trim_end();
+    let start = trimmed.len() - trimmed.trim_start().len();
+    return line_start + start..line_start + trimmed.len();
+}
+
+/// Find blocks enclosed by braces around offset, from innermost to outermost.
+///
+/// Braces in strings and comments are not skipped.
+///
+/// # Arguments
+///
+/// + `content`: Document content.
+/// + `offset`: Byte offset of cursor.
+fn brace_blocks(content: &str, offset: usize) -> Vec<std::ops::Range<usize>> {
+    let bytes = content.as_bytes();
+    let mut ret = Vec::new();
+
+    let (mut start, mut end) = (offset, offset);
+    loop {
+        let mut depth = 0;
+        let mut open = None;
+        for idx in (0..start).rev() {
+            match bytes[idx] {
+                b'}' => depth += 1,
+                b'{' if depth == 0 => {
+                    open = Some(idx);
+                    break;
+                }
+                b'{' => depth -= 1,
+                _ => (),
+            }
+        }
+
+        let mut depth = 0;
+        let mut close = None;
+        for (idx, c) in bytes.iter().enumerate().skip(end) {
+            match c {
+                b'{' => depth += 1,
+                b'}' if depth == 0 => {
+                    close = Some(idx);
+                    break;
+                }
+                b'}' => depth -= 1,
+                _ => (),
+            }
+        }
+
+        match (open, close) {
+            (Some(open), Some(close)) => {
+                ret.push(open..close + 1);
+                start = open;
+                end = close + 1;
+            }
+            _ => break,
+        }
+    }
+
+    return ret;
+}
+
+/// Find blocks by indentation around line, from innermost to outermost.
+///
+/// A block starts from a line with less indentation, and ends before the
+/// next line that is not indented more than it.
+///
+/// # Arguments
+///
+/// + `lines`: Lines of document.
+/// + `line_no`: Line of cursor.
+///
+/// # Returns
+///
+/// First and last line of each block.
+fn indent_blocks(lines: &[&str], line_no: usize) -> Vec<(usize, usize)> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let is_blank = |line: &str| line.trim().is_empty();
+
+    let mut ret = Vec::new();
+    if is_blank(lines[line_no]) {
+        return ret;
+    }
+
+    let (mut first, mut last) = (line_no, line_no);
+    let mut level = indent(lines[line_no]);
+    loop {
+        let header = (0..first)
+            .rev()
+            .find(|v| !is_blank(lines[*v]) && indent(lines[*v]) < level);
+        let header = match header {
+            Some(v) => v,
+            None => break,
+        };
+
+        let header_level = indent(lines[header]);
+        for (idx, line) in lines.iter().enumerate().skip(last + 1) {
+            if is_blank(line) {
+                continue;
+            }
+            if indent(line) <= header_level {
+                break;
+            }
+            last = idx;
+        }
+
+        ret.push((header, last));
+        first = header;
+        level = header_level;
+    }
+
+    return ret;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flatten selection range chain, from innermost to outermost.
+    fn flatten(mut range: &SelectionRange) -> Vec<(u32, u32, u32, u32)> {
+        let mut ret = Vec::new();
+        loop {
+            let (start, end) = (range.range.start, range.range.end);
+            ret.push((start.line, start.character, end.line, end.character));
+            match &range.parent {
+                Some(v) => range = v,
+                None => return ret,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn expand_word_then_line() {
+        let service = crate::testing::backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///src/a.c").unwrap();
+        let text = "int main(void)\n{\n    return add(1, 2);\n}\n";
+        backend
+            .rt
+            .lock()
+            .await
+            .open_files
+            .insert(uri.clone(), String::from(text));
+
+        let params = SelectionRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            positions: vec![Position::new(2, 12), Position::new(0, 0)],
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = selection_range(backend, params).await.unwrap().unwrap();
+
+        assert_eq!(
+            flatten(&ret[0]),
+            [(2, 11, 2, 14), (2, 4, 2, 21), (1, 0, 3, 1)]
+        );
+        assert_eq!(flatten(&ret[1]), [(0, 0, 0, 3), (0, 0, 0, 14)]);
+    }
+
+    #[test]
+    fn expand_by_indentation() {
+        let lines = [
+            "def main():",
+            "    if x:",
+            "        run()",
+            "",
+            "    done()",
+            "end()",
+        ];
+        assert_eq!(indent_blocks(&lines, 2), [(1, 2), (0, 4)]);
+        assert_eq!(indent_blocks(&lines, 4), [(0, 4)]);
+        assert_eq!(indent_blocks(&lines, 3), []);
+    }
+}
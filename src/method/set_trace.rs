@@ -0,0 +1,46 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn set_trace(backend: &crate::TagsLspBackend, params: SetTraceParams) {
+    let mut rt = backend.rt.lock().await;
+    apply_trace(backend, &mut rt, params.value);
+}
+
+/// Store trace value requested by client.
+///
+/// Requests are logged at TRACE level when the value is `verbose`.
+///
+/// # Arguments
+///
+/// + `backend`: LSP backend.
+/// + `rt`: A mut reference to Runtime.
+/// + `value`: Trace value.
+pub fn apply_trace(backend: &crate::TagsLspBackend, rt: &mut crate::Runtime, value: TraceValue) {
+    tracing::debug!("Set trace to {:?}", value);
+
+    rt.trace = value;
+    backend.trace_verbose.store(
+        value == TraceValue::Verbose,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn update_trace_on_notification() {
+        let service = crate::testing::backend();
+        let backend = service.inner();
+
+        for value in [TraceValue::Verbose, TraceValue::Messages, TraceValue::Off] {
+            set_trace(backend, SetTraceParams { value }).await;
+            assert_eq!(backend.rt.lock().await.trace, value);
+
+            let verbose = backend
+                .trace_verbose
+                .load(std::sync::atomic::Ordering::Relaxed);
+            assert_eq!(verbose, value == TraceValue::Verbose);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn signature_help(
+    backend: &crate::TagsLspBackend,
+    params: SignatureHelpParams,
+) -> tower_lsp::jsonrpc::Result<Option<SignatureHelp>> {
+    /// The maximum number of signatures to show.
+    const SIGNATURE_LIMIT: usize = 3;
+
+    let doc_uri = &params.text_document_position_params.text_document.uri;
+    let doc_pos = &params.text_document_position_params.position;
+    let rt = backend.snapshot(Some(doc_uri)).await;
+    let content = match &rt.document {
+        Some(v) => v.clone(),
+        None => crate::method::read_file_content(doc_uri.path(), rt.config.file_encoding()).await?,
+    };
+
+    let lang = crate::method::document_language_id(&rt, doc_uri);
+    let offset = match byte_offset_of(&content, doc_pos, rt.position_utf16) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let (word, active_parameter) = match enclosing_call(&content[..offset], &lang) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let name = crate::method::tag_name_of(&word);
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let mut signature_list = Vec::<SignatureInformation>::new();
+    for tags_file in rt.tags.iter() {
+        for entry in tags_file.lookup(name) {
+            if !is_callable(entry) || !crate::method::tag_target_exists(tags_file, entry).await {
+                continue;
+            }
+            let loc = match crate::method::find_tag_location(
+                tags_file,
+                entry,
+                false,
+                crate::tags::AddressResolution::Verify,
+                &cache,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let content = cache.read(loc.uri.path()).await?;
+            let label = match content.lines().nth(loc.range.start.line as usize) {
+                Some(v) => v.trim().trim_end_matches(['{', ';']).trim_end(),
+                None => continue,
+            };
+            if !label.contains('(') || signature_list.iter().any(|v| v.label == label) {
+                continue;
+            }
+
+            let parameters = split_parameters(label)
+                .into_iter()
+                .map(|v| ParameterInformation {
+                    label: ParameterLabel::Simple(v),
+                    documentation: None,
+                })
+                .collect();
+            signature_list.push(SignatureInformation {
+                label: label.to_string(),
+                documentation: Some(Documentation::String(format!(
+                    "{}:{}",
+                    loc.uri.path(),
+                    loc.range.start.line + 1
+                ))),
+                parameters: Some(parameters),
+                active_parameter: None,
+            });
+
+            if signature_list.len() >= SIGNATURE_LIMIT {
+                break;
+            }
+        }
+
+        if signature_list.len() >= SIGNATURE_LIMIT {
+            break;
+        }
+    }
+
+    if signature_list.is_empty() {
+        return Ok(None);
+    }
+
+    return Ok(Some(SignatureHelp {
+        signatures: signature_list,
+        active_signature: Some(0),
+        active_parameter: Some(active_parameter),
+    }));
+}
+
+/// Whether tag entry can be called with arguments.
+///
+/// # Arguments
+///
+/// + `entry`: Tag entry.
+pub fn is_callable(entry: &crate::tags::TagEntry) -> bool {
+    let kind = match entry.kind.as_deref() {
+        Some(v) => crate::tags::kind_name(v),
+        None => return false,
+    };
+
+    // Function-like macros are told apart by the `(` of definition line.
+    return matches!(
+        kind,
+        "function" | "prototype" | "method" | "macro" | "constructor"
+    );
+}
+
+/// Convert position into byte offset of content.
+///
+/// # Arguments
+///
+/// + `content`: Document content.
+/// + `pos`: Position in document.
+/// + `utf16`: `pos` counts UTF-16 code units instead of bytes.
+fn byte_offset_of(content: &str, pos: &Position, utf16: bool) -> Option<usize> {
+    let mut line_start = 0;
+    for _ in 0..pos.line {
+        line_start += content[line_start..].find('\n')? + 1;
+    }
+
+    let line = content[line_start..].split('\n').next().unwrap_or_default();
+    let column = match utf16 {
+        true => crate::method::utf16_to_byte_offset(line, pos.character),
+        false => (pos.character as usize).min(line.len()),
+    };
+    return Some(line_start + column);
+}
+
+/// Find the call that encloses the end of text.
+///
+/// Brackets in strings and comments are not skipped, so the result is only a
+/// guess.
+///
+/// # Arguments
+///
+/// + `text`: Document content before cursor.
+/// + `lang`: LSP language id, decides the word boundaries.
+///
+/// # Returns
+///
+/// Name of the called function, and index of the argument under cursor.
+fn enclosing_call(text: &str, lang: &str) -> Option<(String, u32)> {
+    let bytes = text.as_bytes();
+
+    let mut depth = 0;
+    let mut comma = 0;
+    let mut open = None;
+    for idx in (0..bytes.len()).rev() {
+        match bytes[idx] {
+            b')' | b']' => depth += 1,
+            b'(' | b'[' if depth > 0 => depth -= 1,
+            b'(' => {
+                open = Some(idx);
+                break;
+            }
+            b'[' => return None,
+            b',' if depth == 0 => comma += 1,
+            b';' | b'{' | b'}' if depth == 0 => return None,
+            _ => (),
+        }
+    }
+
+    let before = text[..open?].trim_end();
+    let line = before.rsplit('\n').next().unwrap_or(before);
+    let (word, range) = crate::method::word_at(line, line.len(), lang)?;
+    if range.end != line.len() {
+        return None;
+    }
+
+    return Some((word, comma));
+}
+
+/// Split parameters of signature by top level commas.
+///
+/// # Arguments
+///
+/// + `label`: Signature text, e.g. `int add(int a, int b)`.
+fn split_parameters(label: &str) -> Vec<String> {
+    let start = match label.find('(') {
+        Some(v) => v + 1,
+        None => return Vec::new(),
+    };
+
+    let mut ret = Vec::new();
+    let mut depth = 0;
+    let mut begin = start;
+    for (idx, c) in label[start..].char_indices() {
+        let idx = start + idx;
+        match c {
+            '(' | '[' | '<' | '{' => depth += 1,
+            ')' if depth == 0 => {
+                ret.push(&label[begin..idx]);
+                begin = label.len();
+                break;
+            }
+            ')' | ']' | '>' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                ret.push(&label[begin..idx]);
+                begin = idx + 1;
+            }
+            _ => (),
+        }
+    }
+
+    // Parameters may continue on the next lines.
+    if begin < label.len() {
+        ret.push(&label[begin..]);
+    }
+
+    return ret
+        .into_iter()
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn signature_of_called_function() {
+        let (mut client, uri) = crate::testing::start().await;
+
+        // Second argument of `return add(1, 2);`.
+        let params = SignatureHelpParams {
+            context: None,
+            text_document_position_params: crate::testing::position(&uri, 7, 18),
+            work_done_progress_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::SignatureHelpRequest>(params)
+            .await
+            .unwrap()
+            .expect("no signature");
+
+        assert_eq!(ret.signatures.len(), 1);
+        assert_eq!(ret.signatures[0].label, "int add(int a, int b)");
+        assert_eq!(ret.active_parameter, Some(1));
+        let parameters: Vec<_> = ret.signatures[0]
+            .parameters
+            .iter()
+            .flatten()
+            .map(|v| v.label.clone())
+            .collect();
+        assert_eq!(
+            parameters,
+            [
+                ParameterLabel::Simple(String::from("int a")),
+                ParameterLabel::Simple(String::from("int b")),
+            ]
+        );
+
+        client.shutdown().await;
+    }
+
+    #[test]
+    fn find_enclosing_call() {
+        assert_eq!(
+            enclosing_call("add(1, ", "c"),
+            Some((String::from("add"), 1))
+        );
+        assert_eq!(
+            enclosing_call("f(g(1, 2), ", "c"),
+            Some((String::from("f"), 1))
+        );
+        assert_eq!(enclosing_call("f(g(", "c"), Some((String::from("g"), 0)));
+        assert_eq!(enclosing_call("f(1); x", "c"), None);
+        assert_eq!(enclosing_call("(a, ", "c"), None);
+    }
+}
@@ -0,0 +1,137 @@
+use tower_lsp::lsp_types::*;
+
+/// Number of results in a response, for logging.
+pub trait ResultCount {
+    /// Get number of results, `None` if not countable.
+    fn result_count(&self) -> Option<usize>;
+}
+
+impl ResultCount for () {
+    fn result_count(&self) -> Option<usize> {
+        return None;
+    }
+}
+
+impl ResultCount for InitializeResult {
+    fn result_count(&self) -> Option<usize> {
+        return None;
+    }
+}
+
+impl ResultCount for CodeLens {
+    fn result_count(&self) -> Option<usize> {
+        return None;
+    }
+}
+
+impl ResultCount for Hover {
+    fn result_count(&self) -> Option<usize> {
+        return None;
+    }
+}
+
+impl ResultCount for SignatureHelp {
+    fn result_count(&self) -> Option<usize> {
+        return Some(self.signatures.len());
+    }
+}
+
+impl ResultCount for serde_json::Value {
+    fn result_count(&self) -> Option<usize> {
+        return None;
+    }
+}
+
+impl<T> ResultCount for Vec<T> {
+    fn result_count(&self) -> Option<usize> {
+        return Some(self.len());
+    }
+}
+
+impl ResultCount for GotoDefinitionResponse {
+    fn result_count(&self) -> Option<usize> {
+        match self {
+            GotoDefinitionResponse::Scalar(_) => return Some(1),
+            GotoDefinitionResponse::Array(v) => return Some(v.len()),
+            GotoDefinitionResponse::Link(v) => return Some(v.len()),
+        }
+    }
+}
+
+impl ResultCount for DocumentSymbolResponse {
+    fn result_count(&self) -> Option<usize> {
+        match self {
+            DocumentSymbolResponse::Flat(v) => return Some(v.len()),
+            DocumentSymbolResponse::Nested(v) => return Some(v.len()),
+        }
+    }
+}
+
+impl ResultCount for CompletionResponse {
+    fn result_count(&self) -> Option<usize> {
+        match self {
+            CompletionResponse::Array(v) => return Some(v.len()),
+            CompletionResponse::List(v) => return Some(v.items.len()),
+        }
+    }
+}
+
+impl<T: ResultCount> ResultCount for Option<T> {
+    fn result_count(&self) -> Option<usize> {
+        match self {
+            Some(v) => return v.result_count(),
+            None => return Some(0),
+        }
+    }
+}
+
+/// Run handler of LSP method and log how long it takes at DEBUG.
+///
+/// The handler runs in a span named after the method, so logs of the handler
+/// can be told apart.
+///
+/// # Arguments
+///
+/// + `method`: Name of the handler, e.g. `goto_definition`.
+/// + `fut`: The handler, including waiting for locks.
+pub async fn timed<F, T>(method: &str, fut: F) -> T
+where
+    F: std::future::Future<Output = T>,
+    T: TimedOutput,
+{
+    use tracing::Instrument;
+
+    let start = std::time::Instant::now();
+    let ret = fut
+        .instrument(tracing::debug_span!("request", method))
+        .await;
+    let elapsed = start.elapsed();
+
+    match ret.summary() {
+        Some(v) => tracing::debug!("{} took {:.1?} ({})", method, elapsed, v),
+        None => tracing::debug!("{} took {:.1?}", method, elapsed),
+    }
+
+    return ret;
+}
+
+/// Output of handler that can be summarized for logging.
+pub trait TimedOutput {
+    /// Get summary of output, e.g. `2 results`.
+    fn summary(&self) -> Option<String>;
+}
+
+impl TimedOutput for () {
+    fn summary(&self) -> Option<String> {
+        return None;
+    }
+}
+
+impl<T: ResultCount> TimedOutput for tower_lsp::jsonrpc::Result<T> {
+    fn summary(&self) -> Option<String> {
+        match self {
+            Ok(v) => return v.result_count().map(|v| format!("{} results", v)),
+            Err(e) => return Some(format!("error: {}", e.message)),
+        }
+    }
+}
@@ -4,6 +4,141 @@ pub async fn goto_type_definition(
     backend: &crate::TagsLspBackend,
     params: request::GotoTypeDefinitionParams,
 ) -> tower_lsp::jsonrpc::Result<Option<request::GotoTypeDefinitionResponse>> {
-    let ret = crate::method::definition::goto_definition(backend, params).await?;
-    return Ok(ret);
+    let file_uri = &params.text_document_position_params.text_document.uri;
+    let file_position = &params.text_document_position_params.position;
+    let rt = backend.snapshot(Some(file_uri)).await;
+    let cwd = crate::method::find_belong_workspace_folder(
+        &rt.workspace_folders,
+        file_uri,
+        rt.config.ignore_case_fs(),
+    )?;
+
+    // GNU Global does not record types, jump to definition instead.
+    if crate::index::detect(&rt.config, &cwd) == crate::index::BackendKind::Global {
+        drop(rt);
+        let ret = crate::method::definition::goto_definition(backend, params).await?;
+        return Ok(ret);
+    }
+
+    // Get symbol from position.
+    let symbol = crate::method::get_symbol_by_position(&rt, file_uri, file_position).await?;
+    let symbol = crate::method::tag_name_of(&symbol);
+
+    let lang = crate::method::document_language(&rt, file_uri);
+
+    let type_name = match find_type_name(&rt.tags, symbol, lang) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let type_name = crate::method::tag_name_of(&type_name);
+
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let index = crate::index::select(&rt, &cwd, &cache);
+    let mut loc_list = index.lookup(type_name, lang).await?;
+    crate::method::limit_results(&mut loc_list, rt.config.max_results(), type_name);
+    crate::method::encode_locations(&mut loc_list, rt.position_utf16, &cache).await;
+    crate::method::canonicalize_locations(&mut loc_list, &rt.config).await;
+
+    if loc_list.is_empty() {
+        return Ok(None);
+    }
+
+    // Keep single location as scalar for client compatibility.
+    if loc_list.len() == 1 {
+        return Ok(Some(GotoDefinitionResponse::Scalar(loc_list.remove(0))));
+    }
+
+    return Ok(Some(GotoDefinitionResponse::Array(loc_list)));
+}
+
+/// Find type name of symbol from `typeref:` field of its entries.
+///
+/// Entries in the same language as `lang` are preferred.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+/// + `symbol`: Symbol name.
+/// + `lang`: Language of the requesting file, as named by ctags.
+fn find_type_name(
+    tags: &[std::sync::Arc<crate::tags::TagsFile>],
+    symbol: &str,
+    lang: Option<&str>,
+) -> Option<String> {
+    let mut fallback = None;
+
+    for tags_file in tags {
+        for entry in tags_file.lookup(symbol) {
+            let type_name = match entry.type_name() {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let same_language = match (entry.language(), lang) {
+                (Some(a), Some(b)) => crate::tags::is_same_language(a, b),
+                _ => true,
+            };
+            if same_language {
+                return Some(type_name.to_string());
+            }
+
+            if fallback.is_none() {
+                fallback = Some(type_name.to_string());
+            }
+        }
+    }
+
+    return fallback;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn type_of_variable() {
+        let dir = std::env::temp_dir().join(format!(
+            "tags-lsp-test-type-definition-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = "struct Foo {\n    int x;\n};\n\nstruct Foo foo;\nint bar;\n";
+        std::fs::write(dir.join("a.c"), content).unwrap();
+        let tags = "Foo\ta.c\t/^struct Foo {$/;\"\ts\n\
+                    bar\ta.c\t/^int bar;$/;\"\tv\n\
+                    foo\ta.c\t/^struct Foo foo;$/;\"\tv\ttyperef:struct:Foo\n";
+        std::fs::write(dir.join("tags-lsp-test-tags"), tags).unwrap();
+        let root = Url::from_directory_path(&dir).unwrap();
+        let uri = Url::from_file_path(dir.join("a.c")).unwrap();
+
+        let mut client = crate::testing::Client::start(&[
+            "--no-auto-generate",
+            "--tags-filename",
+            "tags-lsp-test-tags",
+        ]);
+        client.initialize(&root).await;
+
+        // `foo` of `struct Foo foo;` and `bar` of `int bar;`.
+        let mut ret = Vec::new();
+        for (line, character) in [(4, 12), (5, 5)] {
+            let params = request::GotoTypeDefinitionParams {
+                text_document_position_params: crate::testing::position(&uri, line, character),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            ret.push(
+                client
+                    .request::<request::GotoTypeDefinition>(params)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let expect = Location::new(uri, Range::new(Position::new(0, 7), Position::new(0, 10)));
+        assert_eq!(ret[0], Some(GotoDefinitionResponse::Scalar(expect)));
+        assert_eq!(ret[1], None);
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,56 @@
+use tower_lsp::lsp_types::*;
+
+pub async fn did_change_workspace_folders(
+    backend: &crate::TagsLspBackend,
+    params: DidChangeWorkspaceFoldersParams,
+) {
+    let mut rt = backend.rt.lock().await;
+
+    // Tags files listed explicitly do not belong to any folder.
+    let discover = rt.config.tags.is_empty() && rt.config.single_file.is_none();
+
+    for folder in &params.event.removed {
+        tracing::info!("Remove workspace folder {}", folder.uri);
+        rt.workspace_folders.retain(|v| v.uri != folder.uri);
+        rt.watchers.remove(&Some(folder.uri.clone()));
+    }
+
+    // Drop tags that no remaining folder uses.
+    if discover && !params.event.removed.is_empty() {
+        let keep: Vec<_> = rt
+            .workspace_folders
+            .iter()
+            .filter_map(|v| Some((v.uri.to_file_path().ok()?, rt.folder_config(&v.uri))))
+            .flat_map(|(dir, config)| crate::tags::discover_upward(&dir, &config.tags_filename))
+            .collect();
+        rt.tags
+            .update(|tags| tags.retain(|v| keep.contains(&v.path)));
+    }
+
+    for folder in &params.event.added {
+        tracing::info!("Add workspace folder {}", folder.uri);
+        rt.workspace_folders.push(folder.clone());
+    }
+
+    // Read `.tags-lsp.json` of new folders, and forget removed ones.
+    crate::method::initialize::configure(&mut rt);
+
+    let mut new_folders = Vec::new();
+    for folder in params.event.added {
+        if !discover {
+            continue;
+        }
+
+        let config = rt.folder_config(&folder.uri).clone();
+        for v in crate::method::initialized::load_folder_tags(&config, &folder) {
+            if rt.tags.load().iter().any(|t| t.path == v.path) {
+                continue;
+            }
+            crate::method::publish_tags_diagnostics(&backend.client, &rt.config, &v).await;
+            rt.tags.update(|tags| tags.push(std::sync::Arc::new(v)));
+        }
+        new_folders.push(folder);
+    }
+
+    crate::watch::watch_folders(&backend.client, &backend.rt, &mut rt, &new_folders);
+}
@@ -0,0 +1,634 @@
+use tower_lsp::lsp_types::*;
+
+/// The number of symbols sent in each `$/progress` notification.
+const PARTIAL_BATCH_SIZE: usize = 16;
+
+pub async fn symbol(
+    backend: &crate::TagsLspBackend,
+    params: WorkspaceSymbolParams,
+) -> tower_lsp::jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+    let rt = backend.snapshot(None).await;
+    let opts = SymbolOptions {
+        low_precision: rt.config.low_precision,
+        address_resolution: rt.config.address_resolution,
+        max_results: rt.config.max_results(),
+        utf16: rt.position_utf16,
+        detail_path: rt.symbol_detail_path,
+        sort: rt.symbol_sort,
+    };
+    let match_mode = rt.config.match_mode;
+    let workspace_folders = rt.workspace_folders.clone();
+
+    // Symbols are streamed with `$/progress` if client asks for.
+    let mut partial = params
+        .partial_result_params
+        .partial_result_token
+        .map(|token| PartialSender {
+            client: &backend.client,
+            token,
+            workspace_folders: &workspace_folders,
+            opts,
+            sent: 0,
+        });
+
+    // Search loaded tags first.
+    let cache = crate::cache::FileCache::new(rt.config.file_encoding());
+    let mut symbol_list = search_tags(
+        &rt.tags,
+        &params.query,
+        match_mode,
+        &opts,
+        rt.hide_anonymous,
+        partial.as_mut(),
+        &cache,
+    )
+    .await;
+
+    // Case-insensitive substring match, or prefix match.
+    let query = match match_mode {
+        crate::tags::MatchMode::Prefix => format!("{}.*", params.query),
+        _ => format!(".*{}.*", params.query),
+    };
+    search_gtags(
+        &rt,
+        &mut symbol_list,
+        &query,
+        &opts,
+        partial.as_mut(),
+        &cache,
+    )
+    .await;
+
+    finish_symbols(&mut symbol_list, &workspace_folders, opts, &cache).await;
+
+    // The whole result goes through `$/progress` once streaming starts.
+    if let Some(v) = partial.filter(|v| v.sent > 0) {
+        send_partial_result(v.client, &v.token, &symbol_list).await;
+        return Ok(Some(Vec::new()));
+    }
+
+    return Ok(Some(symbol_list));
+}
+
+/// Options of how symbols are searched and returned.
+#[derive(Debug, Clone, Copy)]
+struct SymbolOptions {
+    /// Do not find precise position, just point to begin of line.
+    low_precision: bool,
+
+    /// Whether to confirm addresses against file contents.
+    address_resolution: crate::tags::AddressResolution,
+
+    /// The maximum number of symbols to return.
+    max_results: usize,
+
+    /// Whether client uses UTF-16 offsets.
+    utf16: bool,
+
+    /// Show relative path and line number of symbols.
+    detail_path: bool,
+
+    /// Order of symbols.
+    sort: SymbolSort,
+}
+
+/// Order of `workspace/symbol` results.
+///
+/// Results are always picked by score, so the order does not change which
+/// symbols are returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolSort {
+    /// Best matches first.
+    #[default]
+    Score,
+
+    /// Types, then functions, then variables, each sorted by name.
+    Kind,
+
+    /// Sorted by name, ignoring case.
+    Name,
+}
+
+impl SymbolSort {
+    /// Sort symbols, keep the order of score if `SymbolSort::Score`.
+    ///
+    /// # Arguments
+    ///
+    /// + `symbol_list`: Symbols in order of score.
+    fn sort(&self, symbol_list: &mut [SymbolInformation]) {
+        let name = |v: &SymbolInformation| v.name.to_lowercase();
+        match self {
+            SymbolSort::Score => (),
+            SymbolSort::Kind => symbol_list.sort_by_key(|v| (kind_rank(v.kind), name(v))),
+            SymbolSort::Name => symbol_list.sort_by_key(|v| (name(v), kind_rank(v.kind))),
+        }
+    }
+}
+
+/// Group of symbol kind for `SymbolSort::Kind`, smaller comes first.
+///
+/// # Arguments
+///
+/// + `kind`: Symbol kind.
+fn kind_rank(kind: SymbolKind) -> u8 {
+    match kind {
+        SymbolKind::NAMESPACE
+        | SymbolKind::MODULE
+        | SymbolKind::PACKAGE
+        | SymbolKind::CLASS
+        | SymbolKind::STRUCT
+        | SymbolKind::INTERFACE
+        | SymbolKind::ENUM
+        | SymbolKind::TYPE_PARAMETER => return 0,
+        SymbolKind::FUNCTION
+        | SymbolKind::METHOD
+        | SymbolKind::CONSTRUCTOR
+        | SymbolKind::OPERATOR => return 1,
+        SymbolKind::VARIABLE
+        | SymbolKind::FIELD
+        | SymbolKind::PROPERTY
+        | SymbolKind::CONSTANT
+        | SymbolKind::ENUM_MEMBER => return 2,
+        _ => return 3,
+    }
+}
+
+/// Search loaded tags files for symbols that fuzzy match query.
+///
+/// Symbols are ranked by score, so exact and prefix matches come first.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+/// + `query`: The query string, matched case-insensitively.
+/// + `match_mode`: Only match the start of names in `MatchMode::Prefix`.
+/// + `opts`: How symbols are searched.
+/// + `hide_anonymous`: Hide or rename anonymous types.
+/// + `partial`: Sends full batches of symbols to client, if streaming.
+/// + `cache`: Cache of file contents for current request.
+async fn search_tags(
+    tags: &[std::sync::Arc<crate::tags::TagsFile>],
+    query: &str,
+    match_mode: crate::tags::MatchMode,
+    opts: &SymbolOptions,
+    hide_anonymous: bool,
+    mut partial: Option<&mut PartialSender<'_>>,
+    cache: &crate::cache::FileCache,
+) -> Vec<SymbolInformation> {
+    use fuzzy_matcher::FuzzyMatcher;
+
+    let matcher = fuzzy_matcher::skim::SkimMatcherV2::default().ignore_case();
+    let query_lower = query.to_lowercase();
+
+    // Dropped without finishing if client cancels the request.
+    let guard = crate::method::CancelGuard::new("Symbol search");
+
+    let mut candidate_list = Vec::new();
+    for tags_file in tags {
+        for (idx, entry) in tags_file.entries.iter().enumerate() {
+            // Give cancellation a chance in huge tags files.
+            if idx % 4096 == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            // Match the name shown to user.
+            let name = match hide_anonymous {
+                true => match tags_file.readable_name(&entry.name) {
+                    Some(v) => v,
+                    None => continue,
+                },
+                false => entry.name.as_str(),
+            };
+
+            if match_mode == crate::tags::MatchMode::Prefix
+                && !name.to_lowercase().starts_with(&query_lower)
+            {
+                continue;
+            }
+
+            if let Some(score) = matcher.fuzzy_match(name, query) {
+                candidate_list.push((score, tags_file, entry));
+            }
+        }
+    }
+
+    // Stable sort keeps tags file order for equal scores.
+    candidate_list.sort_by_key(|v| std::cmp::Reverse(v.0));
+
+    let max_results = opts.max_results;
+    if candidate_list.len() > max_results {
+        tracing::debug!("Return {} of {} symbols", max_results, candidate_list.len());
+    }
+
+    // Several tags files may cover the same code.
+    let mut seen = std::collections::HashSet::new();
+    let mut symbol_list = Vec::<SymbolInformation>::new();
+    for (_, tags_file, entry) in candidate_list {
+        let sent = partial.as_ref().map_or(0, |v| v.sent);
+        if sent + symbol_list.len() >= max_results {
+            break;
+        }
+
+        tokio::task::yield_now().await;
+        if !crate::method::tag_target_exists(tags_file, entry).await {
+            continue;
+        }
+        let loc = match crate::method::find_tag_location(
+            tags_file,
+            entry,
+            opts.low_precision,
+            opts.address_resolution,
+            cache,
+        )
+        .await
+        {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if !seen.insert((&entry.name, loc.uri.clone(), loc.range.start.line)) {
+            continue;
+        }
+
+        let info = crate::method::create_tag_symbol_information(entry, loc);
+        let info = match hide_anonymous {
+            true => match crate::method::deanonymize_symbol(tags_file, info) {
+                Some(v) => v,
+                None => continue,
+            },
+            false => info,
+        };
+        symbol_list.push(info);
+
+        if let Some(v) = partial.as_mut() {
+            v.flush(&mut symbol_list, cache).await;
+        }
+    }
+    guard.finish();
+
+    return symbol_list;
+}
+
+/// Prepare symbols for returning to client.
+///
+/// Symbols sent as partial results are sorted within each batch.
+///
+/// # Arguments
+///
+/// + `symbol_list`: Found symbols.
+/// + `workspace_folders`: Workspace folders.
+/// + `opts`: Search options.
+/// + `cache`: Cache of file contents for current request.
+async fn finish_symbols(
+    symbol_list: &mut [SymbolInformation],
+    workspace_folders: &[WorkspaceFolder],
+    opts: SymbolOptions,
+    cache: &crate::cache::FileCache,
+) {
+    if opts.detail_path {
+        for info in symbol_list.iter_mut() {
+            add_detail_path(info, workspace_folders);
+        }
+    }
+
+    crate::method::encode_symbol_locations(symbol_list, opts.utf16, cache).await;
+    opts.sort.sort(symbol_list);
+}
+
+/// Show `path:line` of symbol in container name, which clients display as
+/// detail.
+///
+/// Path is relative to the nearest workspace folder that contains it.
+///
+/// # Arguments
+///
+/// + `info`: Symbol information.
+/// + `workspace_folders`: Workspace folders.
+fn add_detail_path(info: &mut SymbolInformation, workspace_folders: &[WorkspaceFolder]) {
+    let path = std::path::Path::new(info.location.uri.path());
+    let relative = workspace_folders
+        .iter()
+        .filter_map(|v| path.strip_prefix(v.uri.path()).ok())
+        .min_by_key(|v| v.components().count())
+        .unwrap_or(path);
+
+    let detail = format!(
+        "{}:{}",
+        relative.display(),
+        info.location.range.start.line + 1
+    );
+    info.container_name = match info.container_name.take() {
+        Some(v) => Some(format!("{} ({})", v, detail)),
+        None => Some(detail),
+    };
+}
+
+/// Search GNU Global databases for symbols until `max_results` reached.
+///
+/// Folders served by ctags are skipped, their symbols come from loaded tags.
+/// A folder whose database cannot be queried is skipped too.
+///
+/// # Arguments
+///
+/// + `rt`: Snapshot of Runtime.
+/// + `symbol_list`: Found symbols, new symbols are appended.
+/// + `query`: The query pattern.
+/// + `opts`: How symbols are searched.
+/// + `partial`: Sends full batches of symbols to client, if streaming.
+/// + `cache`: Cache of file contents for current request.
+async fn search_gtags(
+    rt: &crate::Snapshot,
+    symbol_list: &mut Vec<SymbolInformation>,
+    query: &str,
+    opts: &SymbolOptions,
+    mut partial: Option<&mut PartialSender<'_>>,
+    cache: &crate::cache::FileCache,
+) {
+    let sent = |v: &Option<&mut PartialSender<'_>>| v.as_ref().map_or(0, |v| v.sent);
+    if sent(&partial) + symbol_list.len() >= opts.max_results {
+        return;
+    }
+
+    for ele in &rt.workspace_folders {
+        if crate::index::detect(&rt.config, ele) != crate::index::BackendKind::Global {
+            continue;
+        }
+
+        let index = crate::index::select(rt, ele, cache);
+        let found = match index.symbols(query).await {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("Cannot search symbols in {}: {}", ele.uri, e.message);
+                continue;
+            }
+        };
+
+        for info in found {
+            symbol_list.push(info);
+
+            if sent(&partial) + symbol_list.len() >= opts.max_results {
+                tracing::debug!("Return the first {} symbols", opts.max_results);
+                return;
+            }
+            if let Some(v) = partial.as_mut() {
+                v.flush(symbol_list, cache).await;
+            }
+        }
+    }
+}
+
+/// Sends symbols to client in batches with `$/progress`, before the response
+/// of request.
+struct PartialSender<'a> {
+    /// LSP client.
+    client: &'a tower_lsp::Client,
+
+    /// Partial result token from client.
+    token: NumberOrString,
+
+    /// Workspace folders, for preparing symbols.
+    workspace_folders: &'a [WorkspaceFolder],
+
+    /// Search options, for preparing symbols.
+    opts: SymbolOptions,
+
+    /// Number of symbols sent.
+    sent: usize,
+}
+
+impl PartialSender<'_> {
+    /// Send symbols to client if there are enough for a batch.
+    ///
+    /// # Arguments
+    ///
+    /// + `symbol_list`: Symbols not sent yet, cleared after sending.
+    /// + `cache`: Cache of file contents for current request.
+    async fn flush(
+        &mut self,
+        symbol_list: &mut Vec<SymbolInformation>,
+        cache: &crate::cache::FileCache,
+    ) {
+        if symbol_list.len() < PARTIAL_BATCH_SIZE {
+            return;
+        }
+
+        finish_symbols(symbol_list, self.workspace_folders, self.opts, cache).await;
+        send_partial_result(self.client, &self.token, symbol_list).await;
+        self.sent += symbol_list.len();
+        symbol_list.clear();
+    }
+}
+
+enum TagsLspProgress {}
+
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TagsLspProgressParams {
+    token: ProgressToken,
+    value: TagsLspProgressParamsValue,
+}
+
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(untagged)]
+enum TagsLspProgressParamsValue {
+    WorkDone(WorkDoneProgress),
+    Symbol(Option<Vec<SymbolInformation>>),
+}
+
+impl notification::Notification for TagsLspProgress {
+    type Params = TagsLspProgressParams;
+    const METHOD: &'static str = "$/progress";
+}
+
+async fn send_partial_result(
+    client: &tower_lsp::Client,
+    token: &NumberOrString,
+    symbol_list: &[SymbolInformation],
+) {
+    let symbol_list: Option<Vec<SymbolInformation>> = if symbol_list.is_empty() {
+        None
+    } else {
+        Some(symbol_list.to_vec())
+    };
+
+    client
+        .send_notification::<TagsLspProgress>(TagsLspProgressParams {
+            token: token.clone(),
+            value: TagsLspProgressParamsValue::Symbol(symbol_list),
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Search symbols of `affable`, `foo_bar` and `other` in a temporary
+    /// workspace.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Unique name of temporary workspace.
+    /// + `query`: The query string.
+    /// + `match_mode`: How to match names.
+    async fn search(name: &str, query: &str, match_mode: crate::tags::MatchMode) -> Vec<String> {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.c"), "int affable;\nint foo_bar;\nint other;\n").unwrap();
+
+        let entries = ["affable\ta.c\t1", "foo_bar\ta.c\t2", "other\ta.c\t3"]
+            .iter()
+            .filter_map(|v| crate::tags::parse_line(v))
+            .collect();
+        let tags = vec![std::sync::Arc::new(crate::tags::TagsFile::new(
+            dir.join("tags"),
+            entries,
+        ))];
+
+        let opts = SymbolOptions {
+            low_precision: false,
+            address_resolution: Default::default(),
+            max_results: 100,
+            utf16: false,
+            detail_path: false,
+            sort: SymbolSort::Score,
+        };
+        let cache = crate::cache::FileCache::new(encoding_rs::UTF_8);
+        let ret = search_tags(&tags, query, match_mode, &opts, true, None, &cache).await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        return ret.into_iter().map(|v| v.name).collect();
+    }
+
+    #[tokio::test]
+    async fn rank_by_fuzzy_score() {
+        let ret = search("rank", "fb", crate::tags::MatchMode::Exact).await;
+        assert_eq!(ret, ["foo_bar", "affable"]);
+
+        let ret = search("rank-case", "FOO", crate::tags::MatchMode::Exact).await;
+        assert_eq!(ret, ["foo_bar"]);
+    }
+
+    #[tokio::test]
+    async fn prefix_match_only() {
+        let ret = search("prefix", "a", crate::tags::MatchMode::Prefix).await;
+        assert_eq!(ret, ["affable"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detail_path_relative_to_nearest_folder() {
+        let folders = ["/ws", "/ws/lib"].map(|v| WorkspaceFolder {
+            uri: Url::from_file_path(v).unwrap(),
+            name: String::from(v),
+        });
+        let info = |path: &str, line: u32, scope: Option<&str>| {
+            let entry = crate::tags::TagEntry {
+                scope: scope.map(String::from),
+                ..crate::tags::parse_line("foo\ta.c\t1").unwrap()
+            };
+            let range = Range::new(Position::new(line, 0), Position::new(line, 0));
+            let loc = Location::new(Url::from_file_path(path).unwrap(), range);
+            let mut info = crate::method::create_tag_symbol_information(&entry, loc);
+            add_detail_path(&mut info, &folders);
+            return info.container_name.unwrap();
+        };
+
+        assert_eq!(info("/ws/src/a.c", 9, None), "src/a.c:10");
+        assert_eq!(info("/ws/lib/b.c", 0, None), "b.c:1");
+        assert_eq!(info("/other/c.c", 4, Some("Foo")), "Foo (/other/c.c:5)");
+    }
+
+    #[test]
+    fn sort_by_kind_then_name() {
+        let uri = Url::parse("file:///src/a.c").unwrap();
+        let lines = [
+            "count\ta.c\t1;\"\tv",
+            "Point\ta.c\t2;\"\ts",
+            "add\ta.c\t3;\"\tf",
+            "Buffer\ta.c\t4;\"\tc",
+            "main\ta.c\t5;\"\tf",
+            "Add\ta.c\t6;\"\tv",
+        ];
+        let symbol_list: Vec<_> = lines
+            .iter()
+            .filter_map(|v| crate::tags::parse_line(v))
+            .map(|v| {
+                let loc = Location::new(uri.clone(), Range::default());
+                crate::method::create_tag_symbol_information(&v, loc)
+            })
+            .collect();
+        let sorted = |sort: SymbolSort| {
+            let mut list = symbol_list.clone();
+            sort.sort(&mut list);
+            return list.into_iter().map(|v| v.name).collect::<Vec<_>>();
+        };
+
+        assert_eq!(
+            sorted(SymbolSort::Kind),
+            ["Buffer", "Point", "add", "main", "Add", "count"]
+        );
+        assert_eq!(
+            sorted(SymbolSort::Name),
+            ["add", "Add", "Buffer", "count", "main", "Point"]
+        );
+        assert_eq!(
+            sorted(SymbolSort::Score),
+            ["count", "Point", "add", "Buffer", "main", "Add"]
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_symbols_in_batches() {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-partial-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let names: Vec<_> = (0..40).map(|i| format!("sym_{:02}", i)).collect();
+        let content: String = names.iter().map(|v| format!("int {};\n", v)).collect();
+        let tags: String = names
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{}\ta.c\t{};\"\tv\n", v, i + 1))
+            .collect();
+        std::fs::write(dir.join("a.c"), content).unwrap();
+        std::fs::write(dir.join("tags-lsp-test-tags"), tags).unwrap();
+
+        let args = [
+            "--no-auto-generate",
+            "--tags-filename",
+            "tags-lsp-test-tags",
+        ];
+        let mut client = crate::testing::Client::start(&args);
+        client
+            .initialize(&Url::from_directory_path(&dir).unwrap())
+            .await;
+
+        let params = WorkspaceSymbolParams {
+            query: String::from("sym"),
+            work_done_progress_params: Default::default(),
+            partial_result_params: PartialResultParams {
+                partial_result_token: Some(NumberOrString::String(String::from("symbols"))),
+            },
+        };
+        let ret = client
+            .request::<request::WorkspaceSymbolRequest>(params)
+            .await
+            .unwrap();
+        assert_eq!(ret, Some(WorkspaceSymbolResponse::Flat(Vec::new())));
+
+        let is_batch = |v: &serde_json::Value| {
+            return v["method"] == "$/progress" && v["params"]["token"] == "symbols";
+        };
+        let batches: Vec<_> = client
+            .wait_notifications(is_batch, 3)
+            .await
+            .iter()
+            .map(|v| v["params"]["value"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(batches, [16, 16, 8]);
+
+        client.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
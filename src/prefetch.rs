@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+/// Read source files that most tags point to, so that the first jumps do not
+/// wait for disk.
+///
+/// Files are read in order of how many tags point to them, until `budget`
+/// bytes are read. Files larger than the remaining budget are skipped. The
+/// content is thrown away, only the page cache of OS keeps it.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+/// + `budget`: The maximum number of bytes to read.
+///
+/// # Returns
+///
+/// Handle of the prefetch task, aborting it stops reading.
+pub fn spawn_prefetch(
+    tags: Arc<Vec<Arc<crate::tags::TagsFile>>>,
+    budget: u64,
+) -> tokio::task::AbortHandle {
+    let task = tokio::task::spawn(async move {
+        let start = std::time::Instant::now();
+
+        let file_list = match tokio::task::spawn_blocking(move || rank_files(&tags)).await {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        let (count, total) = prefetch_files(file_list, budget).await;
+
+        tracing::info!(
+            "Prefetched {} files, {} of {} bytes in {:?}",
+            count,
+            total,
+            budget,
+            start.elapsed()
+        );
+    });
+
+    return task.abort_handle();
+}
+
+/// Read files in order until `budget` bytes are read.
+///
+/// # Arguments
+///
+/// + `file_list`: Files to read, in order.
+/// + `budget`: The maximum number of bytes to read.
+///
+/// # Returns
+///
+/// Number of files read, and the total bytes of them.
+async fn prefetch_files(file_list: Vec<PathBuf>, budget: u64) -> (usize, u64) {
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total: u64 = 0;
+    let mut count = 0;
+    for path in file_list {
+        // Requests go first, prefetch only uses idle time.
+        tokio::task::yield_now().await;
+
+        let size = match tokio::fs::metadata(&path).await {
+            Ok(v) => v.len(),
+            Err(_) => continue,
+        };
+        if total + size > budget {
+            tracing::debug!(
+                "Skip prefetch of {}: {} bytes exceeds budget",
+                path.display(),
+                size
+            );
+            continue;
+        }
+
+        if let Ok(mut file) = tokio::fs::File::open(&path).await {
+            while let Ok(n) = file.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+            }
+            total += size;
+            count += 1;
+        }
+    }
+
+    return (count, total);
+}
+
+/// Sort files pointed to by tags, the most pointed first.
+///
+/// # Arguments
+///
+/// + `tags`: Loaded tags files.
+fn rank_files(tags: &[Arc<crate::tags::TagsFile>]) -> Vec<PathBuf> {
+    let mut count = HashMap::<PathBuf, usize>::new();
+    for tags_file in tags {
+        for entry in &tags_file.entries {
+            *count.entry(tags_file.entry_path(entry)).or_default() += 1;
+        }
+    }
+
+    let mut ret: Vec<_> = count.into_iter().collect();
+    ret.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    return ret.into_iter().map(|v| v.0).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn prefetch_within_budget() {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-prefetch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, size) in [("a.c", 100), ("b.c", 300), ("c.c", 50), ("d.c", 60)] {
+            std::fs::write(dir.join(name), vec![b'x'; size]).unwrap();
+        }
+
+        // `b.c` is the most pointed, but larger than budget.
+        let lines = [
+            "b\tb.c\t1",
+            "b\tb.c\t2",
+            "b\tb.c\t3",
+            "a\ta.c\t1",
+            "a\ta.c\t2",
+            "c\tc.c\t1",
+            "d\td.c\t1",
+            "e\tgone.c\t1",
+        ];
+        let entries = lines
+            .iter()
+            .filter_map(|v| crate::tags::parse_line(v))
+            .collect();
+        let tags = crate::tags::TagsFile::new(dir.join("tags"), entries);
+
+        let file_list = rank_files(&[Arc::new(tags)]);
+        let names: Vec<_> = file_list
+            .iter()
+            .map(|v| v.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["b.c", "a.c", "c.c", "d.c", "gone.c"]);
+
+        assert_eq!(prefetch_files(file_list.clone(), 200).await, (2, 150));
+        assert_eq!(prefetch_files(file_list.clone(), 500).await, (3, 450));
+        assert_eq!(prefetch_files(file_list, 0).await, (0, 0));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+/// File name of etags database, as generated by `etags` or `ctags -e`.
+pub const ETAGS_FILENAME: &str = "TAGS";
+
+/// Form feed that starts each file section.
+const SECTION_START: char = '\x0c';
+
+/// Check if content looks like an etags file.
+///
+/// # Arguments
+///
+/// + `path`: Path to tags file.
+/// + `content`: Content of tags file.
+pub fn is_etags(path: &std::path::Path, content: &[u8]) -> bool {
+    return path.file_name().is_some_and(|v| v == ETAGS_FILENAME)
+        || content.first() == Some(&(SECTION_START as u8));
+}
+
+/// Parse etags file content.
+///
+/// Each file section looks like:
+///
+/// ```txt
+/// \x0c
+/// file,size
+/// pattern\x7fname\x01line,offset
+/// pattern\x7fline,offset
+/// ```
+///
+/// The name may be omitted, then it is the last identifier of pattern.
+/// Sections of `file,include` only reference other tags files and have no
+/// entries.
+///
+/// # Arguments
+///
+/// + `content`: Content of tags file.
+///
+/// # Returns
+///
+/// Tag entries and line numbers of malformed lines, starting from 0.
+pub fn parse_content(content: &str) -> (Vec<super::TagEntry>, Vec<u32>) {
+    let mut entries = Vec::new();
+    let mut malformed = Vec::new();
+
+    let mut file: Option<&str> = None;
+    let mut section_start = false;
+    for (line_no, line) in content.lines().enumerate() {
+        if line.starts_with(SECTION_START) {
+            section_start = true;
+            continue;
+        }
+
+        // The line after form feed names the source file.
+        if section_start {
+            section_start = false;
+            file = match line.rsplit_once(',') {
+                Some((_, "include")) => None,
+                Some((v, _)) if !v.is_empty() => Some(v),
+                _ => {
+                    malformed.push(line_no as u32);
+                    None
+                }
+            };
+            continue;
+        }
+
+        let entry = file.and_then(|v| parse_line(v, line));
+        match entry {
+            Some(v) => entries.push(v),
+            None => {
+                tracing::debug!("Skip malformed etags line: {}", line);
+                malformed.push(line_no as u32);
+            }
+        }
+    }
+
+    return (entries, malformed);
+}
+
+/// Parse one tag line of etags file.
+///
+/// # Arguments
+///
+/// + `file`: Source file of current section.
+/// + `line`: The line to parse.
+fn parse_line(file: &str, line: &str) -> Option<super::TagEntry> {
+    let (pattern, rest) = line.split_once('\x7f')?;
+    let (name, position) = match rest.split_once('\x01') {
+        Some((name, position)) => (name, position),
+        None => (implicit_name(pattern)?, rest),
+    };
+    if name.is_empty() {
+        return None;
+    }
+
+    // Line number may be omitted, leaving only byte offset. Pattern is the
+    // beginning of line, so it still matches if the line moves.
+    let line = position.split(',').next().and_then(|v| v.parse().ok());
+    let address = match (pattern.is_empty(), line) {
+        (false, _) => super::TagAddress::Pattern(format!("^{}", escape_pattern(pattern))),
+        (true, Some(v)) => super::TagAddress::Line(v),
+        (true, None) => return None,
+    };
+
+    return Some(super::TagEntry {
+        name: name.to_string(),
+        file: file.to_string(),
+        address,
+        line,
+        end: None,
+        kind: None,
+        language: None,
+        scope: None,
+        scope_kind: None,
+        inherits: None,
+        typeref: None,
+    });
+}
+
+/// Get the last identifier of pattern, e.g. `add` of `int add(`.
+///
+/// # Arguments
+///
+/// + `pattern`: Beginning of the line where the tag is defined.
+fn implicit_name(pattern: &str) -> Option<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+    let trimmed = pattern.trim_end_matches(|c: char| !is_ident(c));
+    let start = trimmed
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident(*c))
+        .last()
+        .map(|(idx, _)| idx)?;
+
+    return Some(&trimmed[start..]);
+}
+
+/// Escape text so that it can be used as ctags search pattern.
+///
+/// # Arguments
+///
+/// + `text`: Literal text.
+fn escape_pattern(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\\' || c == '/' || c == '$' {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+
+    return ret;
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::*;
+
+    #[tokio::test]
+    async fn locations_of_etags_entries() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/etags");
+        let tags_file = crate::tags::parse(&dir.join("TAGS"), 0, encoding_rs::UTF_8).unwrap();
+        assert!(tags_file.malformed.is_empty());
+
+        let uri = Url::from_file_path(dir.join("lib.c")).unwrap();
+        let cache = crate::cache::FileCache::new(encoding_rs::UTF_8);
+        let mut ret = Vec::new();
+        for name in ["MAX", "add", "path", "counter"] {
+            let entry = tags_file.lookup(name).next().unwrap();
+            let loc = crate::method::find_tag_location(
+                &tags_file,
+                entry,
+                false,
+                Default::default(),
+                &cache,
+            )
+            .await
+            .unwrap();
+            assert_eq!(loc.uri, uri);
+            ret.push((
+                loc.range.start.line,
+                loc.range.start.character,
+                loc.range.end.character,
+            ));
+        }
+
+        assert_eq!(ret, [(0, 8, 11), (1, 4, 7), (5, 6, 10), (6, 4, 11)]);
+
+        // `/` and trailing `$` of pattern are literal text.
+        let entry = tags_file.lookup("path").next().unwrap();
+        let pattern = match &entry.address {
+            crate::tags::TagAddress::Pattern(v) => crate::tags::SearchPattern::new(v),
+            v => panic!("unexpected address {:?}", v),
+        };
+        assert_eq!(pattern.text, "char *path = \"/$");
+        assert!(!pattern.anchor_end);
+        let entry = tags_file.lookup("counter").next().unwrap();
+        assert_eq!(entry.address, crate::tags::TagAddress::Line(7));
+    }
+}
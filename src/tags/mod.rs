@@ -0,0 +1,1673 @@
+pub mod etags;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tower_lsp::lsp_types::*;
+
+/// Default file name of ctags database.
+pub const TAGS_FILENAME: &str = "tags";
+
+/// Address of a tag entry, e.g. where to find the symbol in file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TagAddress {
+    /// Line number, starting from 1.
+    Line(u32),
+
+    /// Ex search pattern with delimiters stripped, still escaped.
+    Pattern(String),
+}
+
+/// One line of ctags file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagEntry {
+    /// Symbol name.
+    pub name: String,
+
+    /// File path as recorded in tags file.
+    pub file: String,
+
+    /// Where to find the symbol in file.
+    pub address: TagAddress,
+
+    /// Line number where pattern address is expected to match, starting
+    /// from 1. Taken from `123;/pattern/` address or `line:` field.
+    pub line: Option<u32>,
+
+    /// Line number where the definition ends, starting from 1. Taken from
+    /// `end:` field, which ctags writes with `--fields=+e`.
+    pub end: Option<u32>,
+
+    /// Kind of tag, either a single letter or full name.
+    pub kind: Option<String>,
+
+    /// Language of source file, as named by ctags.
+    pub language: Option<String>,
+
+    /// Name of the enclosing scope, e.g. the class of a method.
+    pub scope: Option<String>,
+
+    /// Kind of the enclosing scope, e.g. `class`.
+    pub scope_kind: Option<String>,
+
+    /// Base classes or interfaces, separated by comma.
+    pub inherits: Option<String>,
+
+    /// Type of symbol, e.g. `struct:point`.
+    pub typeref: Option<String>,
+}
+
+impl TagEntry {
+    /// Get language of entry.
+    ///
+    /// Use `language:` field if present, otherwise guess from file extension.
+    pub fn language(&self) -> Option<&str> {
+        match &self.language {
+            Some(v) => Some(v.as_str()),
+            None => language_from_path(Path::new(&self.file)),
+        }
+    }
+
+    /// Get type name of entry from `typeref:` field.
+    ///
+    /// The kind prefix like `struct:` is stripped.
+    pub fn type_name(&self) -> Option<&str> {
+        let typeref = self.typeref.as_deref()?;
+        return match typeref.split_once(':') {
+            Some((_, v)) => Some(v),
+            None => Some(typeref),
+        };
+    }
+
+    /// Whether entry implements a method declared elsewhere, e.g. a method of
+    /// Rust `impl` block.
+    pub fn is_implementation(&self) -> bool {
+        return self.scope_kind.as_deref() == Some("implementation");
+    }
+
+    /// Whether entry only declares the symbol, like a C prototype or an
+    /// `extern` variable.
+    pub fn is_declaration(&self) -> bool {
+        return matches!(
+            self.kind.as_deref(),
+            Some("p" | "prototype" | "x" | "externvar")
+        );
+    }
+}
+
+/// How to compare symbol with tag names.
+#[derive(Debug, clap::ValueEnum, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Tag name equals to symbol.
+    #[default]
+    Exact,
+
+    /// Tag name starts with symbol.
+    Prefix,
+
+    /// Tag name equals to symbol, ignoring case.
+    Ci,
+}
+
+impl MatchMode {
+    /// Whether tag name matches symbol.
+    ///
+    /// # Arguments
+    ///
+    /// + `tag_name`: Name of tag.
+    /// + `symbol`: The symbol to match.
+    pub fn is_match(&self, tag_name: &str, symbol: &str) -> bool {
+        match self {
+            MatchMode::Exact => return tag_name == symbol,
+            MatchMode::Prefix => return tag_name.starts_with(symbol),
+            MatchMode::Ci => return tag_name.eq_ignore_ascii_case(symbol),
+        }
+    }
+}
+
+/// How to resolve address of tag entry into position.
+#[derive(Debug, clap::ValueEnum, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddressResolution {
+    /// Use the recorded line number without reading file, may be wrong after
+    /// edits. Patterns without line number are still searched.
+    Trust,
+
+    /// Read file to confirm the address and find the symbol in line.
+    #[default]
+    Verify,
+}
+
+/// How tags file is sorted, as told by `!_TAG_FILE_SORTED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    /// Not sorted, or unknown.
+    #[default]
+    Unsorted,
+
+    /// Sorted by name in byte order.
+    Sorted,
+
+    /// Sorted by name ignoring case.
+    Foldcase,
+}
+
+/// Information from pseudo-tag lines (`!_TAG_`) of tags file.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagsMeta {
+    /// Program that generated the file, from `!_TAG_PROGRAM_NAME`.
+    pub program_name: Option<String>,
+
+    /// Version of program, from `!_TAG_PROGRAM_VERSION`.
+    pub program_version: Option<String>,
+
+    /// Format version of file, from `!_TAG_FILE_FORMAT`.
+    pub file_format: Option<u32>,
+
+    /// How the file is sorted, from `!_TAG_FILE_SORTED`.
+    pub sorted: SortOrder,
+}
+
+impl TagsMeta {
+    /// Record one pseudo-tag line, unknown pseudo-tags are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// + `line`: Line that starts with `!_TAG_`.
+    pub fn parse_line(&mut self, line: &str) {
+        let mut iter = line.split('\t');
+        let (name, value) = match (iter.next(), iter.next()) {
+            (Some(name), Some(value)) => (name, value.trim()),
+            _ => return,
+        };
+
+        match name {
+            "!_TAG_PROGRAM_NAME" => self.program_name = Some(value.to_string()),
+            "!_TAG_PROGRAM_VERSION" => self.program_version = Some(value.to_string()),
+            "!_TAG_FILE_FORMAT" => self.file_format = value.parse().ok(),
+            "!_TAG_FILE_SORTED" => {
+                self.sorted = match value {
+                    "1" => SortOrder::Sorted,
+                    "2" => SortOrder::Foldcase,
+                    _ => SortOrder::Unsorted,
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A parsed ctags file.
+#[derive(Debug, Clone, Default)]
+pub struct TagsFile {
+    /// Path of tags file.
+    pub path: PathBuf,
+
+    /// Tag entries in the order they appear in the file.
+    pub entries: Vec<TagEntry>,
+
+    /// Line numbers of malformed lines, starting from 0.
+    pub malformed: Vec<u32>,
+
+    /// Information from pseudo-tag lines.
+    pub meta: TagsMeta,
+
+    /// Whether `entries` are sorted by name, so binary search is safe.
+    sorted: bool,
+
+    /// Sorted files with more entries than this are searched by binary search
+    /// instead of hash index, 0 for always using hash index.
+    hash_index_limit: usize,
+
+    /// Whether binary search is used instead of `index`.
+    binary_search: bool,
+
+    /// Index of `entries` keyed by symbol name.
+    index: HashMap<String, Vec<usize>>,
+
+    /// Readable names of anonymous types, taken from typedefs of them.
+    anonymous: HashMap<String, String>,
+}
+
+impl TagsFile {
+    /// Create tags file and build index for entries.
+    ///
+    /// # Arguments
+    ///
+    /// + `path`: Path of tags file.
+    /// + `entries`: Tag entries.
+    pub fn new(path: PathBuf, entries: Vec<TagEntry>) -> TagsFile {
+        let mut ret = TagsFile {
+            path,
+            entries,
+            malformed: Vec::new(),
+            meta: TagsMeta::default(),
+            sorted: false,
+            hash_index_limit: 0,
+            binary_search: false,
+            index: HashMap::new(),
+            anonymous: HashMap::new(),
+        };
+        ret.rebuild_index();
+        return ret;
+    }
+
+    /// Build index for entries.
+    ///
+    /// Entries of the same name, file and line are kept only once, entries
+    /// without known line are compared by address. Must be called after
+    /// `entries` changed.
+    pub fn rebuild_index(&mut self) {
+        let start = std::time::Instant::now();
+
+        let mut seen = std::collections::HashSet::new();
+        let before = self.entries.len();
+        self.entries.retain(|v| {
+            // Same definition may be written as line number or as pattern.
+            let line = match &v.address {
+                TagAddress::Line(n) => Some(*n),
+                TagAddress::Pattern(_) => v.line,
+            };
+            let address = match line {
+                Some(_) => None,
+                None => Some(v.address.clone()),
+            };
+            let key = (
+                v.name.clone(),
+                normalize_path(Path::new(&v.file)),
+                line,
+                address,
+            );
+            seen.insert(key)
+        });
+        if self.entries.len() != before {
+            tracing::debug!(
+                "Drop {} duplicate entries of {}",
+                before - self.entries.len(),
+                self.path.display()
+            );
+        }
+
+        // Entries replaced after save are appended, trust the header only if
+        // the order still holds.
+        self.sorted = self.meta.sorted == SortOrder::Sorted
+            && self.entries.windows(2).all(|v| v[0].name <= v[1].name);
+        if self.meta.sorted == SortOrder::Sorted && !self.sorted {
+            tracing::debug!("{} is not sorted any more", self.path.display());
+        }
+
+        // Huge sorted files skip hash index to save memory.
+        self.binary_search =
+            self.sorted && self.hash_index_limit > 0 && self.entries.len() > self.hash_index_limit;
+
+        self.index.clear();
+        if !self.binary_search {
+            for (idx, entry) in self.entries.iter().enumerate() {
+                self.index.entry(entry.name.clone()).or_default().push(idx);
+            }
+        }
+
+        // `typedef struct { ... } Foo;` has `typeref:struct:__anon...`.
+        self.anonymous.clear();
+        for entry in &self.entries {
+            if entry.kind.as_deref().map(kind_name) != Some("typedef") {
+                continue;
+            }
+            let target = match entry.typeref.as_deref().and_then(|v| v.rsplit(':').next()) {
+                Some(v) if is_anonymous(v) && !is_anonymous(&entry.name) => v,
+                _ => continue,
+            };
+            self.anonymous
+                .entry(target.to_string())
+                .or_insert_with(|| entry.name.clone());
+        }
+
+        match self.binary_search {
+            true => tracing::debug!(
+                "Use binary search for {} entries of {}",
+                self.entries.len(),
+                self.path.display()
+            ),
+            false => tracing::debug!(
+                "Index {} names of {} in {:?}",
+                self.index.len(),
+                self.path.display(),
+                start.elapsed()
+            ),
+        }
+    }
+
+    /// Find all entries that have exactly the same name.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    pub fn lookup<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a TagEntry> + 'a {
+        let (idx_list, range) = match self.binary_search {
+            true => {
+                let start = self.entries.partition_point(|v| v.name.as_str() < name);
+                let end = start + self.entries[start..].partition_point(|v| v.name == name);
+                (&[][..], start..end)
+            }
+            false => match self.index.get(name) {
+                Some(v) => (v.as_slice(), 0..0),
+                None => (&[][..], 0..0),
+            },
+        };
+        idx_list
+            .iter()
+            .map(move |idx| &self.entries[*idx])
+            .chain(self.entries[range].iter())
+    }
+
+    /// Get readable name of symbol.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    ///
+    /// # Returns
+    ///
+    /// The name itself, or the typedef name of anonymous type. `None` if the
+    /// type is anonymous and no typedef names it.
+    pub fn readable_name<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        if !is_anonymous(name) {
+            return Some(name);
+        }
+
+        return self.anonymous.get(name).map(|v| v.as_str());
+    }
+
+    /// Replace anonymous components of scope by readable names, e.g.
+    /// `Outer::__anon1a2b` becomes `Outer::Inner` or `Outer::(anonymous)`.
+    ///
+    /// # Arguments
+    ///
+    /// + `scope`: Scope of tag entry.
+    pub fn readable_scope(&self, scope: &str) -> String {
+        let sep = if scope.contains("::") { "::" } else { "." };
+        let components: Vec<&str> = scope
+            .split(sep)
+            .map(|v| self.readable_name(v).unwrap_or("(anonymous)"))
+            .collect();
+
+        return components.join(sep);
+    }
+
+    /// Replace all entries of file with new entries.
+    ///
+    /// # Arguments
+    ///
+    /// + `path`: Path of source file.
+    /// + `entries`: New entries of the file.
+    /// + `ignore_case`: Compare file paths case-insensitively.
+    pub fn replace_file_entries(&mut self, path: &Path, entries: Vec<TagEntry>, ignore_case: bool) {
+        let path = normalize_path(path);
+        let dir = self.path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        self.entries
+            .retain(|v| !same_path(&normalize_path(&dir.join(&v.file)), &path, ignore_case));
+        self.entries.extend(entries);
+        self.rebuild_index();
+    }
+
+    /// Find all entries whose name matches symbol.
+    ///
+    /// `MatchMode::Exact` uses the index, or binary search for huge sorted
+    /// files. `MatchMode::Prefix` uses binary search if entries are sorted.
+    /// Other modes scan all names.
+    ///
+    /// # Arguments
+    ///
+    /// + `name`: Symbol name.
+    /// + `mode`: How to compare names.
+    pub fn lookup_with<'a>(&'a self, name: &str, mode: MatchMode) -> Vec<&'a TagEntry> {
+        if mode == MatchMode::Exact {
+            return self.lookup(name).collect();
+        }
+
+        if mode == MatchMode::Prefix && self.sorted {
+            let start = self.entries.partition_point(|v| v.name.as_str() < name);
+            return self.entries[start..]
+                .iter()
+                .take_while(|v| v.name.starts_with(name))
+                .collect();
+        }
+
+        if self.binary_search {
+            return self
+                .entries
+                .iter()
+                .filter(|v| mode.is_match(&v.name, name))
+                .collect();
+        }
+
+        let mut ret = Vec::new();
+        for (key, idx_list) in &self.index {
+            if mode.is_match(key, name) {
+                ret.extend(idx_list.iter().map(|idx| &self.entries[*idx]));
+            }
+        }
+        return ret;
+    }
+
+    /// Get path of the file that entry points to.
+    ///
+    /// Relative paths are relative to the directory of tags file. The
+    /// returned path is absolute if the tags file path is.
+    ///
+    /// # Arguments
+    ///
+    /// + `entry`: Tag entry.
+    pub fn entry_path(&self, entry: &TagEntry) -> PathBuf {
+        let dir = self.path.parent().unwrap_or(Path::new("/"));
+        return normalize_path(&dir.join(&entry.file));
+    }
+}
+
+/// Loaded tags files shared between requests.
+///
+/// Readers take a snapshot that stays valid while writers publish a new one,
+/// so lookups never wait for reloads. Writers are expected to be serialized
+/// by the Runtime lock. Sessions of clients connected to the same server share
+/// one instance.
+#[derive(Debug, Clone, Default)]
+pub struct SharedTags {
+    inner: Arc<std::sync::RwLock<Arc<Vec<Arc<TagsFile>>>>>,
+}
+
+impl SharedTags {
+    /// Get snapshot of current tags files.
+    pub fn load(&self) -> Arc<Vec<Arc<TagsFile>>> {
+        return self.inner.read().unwrap().clone();
+    }
+
+    /// Replace all tags files.
+    ///
+    /// # Arguments
+    ///
+    /// + `tags`: New tags files.
+    pub fn store(&self, tags: Vec<TagsFile>) {
+        let tags = tags.into_iter().map(Arc::new).collect();
+        *self.inner.write().unwrap() = Arc::new(tags);
+    }
+
+    /// Replace tags files of the same paths and remove `stale` ones.
+    ///
+    /// Other tags files are kept, e.g. tags loaded by other sessions.
+    ///
+    /// # Arguments
+    ///
+    /// + `stale`: Paths of tags files no longer wanted.
+    /// + `tags`: Tags files loaded again.
+    pub fn replace(&self, stale: &[PathBuf], tags: Vec<TagsFile>) {
+        self.update(|list| {
+            list.retain(|v| !stale.contains(&v.path));
+            for tags_file in tags {
+                let tags_file = Arc::new(tags_file);
+                match list.iter_mut().find(|v| v.path == tags_file.path) {
+                    Some(v) => *v = tags_file,
+                    None => list.push(tags_file),
+                }
+            }
+        });
+    }
+
+    /// Change the list of tags files and publish it.
+    ///
+    /// The list is copied if any snapshot is alive. Tags files in it are
+    /// shared with snapshots, use `Arc::make_mut` to modify one.
+    ///
+    /// # Arguments
+    ///
+    /// + `f`: Function to change the list.
+    pub fn update<R>(&self, f: impl FnOnce(&mut Vec<Arc<TagsFile>>) -> R) -> R {
+        let mut guard = self.inner.write().unwrap();
+        return f(Arc::make_mut(&mut guard));
+    }
+}
+
+/// Convert file path to `file://` uri.
+///
+/// Backslashes, drive letters and UNC paths are handled on Windows, e.g.
+/// `C:\src\main.c` becomes `file:///C:/src/main.c` and `\\server\share\a.c`
+/// becomes `file://server/share/a.c`. Returns `None` if path is relative.
+///
+/// # Arguments
+///
+/// + `path`: Absolute file path.
+pub fn path_to_uri(path: &Path) -> Option<Url> {
+    // ctags on Windows may mix `/` and `\`.
+    #[cfg(windows)]
+    let path = &PathBuf::from(path.to_string_lossy().replace('/', "\\"));
+
+    return Url::from_file_path(normalize_path(path)).ok();
+}
+
+/// Remove `.` and `..` components from path without touching file system.
+///
+/// # Arguments
+///
+/// + `path`: The path to normalize.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => (),
+            std::path::Component::ParentDir => {
+                if !ret.pop() {
+                    ret.push(component);
+                }
+            }
+            _ => ret.push(component),
+        }
+    }
+
+    return ret;
+}
+
+/// Check if name is made up by ctags for anonymous struct, union or enum,
+/// e.g. `__anon7c6b1e2a0108`.
+///
+/// # Arguments
+///
+/// + `name`: Symbol name.
+pub fn is_anonymous(name: &str) -> bool {
+    return name
+        .strip_prefix("__anon")
+        .is_some_and(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+/// Check if two paths are the same, without touching file system.
+///
+/// # Arguments
+///
+/// + `a`: One path.
+/// + `b`: The other path.
+/// + `ignore_case`: Compare components case-insensitively.
+pub fn same_path(a: &Path, b: &Path, ignore_case: bool) -> bool {
+    if !ignore_case {
+        return a == b;
+    }
+
+    return a.components().count() == b.components().count() && path_starts_with(a, b, true);
+}
+
+/// Check if path is inside base, or is base itself.
+///
+/// # Arguments
+///
+/// + `path`: The path to check.
+/// + `base`: The leading part of path.
+/// + `ignore_case`: Compare components case-insensitively.
+pub fn path_starts_with(path: &Path, base: &Path, ignore_case: bool) -> bool {
+    if !ignore_case {
+        return path.starts_with(base);
+    }
+
+    let mut components = path.components();
+    for base_component in base.components() {
+        let component = match components.next() {
+            Some(v) => v,
+            None => return false,
+        };
+        let a = component.as_os_str().to_string_lossy();
+        let b = base_component.as_os_str().to_string_lossy();
+        if a.to_lowercase() != b.to_lowercase() {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+/// Parse ctags file.
+///
+/// The standard Exuberant/Universal ctags line format looks like:
+///
+/// ```txt
+/// name<TAB>file<TAB>address;"<TAB>extension fields
+/// ```
+///
+/// Pseudo-tag lines (`!_TAG_`) are recorded in `meta`. Lines that cannot be
+/// parsed are skipped, and their line numbers are recorded in `malformed`.
+///
+/// Emacs `TAGS` files are detected by file name or the leading form feed, and
+/// parsed by [`etags::parse_content`] instead.
+///
+/// # Arguments
+///
+/// + `path`: Path to tags file.
+/// + `hash_index_limit`: Sorted files with more entries than this are
+///   searched by binary search instead of hash index, 0 for no limit.
+/// + `encoding`: Encoding of source files, patterns are copied from them.
+pub fn parse(
+    path: &Path,
+    hash_index_limit: usize,
+    encoding: &'static encoding_rs::Encoding,
+) -> std::io::Result<TagsFile> {
+    let content = read_tags_content(path)?;
+    let is_etags = etags::is_etags(path, &content);
+    let (content, _, _) = encoding.decode(&content);
+
+    if is_etags {
+        let (entries, malformed) = etags::parse_content(&content);
+        let mut ret = TagsFile::new(path.to_path_buf(), entries);
+        ret.malformed = malformed;
+        return Ok(ret);
+    }
+
+    let mut entries = Vec::new();
+    let mut malformed = Vec::new();
+    let mut meta = TagsMeta::default();
+    for (line_no, line) in content.lines().enumerate() {
+        if line.starts_with("!_TAG_") {
+            meta.parse_line(line);
+            continue;
+        }
+
+        match parse_line(line) {
+            Some(v) => entries.push(v),
+            None => {
+                tracing::debug!("Skip malformed tag line: {}", line);
+                malformed.push(line_no as u32);
+            }
+        }
+    }
+
+    let mut ret = TagsFile {
+        path: path.to_path_buf(),
+        entries,
+        malformed,
+        meta,
+        hash_index_limit,
+        ..Default::default()
+    };
+    ret.rebuild_index();
+    tracing::debug!("Metadata of {}: {:?}", path.display(), ret.meta);
+
+    // Binary search would miss entries of a file that lies about its order.
+    if ret.meta.sorted == SortOrder::Sorted && !ret.sorted {
+        tracing::warn!(
+            "{} claims to be sorted but is not, use hash index instead of binary search",
+            path.display()
+        );
+    }
+    return Ok(ret);
+}
+
+/// Extension fields that name the enclosing scope of tag.
+const SCOPE_FIELDS: &[&str] = &[
+    "class",
+    "struct",
+    "union",
+    "enum",
+    "function",
+    "namespace",
+    "interface",
+    "module",
+    "implementation",
+];
+
+/// Read content of tags file, decompress it if gzip compressed.
+///
+/// # Arguments
+///
+/// + `path`: Path to tags file.
+fn read_tags_content(path: &Path) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let content = std::fs::read(path)?;
+
+    let is_gzip = path.extension().is_some_and(|v| v == "gz") || content.starts_with(&[0x1f, 0x8b]);
+    if !is_gzip {
+        return Ok(content);
+    }
+
+    let mut ret = Vec::new();
+    flate2::read::GzDecoder::new(content.as_slice()).read_to_end(&mut ret)?;
+    return Ok(ret);
+}
+
+/// Parse one line of ctags file.
+///
+/// # Arguments
+///
+/// + `line`: The line to parse.
+pub fn parse_line(line: &str) -> Option<TagEntry> {
+    let mut iter = line.splitn(3, '\t');
+    let name = iter.next()?;
+    let file = iter.next()?;
+    let rest = iter.next()?;
+
+    if name.is_empty() || file.is_empty() {
+        return None;
+    }
+
+    let (address, mut line, rest) = parse_address(rest)?;
+
+    // Extension fields are only available after `;"`.
+    let mut kind = None;
+    let mut language = None;
+    let mut scope = None;
+    let mut scope_kind = None;
+    let mut inherits = None;
+    let mut typeref = None;
+    let mut end = None;
+    if let Some(fields) = rest.strip_prefix(";\"") {
+        for field in fields.split('\t').filter(|v| !v.is_empty()) {
+            match field.split_once(':') {
+                Some(("kind", v)) => kind = Some(v.to_string()),
+                Some(("language", v)) => language = Some(v.to_string()),
+                Some(("typeref", v)) => typeref = Some(v.to_string()),
+                Some(("inherits", v)) => inherits = Some(v.to_string()),
+                Some(("line", v)) if line.is_none() => line = v.parse().ok(),
+                Some(("end", v)) => end = v.parse().ok(),
+                Some((k, v)) if SCOPE_FIELDS.contains(&k) => {
+                    scope = Some(v.to_string());
+                    scope_kind = Some(k.to_string());
+                }
+                Some(_) => (),
+                None => kind = Some(field.to_string()),
+            }
+        }
+    }
+
+    return Some(TagEntry {
+        name: name.to_string(),
+        file: file.to_string(),
+        address,
+        line,
+        end,
+        kind,
+        language,
+        scope,
+        scope_kind,
+        inherits,
+        typeref,
+    });
+}
+
+/// Parse address field, return the address, line number hint of pattern and
+/// the rest of line.
+///
+/// A line number followed by pattern, like `123;/^int main/`, becomes the
+/// pattern with line number hint.
+///
+/// # Arguments
+///
+/// + `data`: The string start with address field.
+fn parse_address(data: &str) -> Option<(TagAddress, Option<u32>, &str)> {
+    let delimiter = data.chars().next()?;
+
+    // Line number.
+    if delimiter.is_ascii_digit() {
+        let end = data
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(data.len());
+        let line_no: u32 = data[..end].parse().ok()?;
+
+        let rest = &data[end..];
+        if let Some(v) = rest.strip_prefix(';') {
+            if v.starts_with('/') || v.starts_with('?') {
+                let (address, _, rest) = parse_address(v)?;
+                return Some((address, Some(line_no), rest));
+            }
+        }
+        return Some((TagAddress::Line(line_no), None, rest));
+    }
+
+    // Search pattern, forward or backward.
+    if delimiter != '/' && delimiter != '?' {
+        return None;
+    }
+
+    let mut escaped = false;
+    for (idx, c) in data.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            let pattern = data[1..idx].to_string();
+            return Some((TagAddress::Pattern(pattern), None, &data[idx + 1..]));
+        }
+    }
+
+    return None;
+}
+
+/// A ctags search pattern with escaping and anchors resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPattern {
+    /// Pattern must match from begin of line.
+    pub anchor_start: bool,
+
+    /// Pattern must match until end of line.
+    pub anchor_end: bool,
+
+    /// Literal text to match.
+    pub text: String,
+}
+
+impl SearchPattern {
+    /// Parse ctags search pattern.
+    ///
+    /// Ctags patterns are not real regular expressions: only `\/`, `\\`
+    /// and the anchoring `^` / `$` are special.
+    ///
+    /// # Arguments
+    ///
+    /// + `pattern`: Pattern with delimiters stripped.
+    pub fn new(pattern: &str) -> SearchPattern {
+        let (anchor_start, pattern) = match pattern.strip_prefix('^') {
+            Some(v) => (true, v),
+            None => (false, pattern),
+        };
+
+        let mut text = String::new();
+        let mut anchor_end = false;
+        let mut iter = pattern.chars().peekable();
+        while let Some(c) = iter.next() {
+            match c {
+                '\\' => match iter.next() {
+                    Some(v) => text.push(v),
+                    None => text.push(c),
+                },
+                '$' if iter.peek().is_none() => anchor_end = true,
+                _ => text.push(c),
+            }
+        }
+
+        return SearchPattern {
+            anchor_start,
+            anchor_end,
+            text,
+        };
+    }
+
+    /// Check if line matches the pattern.
+    ///
+    /// # Arguments
+    ///
+    /// + `line`: The line to check.
+    pub fn is_match(&self, line: &str) -> bool {
+        match (self.anchor_start, self.anchor_end) {
+            (true, true) => line == self.text,
+            (true, false) => line.starts_with(&self.text),
+            (false, true) => line.ends_with(&self.text),
+            (false, false) => line.contains(&self.text),
+        }
+    }
+
+    /// Find the first line that matches the pattern.
+    ///
+    /// # Arguments
+    ///
+    /// + `content`: File content.
+    ///
+    /// # Returns
+    ///
+    /// Line number, starting from 0.
+    pub fn find_line(&self, content: &str) -> Option<u32> {
+        return content
+            .lines()
+            .position(|line| self.is_match(line))
+            .map(|v| v as u32);
+    }
+
+    /// Find the first matching line, trying lines around hint first.
+    ///
+    /// Lines within `NEARBY_LINES` of hint are checked from the nearest, so
+    /// that the right one is found after small edits even if pattern is not
+    /// unique. The whole file is searched if none matches.
+    ///
+    /// # Arguments
+    ///
+    /// + `content`: File content.
+    /// + `hint`: Line number where pattern is expected, starting from 1.
+    ///
+    /// # Returns
+    ///
+    /// Line number, starting from 0.
+    pub fn find_line_near(&self, content: &str, hint: Option<u32>) -> Option<u32> {
+        /// How far from hint to look first.
+        const NEARBY_LINES: usize = 10;
+
+        let hint = match hint {
+            Some(v) => v.saturating_sub(1) as usize,
+            None => return self.find_line(content),
+        };
+
+        // Lines are more often added above than removed, so look down first.
+        let lines: Vec<&str> = content.lines().collect();
+        for distance in 0..=NEARBY_LINES {
+            let candidates = [hint.checked_add(distance), hint.checked_sub(distance)];
+            for idx in candidates.into_iter().flatten() {
+                if lines.get(idx).is_some_and(|v| self.is_match(v)) {
+                    return Some(idx as u32);
+                }
+            }
+        }
+
+        return self.find_line(content);
+    }
+}
+
+/// LSP language id, ctags language name and file extensions of languages.
+///
+/// A language id may appear more than once if ctags names its dialects
+/// differently, the first one is used to map the language id.
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    ("c", "C", &["c", "h"]),
+    (
+        "cpp",
+        "C++",
+        &[
+            "cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "h++", "ipp", "inl",
+        ],
+    ),
+    ("csharp", "C#", &["cs"]),
+    ("objective-c", "ObjectiveC", &["m"]),
+    ("objective-cpp", "ObjectiveC", &["mm"]),
+    ("rust", "Rust", &["rs"]),
+    ("go", "Go", &["go"]),
+    ("java", "Java", &["java"]),
+    ("kotlin", "Kotlin", &["kt", "kts"]),
+    ("scala", "Scala", &["scala", "sc"]),
+    ("swift", "Swift", &["swift"]),
+    ("lisp", "Lisp", &["lisp", "lsp", "cl"]),
+    ("lisp", "EmacsLisp", &["el"]),
+    ("scheme", "Scheme", &["scm", "ss"]),
+    ("clojure", "Clojure", &["clj", "cljs", "cljc"]),
+    ("php", "PHP", &["php"]),
+    ("python", "Python", &["py", "pyi"]),
+    ("ruby", "Ruby", &["rb"]),
+    ("perl", "Perl", &["pl", "pm"]),
+    ("lua", "Lua", &["lua"]),
+    ("javascript", "JavaScript", &["js", "jsx", "mjs", "cjs"]),
+    ("typescript", "TypeScript", &["ts", "tsx", "mts", "cts"]),
+    ("shellscript", "Sh", &["sh", "bash", "zsh"]),
+    ("haskell", "Haskell", &["hs"]),
+    ("erlang", "Erlang", &["erl", "hrl"]),
+    ("elixir", "Elixir", &["ex", "exs"]),
+    ("ocaml", "OCaml", &["ml", "mli"]),
+    ("fortran", "Fortran", &["f", "f90", "f95", "for"]),
+    ("pascal", "Pascal", &["pas"]),
+    ("vhdl", "VHDL", &["vhd", "vhdl"]),
+    ("verilog", "Verilog", &["v", "vh"]),
+    ("systemverilog", "SystemVerilog", &["sv", "svh"]),
+    ("asm", "Asm", &["s", "asm"]),
+    ("makefile", "Make", &["mk", "mak"]),
+    ("cmake", "CMake", &["cmake"]),
+    ("vim", "Vim", &["vim"]),
+    ("sql", "SQL", &["sql"]),
+];
+
+/// Find language of file from its extension.
+///
+/// Extensions are compared case-sensitively first, then case-insensitively,
+/// so that `.C` is C++ and `.PY` is Python.
+///
+/// # Arguments
+///
+/// + `path`: File path.
+fn find_language(
+    path: &Path,
+) -> Option<&'static (&'static str, &'static str, &'static [&'static str])> {
+    let ext = path.extension()?.to_str()?;
+
+    // `.C` and `.H` are C++ by convention.
+    let ext = match ext {
+        "C" | "H" => "cpp",
+        _ => ext,
+    };
+
+    let find = |ext: &str| LANGUAGES.iter().find(|(_, _, list)| list.contains(&ext));
+    return find(ext).or_else(|| find(&ext.to_lowercase()));
+}
+
+/// Guess ctags language name from file extension.
+///
+/// # Arguments
+///
+/// + `path`: File path.
+pub fn language_from_path(path: &Path) -> Option<&'static str> {
+    return find_language(path).map(|(_, v, _)| *v);
+}
+
+/// Guess LSP language id from file extension.
+///
+/// # Arguments
+///
+/// + `path`: File path.
+pub fn language_id_from_path(path: &Path) -> Option<&'static str> {
+    return find_language(path).map(|(v, _, _)| *v);
+}
+
+/// Get ctags language name of LSP language id.
+///
+/// # Arguments
+///
+/// + `id`: LSP language id, like `cpp`.
+pub fn language_from_id(id: &str) -> Option<&'static str> {
+    return LANGUAGES
+        .iter()
+        .find(|(v, _, _)| *v == id)
+        .map(|(_, v, _)| *v);
+}
+
+/// Check if two ctags language names are the same language.
+///
+/// C headers are shared by C and C++, so they are treated as the same.
+pub fn is_same_language(a: &str, b: &str) -> bool {
+    fn normalize(v: &str) -> &str {
+        match v {
+            "C++" => "C",
+            _ => v,
+        }
+    }
+
+    return normalize(a).eq_ignore_ascii_case(normalize(b));
+}
+
+/// Find tags file in directory.
+///
+/// Names are tried in order, and plain `name` is preferred over `name.gz`.
+/// Emacs `TAGS` is tried last.
+///
+/// # Arguments
+///
+/// + `dir`: The directory to search.
+/// + `names`: File names of tags, usually `tags`.
+pub fn discover(dir: &Path, names: &[String]) -> Option<PathBuf> {
+    return names
+        .iter()
+        .flat_map(|v| [v.clone(), format!("{}.gz", v)])
+        .chain(std::iter::once(etags::ETAGS_FILENAME.to_string()))
+        .map(|v| dir.join(v))
+        .find(|v| v.is_file());
+}
+
+/// Find tags files in directory and its ancestors.
+///
+/// Stops at the first directory that contains `.git`, or at root of file
+/// system. Tags files closer to `dir` come first.
+///
+/// # Arguments
+///
+/// + `dir`: The directory to start search.
+/// + `names`: File names of tags, see [`discover`].
+pub fn discover_upward(dir: &Path, names: &[String]) -> Vec<PathBuf> {
+    let mut ret = Vec::new();
+
+    for ele in dir.ancestors() {
+        if let Some(v) = discover(ele, names) {
+            ret.push(v);
+        }
+        if ele.join(".git").exists() {
+            break;
+        }
+    }
+
+    return ret;
+}
+
+/// Get human readable name of ctags kind.
+///
+/// # Arguments
+///
+/// + `kind`: Kind of tag, either a single letter or full name.
+pub fn kind_name(kind: &str) -> &str {
+    match kind {
+        "f" => "function",
+        "p" => "prototype",
+        "m" => "member",
+        "v" => "variable",
+        "l" => "local",
+        "x" => "externvar",
+        "c" => "class",
+        "s" => "struct",
+        "u" => "union",
+        "g" => "enum",
+        "e" => "enumerator",
+        "t" => "typedef",
+        "i" => "interface",
+        "n" => "namespace",
+        "d" => "macro",
+        "M" => "module",
+        _ => kind,
+    }
+}
+
+/// Map ctags kind to LSP symbol kind.
+///
+/// # Arguments
+///
+/// + `kind`: Kind of tag, either a single letter or full name.
+pub fn symbol_kind(kind: Option<&str>) -> SymbolKind {
+    let kind = match kind {
+        Some(v) => v,
+        None => return SymbolKind::NULL,
+    };
+
+    match kind {
+        "f" | "function" | "p" | "prototype" => SymbolKind::FUNCTION,
+        "m" | "member" => SymbolKind::FIELD,
+        "method" => SymbolKind::METHOD,
+        "v" | "variable" | "l" | "local" | "x" | "externvar" => SymbolKind::VARIABLE,
+        "c" | "class" => SymbolKind::CLASS,
+        "s" | "struct" | "u" | "union" => SymbolKind::STRUCT,
+        "g" | "enum" => SymbolKind::ENUM,
+        "e" | "enumerator" => SymbolKind::ENUM_MEMBER,
+        "t" | "typedef" => SymbolKind::TYPE_PARAMETER,
+        "i" | "interface" => SymbolKind::INTERFACE,
+        "n" | "namespace" => SymbolKind::NAMESPACE,
+        "d" | "macro" => SymbolKind::CONSTANT,
+        "M" | "module" => SymbolKind::MODULE,
+        _ => SymbolKind::NULL,
+    }
+}
+
+/// Map ctags kind to LSP completion item kind.
+///
+/// # Arguments
+///
+/// + `kind`: Kind of tag, either a single letter or full name.
+pub fn completion_kind(kind: Option<&str>) -> Option<CompletionItemKind> {
+    let kind = kind?;
+
+    match kind {
+        "f" | "function" | "p" | "prototype" => Some(CompletionItemKind::FUNCTION),
+        "m" | "member" => Some(CompletionItemKind::FIELD),
+        "method" => Some(CompletionItemKind::METHOD),
+        "v" | "variable" | "l" | "local" | "x" | "externvar" => Some(CompletionItemKind::VARIABLE),
+        "c" | "class" => Some(CompletionItemKind::CLASS),
+        "s" | "struct" | "u" | "union" => Some(CompletionItemKind::STRUCT),
+        "g" | "enum" => Some(CompletionItemKind::ENUM),
+        "e" | "enumerator" => Some(CompletionItemKind::ENUM_MEMBER),
+        "t" | "typedef" => Some(CompletionItemKind::TYPE_PARAMETER),
+        "i" | "interface" => Some(CompletionItemKind::INTERFACE),
+        "n" | "namespace" | "M" | "module" => Some(CompletionItemKind::MODULE),
+        "d" | "macro" => Some(CompletionItemKind::CONSTANT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_of_extension() {
+        let list = [
+            ("a.c", "C", "c"),
+            ("a.h", "C", "c"),
+            ("a.cpp", "C++", "cpp"),
+            ("a.hpp", "C++", "cpp"),
+            ("a.C", "C++", "cpp"),
+            ("a.H", "C++", "cpp"),
+            ("a.rs", "Rust", "rust"),
+            ("a.py", "Python", "python"),
+            ("a.PY", "Python", "python"),
+            ("a.go", "Go", "go"),
+            ("a.java", "Java", "java"),
+            ("a.kts", "Kotlin", "kotlin"),
+            ("a.ts", "TypeScript", "typescript"),
+            ("a.mjs", "JavaScript", "javascript"),
+            ("a.sh", "Sh", "shellscript"),
+            ("a.el", "EmacsLisp", "lisp"),
+            ("a.S", "Asm", "asm"),
+        ];
+        for (path, lang, id) in list {
+            assert_eq!(language_from_path(Path::new(path)), Some(lang), "{}", path);
+            assert_eq!(language_id_from_path(Path::new(path)), Some(id), "{}", path);
+        }
+
+        assert_eq!(language_from_path(Path::new("Makefile")), None);
+        assert_eq!(language_from_path(Path::new("a.unknown")), None);
+    }
+
+    #[test]
+    fn drop_duplicate_entries() {
+        let lines = [
+            "add\tmain.c\t1",
+            "add\t./main.c\t/^int add(int a, int b)$/;\"\tf\tline:1",
+            "add\tmain.c\t9",
+            "sub\tmain.c\t/^int sub(int a, int b)$/;\"\tf",
+            "sub\tmain.c\t/^int sub(int a, int b)$/;\"\tf",
+            "sub\tmain.c\t/^int sub(int a)$/;\"\tf",
+        ];
+        let entries = lines.iter().filter_map(|v| parse_line(v)).collect();
+        let tags = TagsFile::new(PathBuf::from("tags"), entries);
+
+        let list: Vec<_> = tags.entries.iter().map(|v| &v.address).collect();
+        assert_eq!(
+            list,
+            [
+                &TagAddress::Line(1),
+                &TagAddress::Line(9),
+                &TagAddress::Pattern(String::from("^int sub(int a, int b)$")),
+                &TagAddress::Pattern(String::from("^int sub(int a)$")),
+            ]
+        );
+    }
+
+    #[test]
+    fn language_of_id() {
+        assert_eq!(language_from_id("cpp"), Some("C++"));
+        assert_eq!(language_from_id("lisp"), Some("Lisp"));
+        assert_eq!(language_from_id("shellscript"), Some("Sh"));
+        assert_eq!(language_from_id("plaintext"), None);
+    }
+
+    #[test]
+    fn lookup_in_unsorted_file_claiming_sorted() {
+        let dir = std::env::temp_dir();
+        let parse_tags = |name: &str, content: &str| {
+            let path = dir.join(format!("tags-lsp-test-{}-{}", name, std::process::id()));
+            std::fs::write(&path, content).unwrap();
+            let ret = parse(&path, 1, encoding_rs::UTF_8).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            return ret;
+        };
+
+        let tags = parse_tags(
+            "unsorted",
+            "!_TAG_FILE_SORTED\t1\t//\nzeta\ta.c\t1\nalpha\ta.c\t2\nmid\ta.c\t3\n",
+        );
+        assert_eq!(tags.meta.sorted, SortOrder::Sorted);
+        assert!(!tags.sorted);
+        assert!(!tags.binary_search);
+        for name in ["alpha", "mid", "zeta"] {
+            assert_eq!(tags.lookup(name).count(), 1, "{}", name);
+        }
+
+        // Really sorted file uses binary search.
+        let tags = parse_tags(
+            "sorted",
+            "!_TAG_FILE_SORTED\t1\t//\nalpha\ta.c\t2\nmid\ta.c\t3\nzeta\ta.c\t1\n",
+        );
+        assert!(tags.binary_search);
+        for name in ["alpha", "mid", "zeta"] {
+            assert_eq!(tags.lookup(name).count(), 1, "{}", name);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn entry_path_relative_to_tags_file() {
+        let lines = ["a\t../src/a.c\t1", "b\t./b.c\t1", "c\t/abs/c.c\t1"];
+        let entries = lines.iter().filter_map(|v| parse_line(v)).collect();
+        let tags = TagsFile::new(PathBuf::from("/ws/sub/tags"), entries);
+
+        let list: Vec<_> = tags.entries.iter().map(|v| tags.entry_path(v)).collect();
+        assert_eq!(
+            list,
+            [
+                PathBuf::from("/ws/src/a.c"),
+                PathBuf::from("/ws/sub/b.c"),
+                PathBuf::from("/abs/c.c"),
+            ]
+        );
+
+        let uri = path_to_uri(&list[0]).unwrap();
+        assert_eq!(uri.as_str(), "file:///ws/src/a.c");
+        assert_eq!(normalize_path(Path::new("/a/./b/../../c")), Path::new("/c"));
+        assert_eq!(normalize_path(Path::new("../a")), Path::new("../a"));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_path_to_uri() {
+        let uri = |path: &str| path_to_uri(Path::new(path)).map(|v| v.to_string());
+
+        assert_eq!(
+            uri(r"C:\src\main.c").as_deref(),
+            Some("file:///C:/src/main.c")
+        );
+        assert_eq!(
+            uri("C:/src/main.c").as_deref(),
+            Some("file:///C:/src/main.c")
+        );
+        assert_eq!(
+            uri(r"C:\src/sub\..\a b.c").as_deref(),
+            Some("file:///C:/src/a%20b.c")
+        );
+        assert_eq!(
+            uri(r"\\server\share\a.c").as_deref(),
+            Some("file://server/share/a.c")
+        );
+        assert_eq!(uri(r"src\main.c"), None);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_entry_path_relative_to_tags_file() {
+        let lines = ["a\t..\\src\\a.c\t1", "b\tsub/b.c\t1", "c\tD:\\abs\\c.c\t1"];
+        let entries = lines.iter().filter_map(|v| parse_line(v)).collect();
+        let tags = TagsFile::new(PathBuf::from(r"C:\ws\sub\tags"), entries);
+
+        let list: Vec<_> = tags
+            .entries
+            .iter()
+            .filter_map(|v| path_to_uri(&tags.entry_path(v)))
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            list,
+            [
+                "file:///C:/ws/src/a.c",
+                "file:///C:/ws/sub/sub/b.c",
+                "file:///D:/abs/c.c",
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup_with_match_modes() {
+        let lines = [
+            "Foo\ta.c\t1",
+            "bar\ta.c\t2",
+            "foo\ta.c\t3",
+            "foobar\ta.c\t4",
+        ];
+        let entries = lines.iter().filter_map(|v| parse_line(v)).collect();
+        let mut tags = TagsFile::new(PathBuf::from("tags"), entries);
+
+        let names = |tags: &TagsFile, name: &str, mode: MatchMode| {
+            let mut ret: Vec<_> = tags
+                .lookup_with(name, mode)
+                .iter()
+                .map(|v| v.name.clone())
+                .collect();
+            ret.sort();
+            return ret;
+        };
+        let check = |tags: &TagsFile| {
+            assert_eq!(names(tags, "foo", MatchMode::Exact), ["foo"]);
+            assert_eq!(names(tags, "FOO", MatchMode::Ci), ["Foo", "foo"]);
+            assert_eq!(names(tags, "foo", MatchMode::Prefix), ["foo", "foobar"]);
+            assert!(names(tags, "baz", MatchMode::Prefix).is_empty());
+        };
+
+        // Hash index.
+        check(&tags);
+
+        // Sorted entries, searched by binary search.
+        tags.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        tags.meta.sorted = SortOrder::Sorted;
+        tags.hash_index_limit = 1;
+        tags.rebuild_index();
+        assert!(tags.binary_search);
+        check(&tags);
+    }
+
+    #[test]
+    fn load_compressed_tags() {
+        use std::io::Write;
+
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace/tags");
+        let plain = parse(&fixture, 0, encoding_rs::UTF_8).unwrap();
+
+        let path = std::env::temp_dir().join(format!("tags-lsp-test-{}.gz", std::process::id()));
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder
+            .write_all(&std::fs::read(&fixture).unwrap())
+            .unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let compressed = parse(&path, 0, encoding_rs::UTF_8).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!plain.entries.is_empty());
+        assert_eq!(compressed.entries, plain.entries);
+        assert_eq!(compressed.meta.sorted, plain.meta.sorted);
+    }
+
+    #[test]
+    fn discover_tags_upward() {
+        let root =
+            std::env::temp_dir().join(format!("tags-lsp-test-discover-{}", std::process::id()));
+        let deep = root.join("project/src/module/deep");
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::create_dir_all(root.join("project/.git")).unwrap();
+        std::fs::write(root.join("tags"), "").unwrap();
+        std::fs::write(root.join("project/tags"), "").unwrap();
+        std::fs::write(root.join("project/src/module/.tags"), "").unwrap();
+
+        // Stops at the directory that contains `.git`.
+        let names = [String::from("tags")];
+        assert_eq!(discover_upward(&deep, &names), [root.join("project/tags")]);
+
+        // Closer files come first.
+        let names = [String::from(".tags"), String::from("tags")];
+        assert_eq!(
+            discover_upward(&deep, &names),
+            [
+                root.join("project/src/module/.tags"),
+                root.join("project/tags")
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_tags_during_reload() {
+        let tags_file = |name: &str| {
+            let entries = parse_line(&format!("{}\ta.c\t1", name))
+                .into_iter()
+                .collect();
+            return TagsFile::new(PathBuf::from("/ws/tags"), entries);
+        };
+        let rt = crate::Runtime::default();
+        let tags = rt.tags.clone();
+        tags.store(vec![tags_file("old")]);
+
+        // Reload holds Runtime lock while new tags are parsed.
+        let rt = std::sync::Arc::new(tokio::sync::Mutex::new(rt));
+        let reload = rt.lock().await;
+
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let tags = tags.clone();
+                tokio::spawn(async move { tags.load()[0].entries[0].name.clone() })
+            })
+            .collect();
+        let timeout = std::time::Duration::from_secs(5);
+        for v in readers {
+            let name = tokio::time::timeout(timeout, v).await.unwrap().unwrap();
+            assert_eq!(name, "old");
+        }
+
+        // Snapshot taken before reload stays valid after it.
+        let snapshot = tags.load();
+        reload.tags.store(vec![tags_file("new")]);
+        drop(reload);
+        assert_eq!(snapshot[0].entries[0].name, "old");
+        assert_eq!(tags.load()[0].entries[0].name, "new");
+    }
+
+    #[test]
+    fn replace_reloaded_tags_only() {
+        let tags_file = |path: &str, name: &str| {
+            let entries = parse_line(&format!("{}\ta.c\t1", name))
+                .into_iter()
+                .collect();
+            return TagsFile::new(PathBuf::from(path), entries);
+        };
+        let tags = SharedTags::default();
+        tags.store(vec![
+            tags_file("/a/tags", "a"),
+            tags_file("/b/tags", "b"),
+            tags_file("/c/tags", "c"),
+        ]);
+
+        tags.replace(
+            &[PathBuf::from("/c/tags")],
+            vec![tags_file("/a/tags", "new"), tags_file("/d/tags", "d")],
+        );
+        let ret: Vec<_> = tags
+            .load()
+            .iter()
+            .map(|v| {
+                (
+                    v.path.to_string_lossy().to_string(),
+                    v.entries[0].name.clone(),
+                )
+            })
+            .collect();
+        let expect = [("/a/tags", "new"), ("/b/tags", "b"), ("/d/tags", "d")];
+        let expect: Vec<_> = expect
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect();
+        assert_eq!(ret, expect);
+    }
+
+    #[test]
+    fn find_nearest_line_of_pattern() {
+        let mut lines = vec!["x = 0;"; 40];
+        lines[2] = "int x;";
+        lines[30] = "int x;";
+        let content = lines.join("\n");
+        let pattern = SearchPattern::new("^int x;$");
+
+        assert_eq!(pattern.find_line_near(&content, None), Some(2));
+        assert_eq!(pattern.find_line_near(&content, Some(5)), Some(2));
+        assert_eq!(pattern.find_line_near(&content, Some(29)), Some(30));
+        assert_eq!(pattern.find_line_near(&content, Some(33)), Some(30));
+
+        // Both matches are more than `NEARBY_LINES` away.
+        assert_eq!(pattern.find_line_near(&content, Some(16)), Some(2));
+        assert_eq!(pattern.find_line_near(&content, Some(45)), Some(2));
+    }
+
+    #[test]
+    fn compare_paths_ignoring_case() {
+        let a = Path::new("/ws/Src/Foo.c");
+        let b = Path::new("/ws/src/foo.c");
+        assert!(!same_path(a, b, false));
+        assert!(same_path(a, b, true));
+        assert!(same_path(a, a, false));
+        assert!(!same_path(a, Path::new("/ws/src"), true));
+        assert!(!same_path(a, Path::new("/ws/src/foo.c/x"), true));
+
+        assert!(!path_starts_with(a, Path::new("/WS/src"), false));
+        assert!(path_starts_with(a, Path::new("/WS/src"), true));
+        assert!(!path_starts_with(a, Path::new("/ws/srcs"), true));
+    }
+
+    #[test]
+    fn parse_pseudo_tags() {
+        let path = std::env::temp_dir().join(format!("tags-lsp-test-meta-{}", std::process::id()));
+        let content = [
+            "!_TAG_FILE_FORMAT\t2\t/extended format/",
+            "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/",
+            "!_TAG_PROGRAM_NAME\tUniversal Ctags\t//",
+            "!_TAG_PROGRAM_VERSION\t6.0.0\t/p6.0.20230101.0/",
+            "!_TAG_KIND_DESCRIPTION!C\tf,function\t/function definitions/",
+            "add\tmain.c\t1;\"\tf",
+            "main\tmain.c\t6;\"\tf",
+        ];
+        std::fs::write(&path, content.join("\n")).unwrap();
+        let tags = parse(&path, 1, encoding_rs::UTF_8).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(tags.meta.program_name.as_deref(), Some("Universal Ctags"));
+        assert_eq!(tags.meta.program_version.as_deref(), Some("6.0.0"));
+        assert_eq!(tags.meta.file_format, Some(2));
+        assert_eq!(tags.meta.sorted, SortOrder::Sorted);
+        assert!(tags.binary_search);
+        assert_eq!(tags.entries.len(), 2);
+        assert!(tags.malformed.is_empty());
+
+        let sorted = |value: &str| {
+            let mut meta = TagsMeta::default();
+            meta.parse_line(&format!("!_TAG_FILE_SORTED\t{}\t//", value));
+            return meta.sorted;
+        };
+        assert_eq!(sorted("0"), SortOrder::Unsorted);
+        assert_eq!(sorted("2"), SortOrder::Foldcase);
+        assert_eq!(sorted("x"), SortOrder::Unsorted);
+        assert_eq!(TagsMeta::default().sorted, SortOrder::Unsorted);
+    }
+
+    #[test]
+    fn binary_search_in_sorted_tags() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace/tags");
+        let lines = |tags: &TagsFile, name: &str| -> Vec<_> {
+            return tags.lookup(name).map(|v| v.line).collect();
+        };
+
+        // Small files and a threshold of 0 keep hash index.
+        assert!(
+            !parse(&fixture, 0, encoding_rs::UTF_8)
+                .unwrap()
+                .binary_search
+        );
+        assert!(
+            !parse(&fixture, 2, encoding_rs::UTF_8)
+                .unwrap()
+                .binary_search
+        );
+
+        let tags = parse(&fixture, 1, encoding_rs::UTF_8).unwrap();
+        assert!(tags.binary_search);
+        assert!(tags.index.is_empty());
+        assert_eq!(lines(&tags, "add"), [Some(1)]);
+        assert_eq!(lines(&tags, "main"), [Some(6)]);
+        for name in ["", "ad", "adds", "zzz"] {
+            assert_eq!(lines(&tags, name), [], "{}", name);
+        }
+
+        // Entries of the same name are next to each other.
+        let entries = [
+            "a\ta.c\t1",
+            "b\ta.c\t2",
+            "b\tb.c\t3",
+            "b\tc.c\t4",
+            "c\ta.c\t5",
+        ]
+        .iter()
+        .filter_map(|v| parse_line(v))
+        .collect();
+        let mut tags = TagsFile::new(PathBuf::from("/ws/tags"), entries);
+        tags.meta.sorted = SortOrder::Sorted;
+        tags.hash_index_limit = 1;
+        tags.rebuild_index();
+        assert!(tags.binary_search);
+        let files: Vec<_> = tags.lookup("b").map(|v| v.file.as_str()).collect();
+        assert_eq!(files, ["a.c", "b.c", "c.c"]);
+        assert_eq!(tags.lookup("c").count(), 1);
+    }
+
+    #[test]
+    fn discover_tags_by_filename() {
+        let dir =
+            std::env::temp_dir().join(format!("tags-lsp-test-filename-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".tags"), "").unwrap();
+        std::fs::write(dir.join("project.tags.gz"), "").unwrap();
+        let names = |list: &[&str]| -> Vec<String> {
+            return list.iter().map(|v| v.to_string()).collect();
+        };
+
+        assert_eq!(discover(&dir, &names(&["tags"])), None);
+        assert_eq!(discover(&dir, &names(&[".tags"])), Some(dir.join(".tags")));
+        assert_eq!(
+            discover(&dir, &names(&["tags", "project.tags", ".tags"])),
+            Some(dir.join("project.tags.gz"))
+        );
+
+        // Plain file preferred over compressed one.
+        std::fs::write(dir.join("project.tags"), "").unwrap();
+        assert_eq!(
+            discover(&dir, &names(&["project.tags"])),
+            Some(dir.join("project.tags"))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
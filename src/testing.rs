@@ -0,0 +1,570 @@
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tower_lsp::lsp_types::*;
+
+/// Stream of one side of the in-memory connection.
+type Stream = tokio::io::DuplexStream;
+
+/// LSP client talking JSON-RPC to a server session in the same process.
+pub struct Client {
+    /// Writes to server.
+    writer: tokio::io::WriteHalf<Stream>,
+
+    /// Reads from server.
+    reader: BufReader<tokio::io::ReadHalf<Stream>>,
+
+    /// Id of the next request.
+    next_id: i64,
+
+    /// Tags loaded by the session.
+    tags: crate::tags::SharedTags,
+
+    /// Watchers of loaded tags.
+    pub watchers: crate::watch::Watchers,
+
+    /// Notifications received from server, in order.
+    pub notifications: Vec<serde_json::Value>,
+
+    /// The server session.
+    session: tokio::task::JoinHandle<()>,
+}
+
+impl Client {
+    /// Start a server session.
+    ///
+    /// # Arguments
+    ///
+    /// + `args`: Command line arguments of server.
+    pub fn start(args: &[&str]) -> Client {
+        return Client::start_shared(args, Default::default(), Default::default());
+    }
+
+    /// Start another server session sharing tags and watchers of this one,
+    /// like a second client connected to the same socket.
+    ///
+    /// # Arguments
+    ///
+    /// + `args`: Command line arguments of server.
+    pub fn connect(&self, args: &[&str]) -> Client {
+        return Client::start_shared(args, self.tags.clone(), self.watchers.clone());
+    }
+
+    /// Start a server session with shared tags and watchers.
+    ///
+    /// # Arguments
+    ///
+    /// + `args`: Command line arguments of server.
+    /// + `tags`: Tags shared by sessions.
+    /// + `watchers`: Watchers shared by sessions.
+    fn start_shared(
+        args: &[&str],
+        tags: crate::tags::SharedTags,
+        watchers: crate::watch::Watchers,
+    ) -> Client {
+        use clap::Parser;
+
+        let argv = std::iter::once("tags-lsp").chain(args.iter().copied());
+        let config = crate::TagsLspConfig::try_parse_from(argv).expect("invalid arguments");
+        let rt = crate::Runtime {
+            config: config.clone(),
+            cli_config: config,
+            tags: tags.clone(),
+            watchers: watchers.clone(),
+            hide_anonymous: true,
+            ..Default::default()
+        };
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (input, output) = tokio::io::split(server);
+        let session = tokio::spawn(crate::serve_session(rt, input, output));
+
+        let (reader, writer) = tokio::io::split(client);
+        return Client {
+            writer,
+            reader: BufReader::new(reader),
+            next_id: 1,
+            tags,
+            watchers,
+            notifications: Vec::new(),
+            session,
+        };
+    }
+
+    /// Write one JSON-RPC message.
+    async fn send(&mut self, msg: serde_json::Value) {
+        let body = msg.to_string();
+        let header = format!("Content-Length: {}\r\n\r\n", body.len());
+        self.writer.write_all(header.as_bytes()).await.unwrap();
+        self.writer.write_all(body.as_bytes()).await.unwrap();
+        self.writer.flush().await.unwrap();
+    }
+
+    /// Read one JSON-RPC message.
+    async fn recv(&mut self) -> serde_json::Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            let n = self.reader.read_line(&mut line).await.unwrap();
+            assert!(n != 0, "server closed connection");
+
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(v) = line.strip_prefix("Content-Length:") {
+                length = v.trim().parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).await.unwrap();
+        return serde_json::from_slice(&body).unwrap();
+    }
+
+    /// Send a request and wait for its response.
+    ///
+    /// Notifications from server are recorded, and requests from server are
+    /// answered with `null`.
+    ///
+    /// # Arguments
+    ///
+    /// + `params`: Parameters of request.
+    pub async fn request<R: request::Request>(
+        &mut self,
+        params: R::Params,
+    ) -> Result<R::Result, serde_json::Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut msg = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": R::METHOD });
+        with_params(&mut msg, params);
+        self.send(msg).await;
+
+        loop {
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(10), self.recv())
+                .await
+                .expect("no response from server");
+
+            if msg.get("method").is_some() {
+                match msg.get("id") {
+                    Some(v) => {
+                        let reply =
+                            serde_json::json!({ "jsonrpc": "2.0", "id": v, "result": null });
+                        self.send(reply).await;
+                    }
+                    None => self.notifications.push(msg),
+                }
+                continue;
+            }
+            if msg["id"] != id {
+                continue;
+            }
+
+            if let Some(err) = msg.get("error") {
+                return Err(err.clone());
+            }
+            return Ok(serde_json::from_value(msg["result"].clone()).unwrap());
+        }
+    }
+
+    /// Wait for notifications from server.
+    ///
+    /// Notifications sent by a handler may arrive after its response, so they
+    /// are waited for instead of checked right after the request.
+    ///
+    /// # Arguments
+    ///
+    /// + `f`: Whether the notification is one to wait for.
+    /// + `count`: Number of matching notifications to wait for.
+    ///
+    /// # Returns
+    ///
+    /// All matching notifications received so far.
+    pub async fn wait_notifications(
+        &mut self,
+        f: impl Fn(&serde_json::Value) -> bool,
+        count: usize,
+    ) -> Vec<serde_json::Value> {
+        while self.notifications.iter().filter(|v| f(v)).count() < count {
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(10), self.recv())
+                .await
+                .expect("no notification from server");
+            if msg.get("method").is_some() && msg.get("id").is_none() {
+                self.notifications.push(msg);
+            }
+        }
+
+        return self
+            .notifications
+            .iter()
+            .filter(|v| f(v))
+            .cloned()
+            .collect();
+    }
+
+    /// Send a notification.
+    ///
+    /// # Arguments
+    ///
+    /// + `params`: Parameters of notification.
+    pub async fn notify<N: notification::Notification>(&mut self, params: N::Params) {
+        let mut msg = serde_json::json!({ "jsonrpc": "2.0", "method": N::METHOD });
+        with_params(&mut msg, params);
+        self.send(msg).await;
+    }
+
+    /// Run `initialize` and `initialized` on workspace folder, then wait for
+    /// tags to be loaded.
+    ///
+    /// # Arguments
+    ///
+    /// + `root`: Workspace folder Url.
+    pub async fn initialize(&mut self, root: &Url) -> InitializeResult {
+        return self.initialize_folders(std::slice::from_ref(root)).await;
+    }
+
+    /// Run `initialize` and `initialized` on workspace folders, then wait for
+    /// tags to be loaded.
+    ///
+    /// # Arguments
+    ///
+    /// + `folders`: Workspace folder Urls.
+    pub async fn initialize_folders(&mut self, folders: &[Url]) -> InitializeResult {
+        let folders = folders
+            .iter()
+            .map(|v| WorkspaceFolder {
+                uri: v.clone(),
+                name: String::from("workspace"),
+            })
+            .collect();
+        let params = InitializeParams {
+            workspace_folders: Some(folders),
+            ..Default::default()
+        };
+        let ret = self
+            .request::<request::Initialize>(params)
+            .await
+            .expect("initialize failed");
+        self.notify::<notification::Initialized>(InitializedParams {})
+            .await;
+
+        // Tags are loaded in background after `initialized`.
+        for _ in 0..100 {
+            if !self.tags.load().is_empty() {
+                return ret;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("tags are not loaded in time");
+    }
+
+    /// Open document with content.
+    ///
+    /// # Arguments
+    ///
+    /// + `uri`: Document Url.
+    /// + `text`: Content of document.
+    pub async fn open(&mut self, uri: &Url, text: &str) {
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: String::from("c"),
+                version: 1,
+                text: String::from(text),
+            },
+        };
+        self.notify::<notification::DidOpenTextDocument>(params)
+            .await;
+    }
+
+    /// Run `shutdown` and `exit`, then wait for the session to end.
+    pub async fn shutdown(mut self) {
+        self.request::<request::Shutdown>(()).await.unwrap();
+        self.notify::<notification::Exit>(()).await;
+        tokio::time::timeout(std::time::Duration::from_secs(10), self.session)
+            .await
+            .expect("session does not end after exit")
+            .unwrap();
+    }
+}
+
+/// Add `params` to message, unless it is `()`.
+///
+/// Server rejects `"params": null` of methods without parameters.
+///
+/// # Arguments
+///
+/// + `msg`: JSON-RPC message.
+/// + `params`: Parameters of message.
+fn with_params(msg: &mut serde_json::Value, params: impl serde::Serialize) {
+    let params = serde_json::to_value(params).unwrap();
+    if !params.is_null() {
+        msg["params"] = params;
+    }
+}
+
+/// Url of fixture workspace.
+pub fn fixture() -> Url {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace");
+    return Url::from_directory_path(dir).unwrap();
+}
+
+/// Position in document.
+///
+/// # Arguments
+///
+/// + `uri`: Document Url.
+/// + `line`: Line number, starting from 0.
+/// + `character`: Column, starting from 0.
+pub fn position(uri: &Url, line: u32, character: u32) -> TextDocumentPositionParams {
+    return TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        position: Position::new(line, character),
+    };
+}
+
+/// Create backend without connection, for calling methods directly.
+///
+/// Client requests sent by the backend are never answered.
+pub fn backend() -> tower_lsp::LspService<crate::TagsLspBackend> {
+    let (service, _) = tower_lsp::LspService::new(|client| crate::TagsLspBackend {
+        client,
+        rt: Default::default(),
+        initialized: std::sync::atomic::AtomicBool::new(true),
+        trace_verbose: Default::default(),
+    });
+    return service;
+}
+
+/// Start server on fixture workspace.
+///
+/// # Returns
+///
+/// The client and Url of `main.c`.
+pub async fn start() -> (Client, Url) {
+    let root = fixture();
+    let mut client = Client::start(&["--no-auto-generate"]);
+    client.initialize(&root).await;
+    return (client, root.join("main.c").unwrap());
+}
+
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn goto_definition() {
+        let (mut client, uri) = start().await;
+
+        // `add` of `return add(1, 2);`.
+        let params = GotoDefinitionParams {
+            text_document_position_params: position(&uri, 7, 12),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::GotoDefinition>(params)
+            .await
+            .unwrap();
+
+        let expect = Location::new(uri, Range::new(Position::new(0, 4), Position::new(0, 7)));
+        assert_eq!(ret, Some(GotoDefinitionResponse::Scalar(expect)));
+
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn hover() {
+        let (mut client, uri) = start().await;
+
+        let params = HoverParams {
+            text_document_position_params: position(&uri, 7, 12),
+            work_done_progress_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::HoverRequest>(params)
+            .await
+            .unwrap();
+        let text = serde_json::to_string(&ret.expect("no hover").contents).unwrap();
+        assert!(text.contains("int add(int a, int b)"), "{}", text);
+
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn references() {
+        let (mut client, uri) = start().await;
+
+        let params = ReferenceParams {
+            text_document_position: position(&uri, 7, 12),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::References>(params)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut lines: Vec<_> = ret.iter().map(|v| v.range.start.line).collect();
+        lines.sort();
+        assert_eq!(lines, [0, 7]);
+        assert!(ret.iter().all(|v| v.uri == uri));
+
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn document_symbol() {
+        let (mut client, uri) = start().await;
+
+        let params = DocumentSymbolParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::DocumentSymbolRequest>(params)
+            .await
+            .unwrap();
+
+        let names: Vec<_> = match ret {
+            Some(DocumentSymbolResponse::Flat(list)) => list
+                .into_iter()
+                .map(|v| (v.name, v.location.range.start.line))
+                .collect(),
+            Some(DocumentSymbolResponse::Nested(list)) => list
+                .into_iter()
+                .map(|v| (v.name, v.range.start.line))
+                .collect(),
+            None => Vec::new(),
+        };
+        assert_eq!(names, [(String::from("add"), 0), (String::from("main"), 5)]);
+
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn workspace_symbol() {
+        let (mut client, uri) = start().await;
+
+        let params = WorkspaceSymbolParams {
+            query: String::from("mai"),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ret = client
+            .request::<request::WorkspaceSymbolRequest>(params)
+            .await
+            .unwrap();
+
+        let list = match ret {
+            Some(WorkspaceSymbolResponse::Flat(v)) => v,
+            v => panic!("unexpected response: {:?}", v),
+        };
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "main");
+        assert_eq!(list[0].kind, SymbolKind::FUNCTION);
+        assert_eq!(list[0].location.uri, uri);
+        assert_eq!(list[0].location.range.start.line, 5);
+
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn reject_request_before_initialize() {
+        let mut client = Client::start(&["--no-auto-generate"]);
+
+        let params = GotoDefinitionParams {
+            text_document_position_params: position(&fixture().join("main.c").unwrap(), 7, 12),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let e = client
+            .request::<request::GotoDefinition>(params)
+            .await
+            .unwrap_err();
+        assert_eq!(e["code"], -32002);
+    }
+
+    #[tokio::test]
+    async fn stop_watchers_after_shutdown() {
+        let root = fixture();
+        let (client, _) = start().await;
+        let watchers = client.watchers.clone();
+
+        // Watcher starts after tags are loaded.
+        for _ in 0..100 {
+            if watchers.contains(&Some(root.clone())) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(watchers.contains(&Some(root.clone())));
+
+        client.shutdown().await;
+        assert!(!watchers.contains(&Some(root)));
+    }
+
+    #[tokio::test]
+    async fn drop_notification_before_initialize() {
+        let root = fixture();
+        let uri = root.join("main.c").unwrap();
+        let mut client = Client::start(&["--no-auto-generate"]);
+
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri, String::from("c"), 1, String::new()),
+        };
+        client
+            .notify::<notification::DidOpenTextDocument>(params)
+            .await;
+
+        // Still served, and nothing is sent back for the notification.
+        client.initialize(&root).await;
+        assert!(client
+            .notifications
+            .iter()
+            .all(|v| v["method"] != "window/showMessage"));
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn two_clients_share_session_data() {
+        let root = fixture();
+        let uri = root.join("main.c").unwrap();
+        let (mut first, _) = start().await;
+        let mut second = first.connect(&["--no-auto-generate"]);
+        second.initialize(&root).await;
+
+        let expect = Location::new(
+            uri.clone(),
+            Range::new(Position::new(0, 4), Position::new(0, 7)),
+        );
+        for client in [&mut first, &mut second] {
+            let params = GotoDefinitionParams {
+                text_document_position_params: position(&uri, 7, 12),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            let ret = client
+                .request::<request::GotoDefinition>(params)
+                .await
+                .unwrap();
+            assert_eq!(ret, Some(GotoDefinitionResponse::Scalar(expect.clone())));
+        }
+
+        let watchers = first.watchers.clone();
+        for _ in 0..100 {
+            if watchers.contains(&Some(root.clone())) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        // Watchers are kept until the last client leaves.
+        first.shutdown().await;
+        assert!(watchers.contains(&Some(root.clone())));
+        second.shutdown().await;
+        assert!(!watchers.contains(&Some(root)));
+    }
+}
@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+/// List files in directory recursively.
+///
+/// Hidden files are always skipped. Unless `no_ignore` is set, files
+/// excluded by `.gitignore`, `.ignore` and global git excludes are skipped.
+///
+/// # Arguments
+///
+/// + `dir`: The directory to walk.
+/// + `no_ignore`: Do not respect ignore files.
+/// + `exclude`: Skip paths matching it, relative to `dir`.
+pub fn walk(dir: &Path, no_ignore: bool, exclude: globset::GlobSet) -> Vec<PathBuf> {
+    let root = dir.to_path_buf();
+    let walker = ignore::WalkBuilder::new(dir)
+        .standard_filters(!no_ignore)
+        .hidden(true)
+        .require_git(false)
+        .filter_entry(move |v| match v.path().strip_prefix(&root) {
+            Ok(path) => path.as_os_str().is_empty() || !exclude.is_match(path),
+            Err(_) => true,
+        })
+        .build();
+
+    return walker
+        .filter_map(|v| v.ok())
+        .filter(|v| v.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|v| v.into_path())
+        .collect();
+}
+
+/// Compile `--exclude` patterns.
+///
+/// # Arguments
+///
+/// + `patterns`: Glob patterns.
+pub fn build_exclude(patterns: &[String]) -> Result<globset::GlobSet, globset::Error> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(globset::Glob::new(pattern)?);
+    }
+    return builder.build();
+}
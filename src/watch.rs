@@ -0,0 +1,333 @@
+use notify::Watcher;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower_lsp::lsp_types::*;
+
+/// Watchers of loaded tags files, one for each workspace folder.
+///
+/// Tags files given by `--tags` belong to no folder and are keyed by `None`.
+/// Watchers are shared by sessions like loaded tags, so a folder opened by
+/// several clients is watched once. They are stopped when the last session
+/// leaves.
+#[derive(Debug, Default, Clone)]
+pub struct Watchers {
+    inner: Arc<std::sync::Mutex<HashMap<Option<Url>, tokio::task::AbortHandle>>>,
+
+    /// Number of sessions using the watchers.
+    sessions: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl Watchers {
+    /// Whether folder is watched.
+    ///
+    /// # Arguments
+    ///
+    /// + `folder`: Workspace folder.
+    pub fn contains(&self, folder: &Option<Url>) -> bool {
+        return self.inner.lock().unwrap().contains_key(folder);
+    }
+
+    /// Keep watcher of folder, the previous one is stopped.
+    ///
+    /// # Arguments
+    ///
+    /// + `folder`: Workspace folder.
+    /// + `task`: Handle of the watcher task.
+    pub fn insert(&self, folder: Option<Url>, task: tokio::task::AbortHandle) {
+        if let Some(v) = self.inner.lock().unwrap().insert(folder, task) {
+            v.abort();
+        }
+    }
+
+    /// Stop watcher of folder.
+    ///
+    /// # Arguments
+    ///
+    /// + `folder`: Workspace folder.
+    pub fn remove(&self, folder: &Option<Url>) {
+        if let Some(v) = self.inner.lock().unwrap().remove(folder) {
+            v.abort();
+        }
+    }
+
+    /// Register a session that uses the watchers.
+    pub fn join(&self) {
+        self.sessions
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Unregister a session, all watchers are stopped if it is the last one.
+    ///
+    /// # Returns
+    ///
+    /// Number of watchers stopped.
+    pub fn leave(&self) -> usize {
+        let prev = self
+            .sessions
+            .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        if prev != 1 {
+            return 0;
+        }
+
+        return self.clear();
+    }
+
+    /// Stop all watchers.
+    ///
+    /// # Returns
+    ///
+    /// Number of watchers stopped.
+    pub fn clear(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let count = inner.len();
+        for (_, v) in inner.drain() {
+            v.abort();
+        }
+
+        return count;
+    }
+}
+
+/// Watch loaded tags files of workspace folders, one watcher for each.
+///
+/// Tags files given by `--tags` are watched together instead. Folders that
+/// are already watched, maybe by another session, are skipped.
+///
+/// # Arguments
+///
+/// + `client`: LSP client.
+/// + `shared`: Runtime to update, the same one as `rt`.
+/// + `rt`: Locked Runtime.
+/// + `folders`: Workspace folders to watch.
+pub fn watch_folders(
+    client: &tower_lsp::Client,
+    shared: &Arc<tokio::sync::Mutex<crate::Runtime>>,
+    rt: &mut crate::Runtime,
+    folders: &[WorkspaceFolder],
+) {
+    let loaded: Vec<_> = rt.tags.load().iter().map(|v| v.path.clone()).collect();
+
+    let mut group_list = Vec::new();
+    if !rt.config.tags.is_empty() {
+        group_list.push((None, loaded));
+    } else {
+        for folder in folders {
+            let dir = match folder.uri.to_file_path() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let config = rt.folder_config(&folder.uri);
+            let paths: Vec<_> = crate::tags::discover_upward(&dir, &config.tags_filename)
+                .into_iter()
+                .filter(|v| loaded.contains(v))
+                .collect();
+            group_list.push((Some(folder.uri.clone()), paths));
+        }
+    }
+
+    let debounce = std::time::Duration::from_millis(rt.config.watch_debounce_ms);
+    for (folder, paths) in group_list {
+        if paths.is_empty() || rt.watchers.contains(&folder) {
+            continue;
+        }
+        if let Some(v) = spawn_tags_watcher(client.clone(), shared.clone(), paths, debounce) {
+            rt.watchers.insert(folder, v);
+        }
+    }
+}
+
+/// Watch tags files and reload them when changed on disk.
+///
+/// The parent directory of each tags file is watched instead of the file
+/// itself, because generators usually replace the file as a whole.
+///
+/// # Arguments
+///
+/// + `client`: LSP client.
+/// + `rt`: Runtime to update.
+/// + `paths`: Tags files to watch.
+/// + `debounce`: How long to wait for more events before reloading.
+///
+/// # Returns
+///
+/// Handle of the watcher task, aborting it stops watching.
+fn spawn_tags_watcher(
+    client: tower_lsp::Client,
+    rt: Arc<tokio::sync::Mutex<crate::Runtime>>,
+    paths: Vec<PathBuf>,
+    debounce: std::time::Duration,
+) -> Option<tokio::task::AbortHandle> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<notify::Event>();
+
+    let mut watcher =
+        match notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                let _ = tx.send(event);
+            }
+        }) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("Cannot create file watcher: {}", e);
+                return None;
+            }
+        };
+
+    let dirs: HashSet<PathBuf> = paths
+        .iter()
+        .filter_map(|v| v.parent().map(|v| v.to_path_buf()))
+        .collect();
+    for dir in &dirs {
+        if let Err(e) = watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+            tracing::warn!("Cannot watch {}: {}", dir.display(), e);
+        }
+    }
+
+    let task = tokio::task::spawn(async move {
+        // Keep watcher alive as long as the task.
+        let _watcher = watcher;
+
+        while let Some(changed) = next_changes(&mut rx, &paths, debounce).await {
+            for path in changed {
+                reload_tags(&client, &rt, path).await;
+            }
+        }
+    });
+
+    return Some(task.abort_handle());
+}
+
+/// Wait for the next burst of events and collect tags files it touched.
+///
+/// A burst ends when no event comes within `debounce`, so it causes one
+/// reload however many events it has.
+///
+/// # Arguments
+///
+/// + `rx`: Receiver of file system events.
+/// + `paths`: Watched tags files.
+/// + `debounce`: How long to wait for more events.
+///
+/// # Returns
+///
+/// `None` if the watcher is gone.
+async fn next_changes(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<notify::Event>,
+    paths: &[PathBuf],
+    debounce: std::time::Duration,
+) -> Option<HashSet<PathBuf>> {
+    let event = rx.recv().await?;
+    let mut changed = HashSet::new();
+    collect_changed(paths, &event, &mut changed);
+
+    loop {
+        match tokio::time::timeout(debounce, rx.recv()).await {
+            Ok(Some(event)) => collect_changed(paths, &event, &mut changed),
+            Ok(None) => return None,
+            Err(_) => return Some(changed),
+        }
+    }
+}
+
+/// Record watched tags files touched by event.
+fn collect_changed(paths: &[PathBuf], event: &notify::Event, changed: &mut HashSet<PathBuf>) {
+    if !event.kind.is_modify() && !event.kind.is_create() {
+        return;
+    }
+
+    for path in &event.paths {
+        if paths.contains(path) {
+            changed.insert(path.clone());
+        }
+    }
+}
+
+/// Parse tags file again and replace the old one in Runtime.
+///
+/// Nothing changes if the old one is not loaded anymore.
+async fn reload_tags(
+    client: &tower_lsp::Client,
+    rt: &tokio::sync::Mutex<crate::Runtime>,
+    path: PathBuf,
+) {
+    tracing::debug!("Reloading {}", path.display());
+
+    let (limit, encoding) = {
+        let rt = rt.lock().await;
+        (
+            rt.config.sorted_index_threshold(),
+            rt.config.file_encoding(),
+        )
+    };
+    let parse_path = path.clone();
+    let tags_file =
+        match tokio::task::spawn_blocking(move || crate::tags::parse(&parse_path, limit, encoding))
+            .await
+        {
+            Ok(Ok(v)) => v,
+            Ok(Err(e)) => {
+                tracing::warn!("Cannot reload {}: {}", path.display(), e);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot reload {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+    tracing::info!(
+        "Reloaded {} tags from {}",
+        tags_file.entries.len(),
+        path.display()
+    );
+
+    let rt = rt.lock().await;
+    if rt.regenerating {
+        tracing::debug!("Skip reloading {}: regenerating", path.display());
+        return;
+    }
+    crate::method::publish_tags_diagnostics(client, &rt.config, &tags_file).await;
+    let tags_file = Arc::new(tags_file);
+    // Tags file of a removed folder is not loaded again.
+    rt.tags
+        .update(|tags| match tags.iter_mut().find(|v| v.path == path) {
+            Some(v) => *v = tags_file,
+            None => tracing::debug!("Skip reloading {}: no longer loaded", path.display()),
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn one_reload_for_burst_of_events() {
+        let paths = vec![PathBuf::from("/ws/tags"), PathBuf::from("/ws/lib/tags")];
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let event = |kind, path: &str| notify::Event::new(kind).add_path(PathBuf::from(path));
+        let modify = notify::EventKind::Modify(notify::event::ModifyKind::Any);
+
+        // ctags writes tags file in many small chunks.
+        let sender = tokio::spawn(async move {
+            for _ in 0..20 {
+                tx.send(event(modify, "/ws/tags")).unwrap();
+                tx.send(event(modify, "/ws/main.c")).unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+            let access = notify::EventKind::Access(notify::event::AccessKind::Any);
+            tx.send(event(access, "/ws/lib/tags")).unwrap();
+            tx
+        });
+
+        let debounce = std::time::Duration::from_millis(200);
+        let timeout = std::time::Duration::from_secs(5);
+        let changed = tokio::time::timeout(timeout, next_changes(&mut rx, &paths, debounce))
+            .await
+            .unwrap();
+        assert_eq!(changed, Some(HashSet::from([PathBuf::from("/ws/tags")])));
+
+        // Nothing more after the burst.
+        drop(sender.await.unwrap());
+        assert_eq!(next_changes(&mut rx, &paths, debounce).await, None);
+    }
+}
@@ -0,0 +1,7 @@
+#define MAX 10
+int add(int a, int b)
+{
+    return a + b;
+}
+char *path = "/$";
+int counter;